@@ -0,0 +1,70 @@
+//! Benchmarks of computing the median of the rate histories of the sources.
+//! Run with `cargo bench --bench median`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use num_rational::BigRational;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::VecDeque;
+
+// The service is a binary crate, so the helpers are included directly.
+#[allow(dead_code)]
+#[path = "../src/helpers.rs"]
+mod helpers;
+
+/// Builds a history of the given length with rates similar to those reported
+/// by the sources.
+fn history(len: usize) -> VecDeque<BigRational> {
+    let mut rng = StdRng::seed_from_u64(42);
+    (0..len).map(|_| BigRational::from_float(rng.gen_range(0.005..0.015)).unwrap()).collect()
+}
+
+fn bench_median(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compute_median");
+    for len in [10, 600, 10_000] {
+        let rates = history(len);
+        group.bench_with_input(BenchmarkId::from_parameter(len), &rates, |b, rates| {
+            b.iter(|| helpers::compute_median(black_box(rates), helpers::MedianEvenPolicy::Average))
+        });
+    }
+    group.finish();
+}
+
+/// Computing the median with a scratch buffer, which is reused across
+/// iterations, like the main loop could across sources.
+fn bench_median_of(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compute_median_of");
+    for len in [10, 600, 10_000] {
+        let rates = history(len);
+        let mut scratch = Vec::with_capacity(len);
+        group.bench_with_input(BenchmarkId::from_parameter(len), &rates, |b, rates| {
+            b.iter(|| {
+                scratch.clear();
+                scratch.extend(black_box(rates).iter());
+                helpers::compute_median_of(&mut scratch, helpers::MedianEvenPolicy::Average)
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Filtering the history and computing its median, as is done in every
+/// update cycle for every source.
+fn bench_filtered_median(c: &mut Criterion) {
+    let mut group = c.benchmark_group("filtered_median");
+    for filter in ["none", "iqr:1.5"] {
+        let filter: helpers::OutlierFilter = filter.parse().unwrap();
+        let rates = history(600);
+        group.bench_with_input(BenchmarkId::from_parameter(&filter), &rates, |b, rates| {
+            b.iter(|| {
+                helpers::compute_median(
+                    &filter.apply(black_box(rates)),
+                    helpers::MedianEvenPolicy::Average,
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_median, bench_median_of, bench_filtered_median);
+criterion_main!(benches);
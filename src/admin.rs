@@ -0,0 +1,341 @@
+use crate::{
+    prometheus::Stats,
+    sources::{lock_history, RateHistory},
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::{mpsc, oneshot};
+use warp::{http::StatusCode, Filter, Reply};
+
+/// Used by the main loop to report the outcome of an update cycle, which was
+/// triggered through the admin endpoint. The outcome is either the
+/// submission id of the update, or the reason no update was performed.
+pub type UpdateNowReply = oneshot::Sender<Result<String, String>>;
+
+/// Reports the outcome of an update cycle, if the cycle was triggered through
+/// the admin endpoint.
+pub fn report_outcome(trigger: &mut Option<UpdateNowReply>, outcome: Result<String, String>) {
+    if let Some(reply) = trigger.take() {
+        // The admin request might have been cancelled, in which case there is no
+        // one to report to.
+        let _ = reply.send(outcome);
+    }
+}
+
+/// An update exceeding the warning threshold, which is held until it is
+/// confirmed or rejected through the admin endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PendingUpdate {
+    pub id:      u64,
+    /// The rate of the update. (In microCCD/EUR)
+    pub rate:    String,
+    /// The change from the rate on chain, rounded to whole percentages, with
+    /// its sign.
+    pub change:  String,
+    /// The update is skipped, if it is not confirmed before this time.
+    pub expires: chrono::DateTime<chrono::Utc>,
+}
+
+/// The update awaiting confirmation, if any, along with the channel for
+/// reporting whether it was confirmed (true) or rejected (false) to the main
+/// loop.
+pub type PendingConfirmation = Arc<Mutex<Option<(PendingUpdate, oneshot::Sender<bool>)>>>;
+
+/// The histories of the sources, by their labels. The main loop adds and
+/// removes sources, when the sources file is reloaded.
+pub type SourceHistories = Arc<Mutex<HashMap<String, Arc<Mutex<RateHistory>>>>>;
+
+/// Whether submitting updates has been paused through the admin endpoint.
+/// Checked by the main loop, before an update is submitted.
+pub type Paused = Arc<AtomicBool>;
+
+/// Confirms or rejects the pending update with the given id.
+fn decide_pending(pending: &PendingConfirmation, id: u64, confirmed: bool) -> (String, StatusCode) {
+    let mut pending = pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match pending.take() {
+        Some((update, decision)) if update.id == id => {
+            if decision.send(confirmed).is_err() {
+                return (format!("Update {} has expired.\n", id), StatusCode::CONFLICT);
+            }
+            if confirmed {
+                log::warn!("Update {} was confirmed through the admin endpoint", id);
+                (format!("Confirmed update {}.\n", id), StatusCode::OK)
+            } else {
+                log::warn!("Update {} was rejected through the admin endpoint", id);
+                (format!("Rejected update {}.\n", id), StatusCode::OK)
+            }
+        }
+        other => {
+            *pending = other;
+            (format!("No update {} is awaiting confirmation.\n", id), StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+/// Checks that the authorization header contains the admin token. The token is
+/// compared in constant time, so the time taken does not reveal how much of a
+/// guess is correct. An empty token authorizes nothing.
+fn is_authorized(authorization: Option<&str>, token: &str) -> bool {
+    if token.is_empty() {
+        return false;
+    }
+    match authorization.and_then(|header| header.strip_prefix("Bearer ")) {
+        Some(given) => constant_time_eq(given.as_bytes(), token.as_bytes()),
+        None => false,
+    }
+}
+
+/// Compares the two byte strings, taking time that only depends on their
+/// lengths.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Enables or disables the source with the given label.
+fn set_source_enabled(
+    sources: &SourceHistories,
+    stats: &Stats,
+    label: &str,
+    enabled: bool,
+) -> (String, StatusCode) {
+    let history =
+        sources.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).get(label).cloned();
+    match history {
+        Some(history) => {
+            lock_history(&history, label).disabled = !enabled;
+            stats.set_source_disabled(label, !enabled);
+            if enabled {
+                log::warn!("{}: Source was enabled through the admin endpoint", label);
+                (format!("Enabled source {}.\n", label), StatusCode::OK)
+            } else {
+                log::warn!("{}: Source was disabled through the admin endpoint", label);
+                (format!("Disabled source {}.\n", label), StatusCode::OK)
+            }
+        }
+        None => (format!("Unknown source {}.\n", label), StatusCode::NOT_FOUND),
+    }
+}
+
+/// Pauses or resumes submitting updates.
+fn set_paused(paused: &Paused, stats: &Stats, pause: bool) -> (String, StatusCode) {
+    paused.store(pause, Ordering::SeqCst);
+    stats.set_paused(pause);
+    if pause {
+        log::warn!("Submitting updates was paused through the admin endpoint");
+        ("Paused submitting updates.\n".to_string(), StatusCode::OK)
+    } else {
+        log::warn!("Submitting updates was resumed through the admin endpoint");
+        ("Resumed submitting updates.\n".to_string(), StatusCode::OK)
+    }
+}
+
+/// Triggers an update cycle in the main loop, and waits for its outcome.
+async fn update_now(update_now: mpsc::Sender<UpdateNowReply>) -> (String, StatusCode) {
+    log::warn!("Update was triggered through the admin endpoint");
+    let (reply, outcome) = oneshot::channel();
+    if update_now.send(reply).await.is_err() {
+        return ("Main loop is not running.\n".to_string(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    match outcome.await {
+        Ok(Ok(message)) => (message + "\n", StatusCode::OK),
+        Ok(Err(message)) => (message + "\n", StatusCode::CONFLICT),
+        Err(_) => (
+            "Update cycle ended without an outcome.\n".to_string(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    }
+}
+
+/**
+ * Serves the admin endpoints, which require the given token as a bearer
+ * token:
+ * - `PUT /sources/<label>/disable`: Stops polling the source, and excludes
+ *   it from updates, while keeping its history.
+ * - `PUT /sources/<label>/enable`: Resumes a disabled source.
+ * - `PUT /update-now`: Triggers an update cycle, and responds with its
+ *   outcome.
+ * - `GET /pending`: Shows the update awaiting confirmation.
+ * - `POST /confirm/<id>` and `POST /reject/<id>`: Confirms or rejects the
+ *   update awaiting confirmation.
+ * - `POST /pause` and `POST /unpause`: Pauses or resumes submitting
+ *   updates. Rates are still computed and recorded while paused.
+ */
+pub async fn serve_admin(
+    sources: SourceHistories,
+    stats: Stats,
+    update_now_sender: mpsc::Sender<UpdateNowReply>,
+    pending: PendingConfirmation,
+    paused: Paused,
+    token: String,
+    port: u16,
+) {
+    let pause_token = token.clone();
+    let pause_paused = paused.clone();
+    let pause_stats = stats.clone();
+    let pause_route = warp::post()
+        .and(warp::path!("pause"))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |authorization: Option<String>| {
+            let (body, status) = if !is_authorized(authorization.as_deref(), &pause_token) {
+                ("Unauthorized.\n".to_string(), StatusCode::UNAUTHORIZED)
+            } else {
+                set_paused(&pause_paused, &pause_stats, true)
+            };
+            warp::reply::with_status(body, status)
+        });
+    let unpause_token = token.clone();
+    let unpause_stats = stats.clone();
+    let unpause_route = warp::post()
+        .and(warp::path!("unpause"))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |authorization: Option<String>| {
+            let (body, status) = if !is_authorized(authorization.as_deref(), &unpause_token) {
+                ("Unauthorized.\n".to_string(), StatusCode::UNAUTHORIZED)
+            } else {
+                set_paused(&paused, &unpause_stats, false)
+            };
+            warp::reply::with_status(body, status)
+        });
+    let sources_token = token.clone();
+    let sources_route = warp::put()
+        .and(warp::path!("sources" / String / String))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |label: String, action: String, authorization: Option<String>| {
+            let (body, status) = if !is_authorized(authorization.as_deref(), &sources_token) {
+                ("Unauthorized.\n".to_string(), StatusCode::UNAUTHORIZED)
+            } else {
+                match action.as_str() {
+                    "enable" => set_source_enabled(&sources, &stats, &label, true),
+                    "disable" => set_source_enabled(&sources, &stats, &label, false),
+                    _ => (format!("Unknown action {}.\n", action), StatusCode::NOT_FOUND),
+                }
+            };
+            warp::reply::with_status(body, status)
+        });
+    let update_now_token = token.clone();
+    let update_now_route = warp::put()
+        .and(warp::path!("update-now"))
+        .and(warp::header::optional::<String>("authorization"))
+        .then(move |authorization: Option<String>| {
+            let authorized = is_authorized(authorization.as_deref(), &update_now_token);
+            let update_now_sender = update_now_sender.clone();
+            async move {
+                let (body, status) = if !authorized {
+                    ("Unauthorized.\n".to_string(), StatusCode::UNAUTHORIZED)
+                } else {
+                    update_now(update_now_sender).await
+                };
+                warp::reply::with_status(body, status)
+            }
+        });
+    let pending_token = token.clone();
+    let pending_show = pending.clone();
+    let pending_route = warp::get()
+        .and(warp::path!("pending"))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |authorization: Option<String>| {
+            if !is_authorized(authorization.as_deref(), &pending_token) {
+                return warp::reply::with_status("Unauthorized.\n", StatusCode::UNAUTHORIZED)
+                    .into_response();
+            }
+            let pending = pending_show.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            match pending.as_ref() {
+                Some((update, _)) => warp::reply::json(update).into_response(),
+                None => warp::reply::with_status(
+                    "No update is awaiting confirmation.\n",
+                    StatusCode::NOT_FOUND,
+                )
+                .into_response(),
+            }
+        });
+    let decide_route = warp::post()
+        .and(warp::path!(String / u64))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |decision: String, id: u64, authorization: Option<String>| {
+            let (body, status) = if !is_authorized(authorization.as_deref(), &token) {
+                ("Unauthorized.\n".to_string(), StatusCode::UNAUTHORIZED)
+            } else {
+                match decision.as_str() {
+                    "confirm" => decide_pending(&pending, id, true),
+                    "reject" => decide_pending(&pending, id, false),
+                    _ => (format!("Unknown decision {}.\n", decision), StatusCode::NOT_FOUND),
+                }
+            };
+            warp::reply::with_status(body, status)
+        });
+    warp::serve(
+        update_now_route
+            .or(sources_route)
+            .or(pending_route)
+            .or(pause_route)
+            .or(unpause_route)
+            .or(decide_route),
+    )
+    .run(([0, 0, 0, 0], port))
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_authorized() {
+        assert!(is_authorized(Some("Bearer secret"), "secret"));
+        assert!(!is_authorized(Some("Bearer wrong"), "secret"));
+        assert!(!is_authorized(Some("secret"), "secret"));
+        assert!(!is_authorized(None, "secret"));
+        assert!(!is_authorized(Some("Bearer secre"), "secret"));
+        assert!(!is_authorized(Some("Bearer secrets"), "secret"));
+        assert!(!is_authorized(Some("Bearer "), "secret"));
+        assert!(!is_authorized(Some("Bearer "), ""));
+        assert!(!is_authorized(Some("Bearer"), ""));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"token", b"token"));
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"toke"));
+        assert!(!constant_time_eq(b"", b"token"));
+    }
+
+    #[tokio::test]
+    async fn test_set_source_enabled() {
+        let stats = crate::prometheus::initialize().await.unwrap().1;
+        let history = Arc::new(Mutex::new(RateHistory::with_capacity(1)));
+        let sources: SourceHistories =
+            Arc::new(Mutex::new(HashMap::from([("source".to_string(), history.clone())])));
+        assert_eq!(set_source_enabled(&sources, &stats, "source", false).1, StatusCode::OK);
+        assert!(history.lock().unwrap().disabled);
+        assert_eq!(set_source_enabled(&sources, &stats, "source", true).1, StatusCode::OK);
+        assert!(!history.lock().unwrap().disabled);
+        assert_eq!(set_source_enabled(&sources, &stats, "other", false).1, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_decide_pending() {
+        let pending: PendingConfirmation = Arc::new(Mutex::new(None));
+        assert_eq!(decide_pending(&pending, 1, true).1, StatusCode::NOT_FOUND);
+        let (sender, decision) = oneshot::channel();
+        let update = PendingUpdate {
+            id:      2,
+            rate:    "1000".into(),
+            change:  "+20".into(),
+            expires: chrono::Utc::now(),
+        };
+        *pending.lock().unwrap() = Some((update, sender));
+        assert_eq!(decide_pending(&pending, 1, true).1, StatusCode::NOT_FOUND);
+        assert!(pending.lock().unwrap().is_some());
+        assert_eq!(decide_pending(&pending, 2, false).1, StatusCode::OK);
+        assert!(pending.lock().unwrap().is_none());
+        assert_eq!(decision.await, Ok(false));
+    }
+}
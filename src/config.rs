@@ -1,18 +1,76 @@
-pub const MAX_TIME_CHECK_SUBMISSION: u64 = 120; // seconds
 pub const MAX_RETRIES: u64 = 5; // When attempting to reach exchange
 pub const INITIAL_RETRY_INTERVAL: u64 = 10; // seconds, when attempting to reach exchange. (This gets doubled each
                                             // unsuccessful try)
+/// Maximum number of seconds we are willing to wait, when a source asks us to
+/// retry later.
+pub const MAX_RETRY_AFTER: u64 = 600; // seconds
+/// User agent to identify the service with, in requests to sources.
+pub const DEFAULT_USER_AGENT: &str = concat!("concordium-euro2ccd/", env!("CARGO_PKG_VERSION"));
 pub const BITFINEX_URL: &str = "https://api-pub.bitfinex.com/v2/calc/fx";
+pub const BITFINEX_WS_URL: &str = "wss://api-pub.bitfinex.com/ws/2";
+/// Symbol of the trading pair, whose ticker channel we subscribe to on
+/// Bitfinex.
+pub const BITFINEX_WS_SYMBOL: &str = "tCCDEUR";
 pub const LIVECOINWATCH_URL: &str = "https://api.livecoinwatch.com/coins/single";
 pub const COINGECKO_URL: &str =
     "https://api.coingecko.com/api/v3/simple/price?ids=concordium&vs_currencies=eur";
 pub const COINMARKETCAP_URL: &str = "https://pro-api.coinmarketcap.com/v2/cryptocurrency/quotes/latest?convert=EUR&slug=concordium&aux=tags";
 
+/// If the rate of a source differs from the median of all sources by more than
+/// this factor, the source is assumed to quote the rate inverted, and is
+/// excluded.
+pub const MAX_SOURCE_DEVIATION_FACTOR: u32 = 100;
+
+/// Maximum halt threshold for updates increasing the exchange rate, in
+/// percentage.
+pub const MAX_INCREASE_THRESHOLD: u16 = 1000;
+
+/// Percentage of the lowest and of the highest medians discarded by the
+/// trimmed mean, when comparing aggregations in the canary.
+pub const CANARY_TRIMMED_MEAN_PERCENT: u8 = 20;
+/// Multiplier of the median absolute deviation used by the MAD-filtered
+/// median, when comparing aggregations in the canary.
+pub const CANARY_MAD_MULTIPLIER: u8 = 3;
+
+/// Size of the history of rates of every source, if only sizes for specific
+/// sources are given.
+pub const DEFAULT_MAX_RATES_SAVED: usize = 60;
+
+/// Window of the updates, whose median the thresholds are checked against,
+/// when the `trailing-24h` threshold baseline is used.
+pub const TRAILING_BASELINE_WINDOW: i64 = 24 * 60 * 60; // seconds
+
+/// Window of the updates, whose changes are summed, when the cumulative change
+/// is limited by `max-daily-change`.
+pub const DAILY_CHANGE_WINDOW: i64 = 24 * 60 * 60; // seconds
+
+/// The maximum time without a finalized update, in update intervals, if no
+/// maximum update gap is given.
+pub const DEFAULT_MAX_UPDATE_GAP_INTERVALS: u64 = 3;
+
 pub const FORCED_DRY_RUN_FILE: &str = "update.lockfile";
 
-pub const RETRY_SUBMISSION_INTERVAL: u64 = 10; // seconds
-/// Expiry of the update instruction. This should be a bit less than
-/// [MAX_TIME_CHECK_SUBMISSION].
-pub const UPDATE_EXPIRY_OFFSET: u64 = 100; // seconds
+/// Exit code of the service, when `max-consecutive-failures` update cycles in a
+/// row have failed.
+pub const EXIT_CODE_CONSECUTIVE_FAILURES: i32 = 3;
+
+/// Time to wait for the response of a source, when probing it at startup.
+pub const SOURCE_PROBE_TIMEOUT: u64 = 5; // seconds
+
+pub const WARMUP_POLL_INTERVAL: u64 = 1; // seconds, when waiting for the first readings
+
+/// Delay before the first retry of connecting to the nodes. (This gets doubled
+/// each unsuccessful try, up to [RECONNECT_MAX_DELAY])
+pub const RECONNECT_INITIAL_DELAY: u64 = 2; // seconds
+/// Maximum delay between retries of connecting to the nodes.
+pub const RECONNECT_MAX_DELAY: u64 = 60; // seconds
+
+/// Maximum difference between the local clock and the time of the last
+/// finalized block, before a warning is logged at startup.
+pub const MAX_CLOCK_SKEW: i64 = 30; // seconds
 
 pub const AWS_REGION: &str = "eu-central-1";
+/// Number of retries of fetching a secret from AWS, when it fails transiently.
+pub const SECRET_MAX_RETRIES: u64 = 4;
+pub const SECRET_INITIAL_RETRY_INTERVAL: u64 = 1; // seconds, when fetching a secret. (This gets doubled each
+                                                  // unsuccessful try)
@@ -2,79 +2,849 @@ use concordium_rust_sdk::types::ExchangeRate;
 use num_bigint::BigInt;
 use num_integer::Integer;
 use num_rational::BigRational;
-use num_traits::{CheckedDiv, ToPrimitive, Zero};
-use std::collections::VecDeque;
+use num_traits::{CheckedDiv, One, Signed, ToPrimitive, Zero};
+use std::{borrow::Cow, collections::VecDeque, fmt, str::FromStr};
 
 /**
- * Compute the average of the rates stored in the given VeqDeque.
- * Returns None if the queue is empty.
+ * Compute the average of the given rates.
+ * Returns None if there are no rates.
  */
-pub fn compute_average(rates: &[BigRational]) -> Option<BigRational> {
-    rates
-        .iter()
-        .fold(BigRational::zero(), |a, b| a + b)
-        .checked_div(&BigRational::from_integer(rates.len().into()))
+pub fn compute_average<'a>(
+    rates: impl IntoIterator<Item = &'a BigRational>,
+) -> Option<BigRational> {
+    let (sum, count) = rates
+        .into_iter()
+        .fold((BigRational::zero(), 0usize), |(sum, count), rate| (sum + rate, count + 1));
+    sum.checked_div(&BigRational::from_integer(count.into()))
+}
+
+/// Which value is the median of an even number of rates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MedianEvenPolicy {
+    /// The average of the two middle rates.
+    Average,
+    /// The lower of the two middle rates.
+    Lower,
+    /// The higher of the two middle rates.
+    Higher,
+}
+
+impl MedianEvenPolicy {
+    /// Selects the median from the two middle rates.
+    fn select(
+        &self,
+        lower_middle: &BigRational,
+        upper_middle: &BigRational,
+    ) -> Option<BigRational> {
+        match self {
+            MedianEvenPolicy::Average => compute_average([lower_middle, upper_middle]),
+            MedianEvenPolicy::Lower => Some(lower_middle.clone()),
+            MedianEvenPolicy::Higher => Some(upper_middle.clone()),
+        }
+    }
+}
+
+impl FromStr for MedianEvenPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "average" => Ok(MedianEvenPolicy::Average),
+            "lower" => Ok(MedianEvenPolicy::Lower),
+            "higher" => Ok(MedianEvenPolicy::Higher),
+            _ => anyhow::bail!("Unknown median policy {}, expected average, lower or higher", s),
+        }
+    }
+}
+
+impl fmt::Display for MedianEvenPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MedianEvenPolicy::Average => write!(f, "average"),
+            MedianEvenPolicy::Lower => write!(f, "lower"),
+            MedianEvenPolicy::Higher => write!(f, "higher"),
+        }
+    }
 }
 
 /**
- * Compute the median of the rates stored in the given VeqDeque.
+ * Compute the median of the rates stored in the given VeqDeque. The policy
+ * determines the median of an even number of rates.
  * Returns None if the queue is empty.
+ * Only references to the rates are reordered, and the median is selected
+ * without sorting all of them.
  */
-pub fn compute_median(rates: &VecDeque<BigRational>) -> Option<BigRational> {
-    let len = rates.len();
+pub fn compute_median(
+    rates: &VecDeque<BigRational>,
+    policy: MedianEvenPolicy,
+) -> Option<BigRational> {
+    compute_median_of(&mut rates.iter().collect::<Vec<&BigRational>>(), policy)
+}
+
+/**
+ * Compute the median of the rates referenced by the given slice, which is
+ * reordered. This allows reusing the slice for multiple computations,
+ * without cloning the rates. The policy determines the median of an even
+ * number of rates.
+ * Returns None if the slice is empty.
+ */
+pub fn compute_median_of(
+    rate_refs: &mut [&BigRational],
+    policy: MedianEvenPolicy,
+) -> Option<BigRational> {
+    let len = rate_refs.len();
     if len == 0 {
         return None;
     }
-    let mut rate_vec = rates.iter().cloned().collect::<Vec<BigRational>>();
-    rate_vec.sort();
+    let (lower, upper_middle, _) = rate_refs.select_nth_unstable(len / 2);
     if len.is_odd() {
-        Some(rate_vec[(len - 1) / 2].clone())
+        Some((*upper_middle).clone())
     } else {
-        compute_average(&rate_vec[(len - 1) / 2..(len / 2) + 1])
+        // The lower middle is the largest of the rates before the upper middle.
+        let lower_middle = lower.iter().max()?;
+        policy.select(lower_middle, upper_middle)
+    }
+}
+
+/**
+ * Remove the rates outside [Q1 - multiplier * IQR, Q3 + multiplier * IQR],
+ * where Q1 and Q3 are the medians of the lower and upper half of the rates,
+ * computed like in compute_median, and IQR is Q3 - Q1. If the number of
+ * rates is odd, the median is in neither half.
+ * Histories with fewer than four rates are not filtered.
+ * The order of the remaining rates is preserved.
+ */
+pub fn filter_iqr(rates: &VecDeque<BigRational>, multiplier: BigRational) -> VecDeque<BigRational> {
+    let len = rates.len();
+    if len < 4 {
+        return rates.clone();
+    }
+    let mut rate_refs = rates.iter().collect::<Vec<&BigRational>>();
+    rate_refs.sort();
+    let (lower, rest) = rate_refs.split_at_mut(len / 2);
+    let upper = &mut rest[len % 2..];
+    let (q1, q3) = match (
+        compute_median_of(lower, MedianEvenPolicy::Average),
+        compute_median_of(upper, MedianEvenPolicy::Average),
+    ) {
+        (Some(q1), Some(q3)) => (q1, q3),
+        _ => return rates.clone(),
+    };
+    let margin = (&q3 - &q1) * multiplier;
+    let low = &q1 - &margin;
+    let high = q3 + margin;
+    rates.iter().filter(|rate| **rate >= low && **rate <= high).cloned().collect()
+}
+
+/**
+ * Compute the mean of the rates stored in the given VecDeque.
+ * Returns None if the queue is empty.
+ */
+pub fn compute_mean(rates: &VecDeque<BigRational>) -> Option<BigRational> { compute_average(rates) }
+
+/**
+ * Compute the mean of the rates stored in the given VecDeque, after
+ * discarding the given percentage of the lowest and of the highest rates.
+ * The number of discarded rates is rounded down, and the percentage must be
+ * less than 50.
+ * Returns None if the queue is empty.
+ */
+pub fn compute_trimmed_mean(rates: &VecDeque<BigRational>, percent: u8) -> Option<BigRational> {
+    let mut rate_vec = rates.iter().collect::<Vec<&BigRational>>();
+    rate_vec.sort();
+    let trim = rate_vec.len() * usize::from(percent) / 100;
+    compute_average(rate_vec[trim..rate_vec.len() - trim].iter().copied())
+}
+
+/**
+ * Compute the median of the rates stored in the given VecDeque, after
+ * discarding the rates further than the multiplier times the median
+ * absolute deviation (MAD) from the median. If the MAD is 0, only the rates
+ * equal to the median are kept, which does not change the median. The
+ * policy only determines the median of the kept rates.
+ * Returns None if the queue is empty.
+ */
+pub fn compute_mad_filtered_median(
+    rates: &VecDeque<BigRational>,
+    multiplier: u8,
+    policy: MedianEvenPolicy,
+) -> Option<BigRational> {
+    let median = compute_median(rates, MedianEvenPolicy::Average)?;
+    let deviations = rates.iter().map(|rate| (rate - &median).abs()).collect::<VecDeque<_>>();
+    let bound = compute_median(&deviations, MedianEvenPolicy::Average)?
+        * BigRational::from_integer(multiplier.into());
+    let kept = rates
+        .iter()
+        .zip(deviations.iter())
+        .filter(|(_, deviation)| **deviation <= bound)
+        .map(|(rate, _)| rate.clone())
+        .collect::<VecDeque<_>>();
+    compute_median(&kept, policy)
+}
+
+/**
+ * Compute the weighted median of the given rates, each paired with its
+ * weight. Rates with weight 0 are ignored. If the rates can be split into
+ * two halves of exactly equal weight, the policy selects from the two
+ * middle rates, so that equal weights give the same result as
+ * compute_median. Returns None if there are no rates with a positive
+ * weight.
+ */
+pub fn compute_weighted_median(
+    rates: &[(BigRational, u32)],
+    policy: MedianEvenPolicy,
+) -> Option<BigRational> {
+    let mut rate_vec = rates.iter().filter(|(_, weight)| *weight > 0).collect::<Vec<_>>();
+    rate_vec.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let total: u64 = rate_vec.iter().map(|(_, weight)| u64::from(*weight)).sum();
+    let mut cumulative = 0u64;
+    for (i, (rate, weight)) in rate_vec.iter().enumerate() {
+        cumulative += u64::from(*weight);
+        if 2 * cumulative > total {
+            return Some(rate.clone());
+        }
+        if 2 * cumulative == total {
+            // The remaining weight is positive, so there is a next rate.
+            return policy.select(rate, &rate_vec[i + 1].0);
+        }
+    }
+    None
+}
+
+/// Strategy for aggregating multiple rates into one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    Median,
+    Mean,
+    /// Mean after discarding the given percentage of the lowest and of the
+    /// highest rates.
+    TrimmedMean(u8),
+    /// Median, where each rate counts according to its weight.
+    WeightedMedian,
+    /// Median after discarding the rates further than the given multiplier
+    /// times the median absolute deviation from the median.
+    MadFilteredMedian(u8),
+}
+
+impl Aggregation {
+    /**
+     * Aggregate the given rates into one. The policy determines the median
+     * of an even number of rates, for the strategies using a median.
+     * Returns None if the queue is empty.
+     */
+    pub fn aggregate(
+        &self,
+        rates: &VecDeque<BigRational>,
+        policy: MedianEvenPolicy,
+    ) -> Option<BigRational> {
+        match self {
+            Aggregation::Median | Aggregation::WeightedMedian => compute_median(rates, policy),
+            Aggregation::Mean => compute_mean(rates),
+            Aggregation::TrimmedMean(percent) => compute_trimmed_mean(rates, *percent),
+            Aggregation::MadFilteredMedian(multiplier) => {
+                compute_mad_filtered_median(rates, *multiplier, policy)
+            }
+        }
+    }
+
+    /**
+     * Aggregate the given rates, each paired with its weight, into one.
+     * Only the weighted median uses the weights, the other strategies
+     * ignore them.
+     * Returns None if there are no rates.
+     */
+    pub fn aggregate_weighted(
+        &self,
+        rates: &[(BigRational, u32)],
+        policy: MedianEvenPolicy,
+    ) -> Option<BigRational> {
+        match self {
+            Aggregation::WeightedMedian => compute_weighted_median(rates, policy),
+            _ => self.aggregate(&rates.iter().map(|(rate, _)| rate.clone()).collect(), policy),
+        }
+    }
+
+    /// Whether the strategy uses the weights of the rates.
+    pub fn is_weighted(&self) -> bool { *self == Aggregation::WeightedMedian }
+}
+
+impl FromStr for Aggregation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "median" => Ok(Aggregation::Median),
+            "mean" => Ok(Aggregation::Mean),
+            "weighted-median" => Ok(Aggregation::WeightedMedian),
+            _ => {
+                if let Some(percent) = s.strip_prefix("trimmed-mean:") {
+                    let percent: u8 = percent.parse()?;
+                    anyhow::ensure!(
+                        percent < 50,
+                        "Trimmed percentage outside of allowed range (0-49): {}",
+                        percent
+                    );
+                    Ok(Aggregation::TrimmedMean(percent))
+                } else if let Some(multiplier) = s.strip_prefix("mad-median:") {
+                    let multiplier: u8 = multiplier.parse()?;
+                    anyhow::ensure!(
+                        multiplier > 0,
+                        "Median absolute deviation multiplier must be at least 1"
+                    );
+                    Ok(Aggregation::MadFilteredMedian(multiplier))
+                } else {
+                    anyhow::bail!(
+                        "Unknown aggregation {}, expected median, mean, trimmed-mean:<percent>, \
+                         weighted-median or mad-median:<multiplier>",
+                        s
+                    )
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Aggregation::Median => write!(f, "median"),
+            Aggregation::Mean => write!(f, "mean"),
+            Aggregation::TrimmedMean(percent) => write!(f, "trimmed-mean:{}", percent),
+            Aggregation::WeightedMedian => write!(f, "weighted-median"),
+            Aggregation::MadFilteredMedian(multiplier) => write!(f, "mad-median:{}", multiplier),
+        }
+    }
+}
+
+/// Filter for removing outliers from the history of a source, before its
+/// median is computed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutlierFilter {
+    /// Keep all rates.
+    None,
+    /// Remove rates further than the multiplier times the interquartile range
+    /// from the quartiles.
+    Iqr(BigRational),
+}
+
+impl OutlierFilter {
+    /// Apply the filter to the given rates. The rates are only copied, if
+    /// they are filtered.
+    pub fn apply<'a>(&self, rates: &'a VecDeque<BigRational>) -> Cow<'a, VecDeque<BigRational>> {
+        match self {
+            OutlierFilter::None => Cow::Borrowed(rates),
+            OutlierFilter::Iqr(multiplier) => Cow::Owned(filter_iqr(rates, multiplier.clone())),
+        }
+    }
+}
+
+impl FromStr for OutlierFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "none" {
+            return Ok(OutlierFilter::None);
+        }
+        match s.strip_prefix("iqr:") {
+            Some(multiplier) => {
+                let multiplier: f64 = multiplier.parse()?;
+                match BigRational::from_float(multiplier) {
+                    Some(rational) if multiplier >= 0.0 => Ok(OutlierFilter::Iqr(rational)),
+                    _ => anyhow::bail!("Invalid interquartile range multiplier: {}", multiplier),
+                }
+            }
+            None => {
+                anyhow::bail!("Unknown outlier filter {}, expected none or iqr:<multiplier>", s)
+            }
+        }
+    }
+}
+
+impl fmt::Display for OutlierFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutlierFilter::None => write!(f, "none"),
+            OutlierFilter::Iqr(multiplier) => {
+                write!(f, "iqr:{}", multiplier.to_f64().unwrap_or(f64::NAN))
+            }
+        }
+    }
+}
+
+/// What the warning and halt thresholds are checked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThresholdBaseline {
+    /// The last update, i.e. the rate on chain.
+    LastUpdate,
+    /// The median of the updates performed in the last 24 hours.
+    Trailing24h,
+}
+
+impl FromStr for ThresholdBaseline {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last-update" => Ok(ThresholdBaseline::LastUpdate),
+            "trailing-24h" => Ok(ThresholdBaseline::Trailing24h),
+            _ => anyhow::bail!(
+                "Unknown threshold baseline {}, expected last-update or trailing-24h",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ThresholdBaseline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ThresholdBaseline::LastUpdate => write!(f, "last-update"),
+            ThresholdBaseline::Trailing24h => write!(f, "trailing-24h"),
+        }
+    }
+}
+
+/// What is done, when an update violates a halt threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HaltAction {
+    /// Force the service into dry run, until the rate on chain is corrected.
+    Protect,
+    /// Skip the update, and try again in the next update cycle.
+    Skip,
+}
+
+impl FromStr for HaltAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "protect" => Ok(HaltAction::Protect),
+            "skip" => Ok(HaltAction::Skip),
+            _ => anyhow::bail!("Unknown halt action {}, expected protect or skip", s),
+        }
     }
 }
 
+impl fmt::Display for HaltAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HaltAction::Protect => write!(f, "protect"),
+            HaltAction::Skip => write!(f, "skip"),
+        }
+    }
+}
+
+/**
+ * Convert a positive BigRational type into an exchange rate.
+ * Fails if the rate is not positive, or too small to be approximated by a
+ * positive exchange rate.
+ */
+pub fn convert_big_fraction_to_exchange_rate(target: &BigRational) -> anyhow::Result<ExchangeRate> {
+    anyhow::ensure!(target.is_positive(), "The rate {} is not positive", target);
+    let (numerator, denominator) = approximate_in_u64(target, MAX_CONTINUED_FRACTION_TERMS);
+    anyhow::ensure!(numerator > 0, "The rate {} is too small to be converted", target);
+    Ok(ExchangeRate::new_unchecked(numerator, denominator))
+}
+
+/**
+ * Convert a positive BigRational type into an exchange rate, which deviates
+ * from it by at most the relative error epsilon, i.e. |rate - target| <=
+ * epsilon * target.
+ * Fails if not even the best approximation by a fraction of u64's is within
+ * the bound.
+ */
+pub fn convert_with_max_error(
+    target: &BigRational,
+    epsilon: &BigRational,
+) -> anyhow::Result<ExchangeRate> {
+    let rate = convert_big_fraction_to_exchange_rate(target)?;
+    let error = relative_error(target, &rate);
+    anyhow::ensure!(
+        error <= *epsilon,
+        "The rate {} cannot be converted within a relative error of {} (best approximation {}/{} \
+         has relative error {})",
+        target,
+        epsilon,
+        rate.numerator(),
+        rate.denominator(),
+        error
+    );
+    Ok(rate)
+}
+
+/**
+ * The relative error of the exchange rate, as an approximation of the
+ * positive target.
+ */
+pub fn relative_error(target: &BigRational, rate: &ExchangeRate) -> BigRational {
+    let approximation = BigRational::new(rate.numerator().into(), rate.denominator().into());
+    (approximation - target).abs() / target
+}
+
+/// The maximum number of terms of a continued fraction computed, when
+/// approximating a rate by a fraction of u64's. The denominators of the
+/// convergents grow at least like the Fibonacci numbers, so they exceed
+/// u64::MAX after at most 93 terms, and the limit is only a safeguard.
+const MAX_CONTINUED_FRACTION_TERMS: usize = 128;
+
 /**
- * Convert a BigRational type into an exchange rate.
+ * Approximate a non-negative BigRational by a fraction of u64's.
  * 1. Check if the BigRational can be translated directly (both bigints are
  *    u64)
- * 2. Divide the numerator and denominator each by 2. Repeat until 1.
- *    succeeds.
+ * 2. Otherwise find the best rational approximation, whose numerator and
+ *    denominator both fit in a u64, using continued fractions: The
+ *    convergents of the continued fraction are computed, until the next one
+ *    no longer fits. The best approximation is then either the last
+ *    convergent that fits, or the largest semiconvergent that fits.
+ * Rates above u64::MAX are converted to u64::MAX.
+ * At most max_terms terms of the continued fraction are computed. If they
+ * are exhausted, the last convergent is returned, and a warning is logged.
  */
-pub fn convert_big_fraction_to_exchange_rate(target: &BigRational) -> ExchangeRate {
-    let mut numerator: BigInt = target.numer().clone();
-    let mut denominator: BigInt = target.denom().clone();
-    loop {
-        // Check if the bigints can fit into u64's.
-        if let (Some(p), Some(q)) = (numerator.to_u64(), denominator.to_u64()) {
-            return ExchangeRate::new_unchecked(p, q);
-        };
-        numerator /= 2;
-        denominator /= 2;
-        let gcd = numerator.gcd(&denominator);
-        if gcd > 1.into() {
-            numerator /= &gcd;
-            denominator /= gcd;
+fn approximate_in_u64(target: &BigRational, max_terms: usize) -> (u64, u64) {
+    // Check if the bigints can fit into u64's.
+    if let (Some(p), Some(q)) = (target.numer().to_u64(), target.denom().to_u64()) {
+        return (p, q);
+    };
+    let max = BigInt::from(u64::MAX);
+    // The two latest convergents, starting with the conventional 0/1 and 1/0.
+    let (mut prev_p, mut prev_q) = (BigInt::zero(), BigInt::one());
+    let (mut p, mut q) = (BigInt::one(), BigInt::zero());
+    let mut numerator = target.numer().clone();
+    let mut denominator = target.denom().clone();
+    let mut terms = 0;
+    while !denominator.is_zero() {
+        if terms == max_terms {
+            log::warn!(
+                "Approximating {} was stopped after {} terms of its continued fraction, using \
+                 {}/{}",
+                target,
+                max_terms,
+                p,
+                q
+            );
+            break;
+        }
+        terms += 1;
+        let (a, remainder) = numerator.div_rem(&denominator);
+        let next_p = &a * &p + &prev_p;
+        let next_q = &a * &q + &prev_q;
+        if next_p > max || next_q > max {
+            // Largest t, such that the semiconvergent (prev_p + t * p) / (prev_q + t * q)
+            // fits.
+            let t_p = if p.is_zero() {
+                a.clone()
+            } else {
+                (&max - &prev_p) / &p
+            };
+            let t_q = if q.is_zero() {
+                a
+            } else {
+                (&max - &prev_q) / &q
+            };
+            let t = t_p.min(t_q);
+            let semi_p = &prev_p + &t * &p;
+            let semi_q = &prev_q + &t * &q;
+            let candidates = [(p, q), (semi_p, semi_q)];
+            return candidates
+                .into_iter()
+                .filter(|(_, q)| !q.is_zero())
+                .min_by_key(|(p, q)| (target - BigRational::new(p.clone(), q.clone())).abs())
+                .and_then(|(p, q)| Some((p.to_u64()?, q.to_u64()?)))
+                .unwrap_or((u64::MAX, 1));
         }
+        prev_p = std::mem::replace(&mut p, next_p);
+        prev_q = std::mem::replace(&mut q, next_q);
+        numerator = std::mem::replace(&mut denominator, remainder);
     }
+    // Either the continued fraction ended, so the last convergent is the (reduced)
+    // target, or the last convergent is the best approximation found.
+    (p.to_u64().unwrap_or(u64::MAX), q.to_u64().filter(|q| *q > 0).unwrap_or(1))
 }
 
 /**
  * Calculates the relative change from the current to the new, in
- * percentages.
+ * percentages. The change is negative, if the new is lower than the
+ * current.
  */
 pub fn relative_change(current: &BigRational, new: &BigRational) -> BigRational {
-    if current > new {
-        (current - new) * BigRational::from_integer(100.into()) / current
+    (new - current) * BigRational::from_integer(100.into()) / current
+}
+
+/**
+ * Calculates the size of the relative change from the current to the new,
+ * in percentages, regardless of its direction.
+ */
+pub fn abs_relative_change(current: &BigRational, new: &BigRational) -> BigRational {
+    relative_change(current, new).abs()
+}
+
+/**
+ * Clamps the new rate, such that it changes at most max_percent from the
+ * current, in either direction.
+ * Returns None, if the new rate is already within that bound.
+ */
+pub fn clamp_change(
+    current: &BigRational,
+    new: &BigRational,
+    max_percent: &BigRational,
+) -> Option<BigRational> {
+    let change = relative_change(current, new);
+    if change.abs() <= *max_percent {
+        return None;
+    }
+    let bound = if change.is_positive() {
+        max_percent.clone()
+    } else {
+        -max_percent
+    };
+    Some(current + current * bound / BigRational::from_integer(100.into()))
+}
+
+/**
+ * Limits the new rate, such that the given cumulative change (in
+ * percentage) plus the change from the current rate stays within
+ * max_percent, in either direction. The limited rate never changes in the
+ * opposite direction of the new rate, so it is the current rate, if the
+ * cumulative change already exceeds the limit.
+ * Returns None, if the new rate is already within the limit.
+ */
+pub fn limit_cumulative_change(
+    current: &BigRational,
+    new: &BigRational,
+    cumulative: &BigRational,
+    max_percent: &BigRational,
+) -> Option<BigRational> {
+    let change = relative_change(current, new);
+    let total = cumulative + &change;
+    if total.abs() <= *max_percent {
+        return None;
+    }
+    let bound = if total.is_positive() {
+        max_percent.clone()
     } else {
-        (new - current) * BigRational::from_integer(100.into()) / current
+        -max_percent
+    };
+    let allowed = bound - cumulative;
+    let allowed = if allowed.is_positive() == change.is_positive() && !allowed.is_zero() {
+        allowed
+    } else {
+        BigRational::zero()
+    };
+    Some(current + current * allowed / BigRational::from_integer(100.into()))
+}
+
+/**
+ * Sums the relative changes (in percentage) between the consecutive given
+ * rates, each paired with the time it was set. The change of a rate is
+ * paired with the time of the rate.
+ */
+pub fn consecutive_changes<T: Clone>(rates: &[(T, BigRational)]) -> VecDeque<(T, BigRational)> {
+    rates
+        .windows(2)
+        .map(|pair| (pair[1].0.clone(), relative_change(&pair[0].1, &pair[1].1)))
+        .collect()
+}
+
+/// Largest power of ten a parsed decimal number may be scaled by, so that a
+/// response like "1e999999999" cannot make us build a huge number.
+const MAX_DECIMAL_EXPONENT: u32 = 64;
+
+/**
+ * Parses a decimal number, e.g. "0.0123", "-1.5" or "1.2e-3", into the
+ * exact fraction it denotes. Returns None, if the text is not a decimal
+ * number, or if its exponent (including the digits after the point) exceeds
+ * [MAX_DECIMAL_EXPONENT] in absolute value.
+ */
+pub fn rational_from_decimal_str(s: &str) -> Option<BigRational> {
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], s[i + 1..].parse::<i32>().ok()?),
+        None => (s, 0),
+    };
+    let (negative, mantissa) = match mantissa.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if integer.is_empty() && fraction.is_empty()
+        || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+    {
+        return None;
     }
+    let digits: BigInt = format!("{}{}", integer, fraction).parse().ok()?;
+    let exponent = exponent.checked_sub(i32::try_from(fraction.len()).ok()?)?;
+    if exponent.unsigned_abs() > MAX_DECIMAL_EXPONENT {
+        return None;
+    }
+    let power = BigInt::from(10).pow(exponent.unsigned_abs());
+    let magnitude = if exponent >= 0 {
+        BigRational::from_integer(digits * power)
+    } else {
+        BigRational::new(digits, power)
+    };
+    Some(
+        if negative {
+            -magnitude
+        } else {
+            magnitude
+        },
+    )
+}
+
+/**
+ * Rounds the given value to the given number of significant decimal digits,
+ * rounding half-way cases away from zero. The number of digits should be
+ * positive.
+ */
+pub fn round_rational(r: &BigRational, digits: u32) -> BigRational {
+    if r.is_zero() {
+        return r.clone();
+    }
+    let ten = BigRational::from_integer(10.into());
+    let lower = BigRational::from_integer(BigInt::from(10).pow(digits.saturating_sub(1)));
+    let upper = &lower * &ten;
+    // Scale the value, such that its integer part has exactly the given number
+    // of digits:
+    let mut scaled = r.abs();
+    let mut factor = BigRational::one();
+    while scaled < lower {
+        scaled = scaled * &ten;
+        factor = factor * &ten;
+    }
+    while scaled >= upper {
+        scaled = scaled / &ten;
+        factor = factor / &ten;
+    }
+    let rounded = scaled.round() / factor;
+    if r.is_negative() {
+        -rounded
+    } else {
+        rounded
+    }
+}
+
+/**
+ * Performs one step of an exponentially weighted moving average, i.e.
+ * computes alpha * new + (1 - alpha) * prev.
+ */
+pub fn ewma_step(prev: &BigRational, new: &BigRational, alpha: &BigRational) -> BigRational {
+    alpha * new + (BigRational::one() - alpha) * prev
+}
+
+/**
+ * Checks whether either of the given values is more than factor times the
+ * other.
+ */
+pub fn differs_by_factor(a: &BigRational, b: &BigRational, factor: u32) -> bool {
+    let factor = BigRational::from_integer(factor.into());
+    a > &(b * &factor) || b > &(a * &factor)
+}
+
+/// Minimum number of rates, for which it can be determined, which of them
+/// deviate from the others. (With two rates, it is unknown which is right)
+pub const MIN_RATES_FOR_DEVIATION_CHECK: usize = 3;
+
+/**
+ * For each of the given rates, computes the median of the other rates, and
+ * returns whether the rate differs from it by more than the given factor,
+ * along with that median. Leaving the rate itself out ensures that a
+ * deviating rate does not pull the reference towards it. Returns None, if
+ * there are fewer than [MIN_RATES_FOR_DEVIATION_CHECK] rates.
+ */
+pub fn deviating_rates(rates: &[&BigRational], factor: u32) -> Option<Vec<(bool, BigRational)>> {
+    if rates.len() < MIN_RATES_FOR_DEVIATION_CHECK {
+        return None;
+    }
+    let deviations = (0..rates.len())
+        .map(|i| {
+            let mut others = rates
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, rate)| *rate)
+                .collect::<Vec<_>>();
+            // There are at least 2 other rates, so the median exists:
+            let median = compute_median_of(&mut others, MedianEvenPolicy::Average)?;
+            Some((differs_by_factor(rates[i], &median, factor), median))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(deviations)
+}
+
+/**
+ * Computes the relative spread between the lowest and the highest of the
+ * given rates, in percentage of the lowest rate. Returns None, if there are
+ * no rates, or the lowest rate is not positive.
+ */
+pub fn relative_spread<'a>(rates: impl Iterator<Item = &'a BigRational>) -> Option<BigRational> {
+    let (min, max) = rates.fold(None, |bounds: Option<(&BigRational, &BigRational)>, rate| {
+        Some(match bounds {
+            Some((min, max)) => (min.min(rate), max.max(rate)),
+            None => (rate, rate),
+        })
+    })?;
+    if !min.is_positive() {
+        return None;
+    }
+    Some(relative_change(min, max))
+}
+
+/**
+ * Computes the spread between the lowest and the highest of the given
+ * rates, relative to the median of the rates, i.e. (max - min) / median.
+ * Returns None, if there are no rates, or the median is not positive.
+ */
+pub fn spread_ratio<'a>(rates: impl Iterator<Item = &'a BigRational>) -> Option<BigRational> {
+    let mut rate_refs = rates.collect::<Vec<_>>();
+    let median = compute_median_of(&mut rate_refs, MedianEvenPolicy::Average)?;
+    if !median.is_positive() {
+        return None;
+    }
+    let min = rate_refs.iter().min()?;
+    let max = rate_refs.iter().max()?;
+    Some((*max - *min) / median)
+}
+
+/**
+ * Counts how many of the given rates are within the tolerance (in
+ * percentage) of the median of all of them. Returns 0, if there are no
+ * rates.
+ */
+pub fn count_agreeing<'a>(
+    rates: impl Iterator<Item = &'a BigRational>,
+    tolerance: &BigRational,
+) -> usize {
+    let mut rate_refs = rates.collect::<Vec<_>>();
+    let median = match compute_median_of(&mut rate_refs, MedianEvenPolicy::Average) {
+        Some(median) if median.is_positive() => median,
+        _ => return 0,
+    };
+    rate_refs.into_iter().filter(|rate| abs_relative_change(&median, rate) <= *tolerance).count()
+}
+
+/**
+ * Select the items to use from the given items, which are paired with their
+ * tier. Tiers are included in increasing order, until at least min_items
+ * are selected, or all tiers are included.
+ */
+pub fn select_by_tier<T>(mut items: Vec<(u8, T)>, min_items: usize) -> Vec<T> {
+    items.sort_by_key(|(tier, _)| *tier);
+    let mut selected = Vec::new();
+    let mut current_tier = None;
+    for (tier, item) in items {
+        if current_tier != Some(tier) {
+            if selected.len() >= min_items {
+                break;
+            }
+            current_tier = Some(tier);
+        }
+        selected.push(item);
+    }
+    selected
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use num_traits::Signed;
 
     #[test]
     fn test_compute_average() {
@@ -94,14 +864,14 @@ mod tests {
                 &BigRational::from_integer(50.into()),
                 &BigRational::from_integer(40.into())
             ),
-            BigRational::from_integer(20.into())
+            BigRational::from_integer((-20).into())
         );
         assert_eq!(
             relative_change(
                 &BigRational::from_integer(50.into()),
                 &BigRational::from_integer(30.into())
             ),
-            BigRational::from_integer(40.into())
+            BigRational::from_integer((-40).into())
         );
         assert_eq!(
             relative_change(
@@ -115,7 +885,7 @@ mod tests {
                 &BigRational::from_integer(100.into()),
                 &BigRational::from_integer(50.into())
             ),
-            BigRational::from_integer(50.into())
+            BigRational::from_integer((-50).into())
         );
         assert_eq!(
             relative_change(
@@ -136,10 +906,176 @@ mod tests {
                 &BigRational::from_integer(200.into()),
                 &BigRational::from_integer(100.into())
             ),
+            BigRational::from_integer((-50).into())
+        );
+        assert_eq!(
+            relative_change(
+                &BigRational::from_integer(50.into()),
+                &BigRational::from_integer(50.into())
+            ),
+            BigRational::zero()
+        );
+        assert_eq!(
+            abs_relative_change(
+                &BigRational::from_integer(50.into()),
+                &BigRational::from_integer(40.into())
+            ),
+            BigRational::from_integer(20.into())
+        );
+        assert_eq!(
+            abs_relative_change(
+                &BigRational::from_integer(50.into()),
+                &BigRational::from_integer(75.into())
+            ),
             BigRational::from_integer(50.into())
         );
     }
 
+    #[test]
+    fn test_limit_cumulative_change() {
+        let rate = |n: i64| BigRational::from_integer(n.into());
+        let current = rate(100);
+        let max_percent = rate(10);
+        assert_eq!(limit_cumulative_change(&current, &rate(105), &rate(4), &max_percent), None);
+        assert_eq!(limit_cumulative_change(&current, &rate(95), &rate(-5), &max_percent), None);
+        assert_eq!(
+            limit_cumulative_change(&current, &rate(105), &rate(8), &max_percent),
+            Some(rate(102))
+        );
+        assert_eq!(
+            limit_cumulative_change(&current, &rate(90), &rate(-5), &max_percent),
+            Some(rate(95))
+        );
+        // A change reducing the cumulative change is allowed:
+        assert_eq!(limit_cumulative_change(&current, &rate(95), &rate(12), &max_percent), None);
+        // The limit never reverses the direction of the change:
+        assert_eq!(
+            limit_cumulative_change(&current, &rate(101), &rate(12), &max_percent),
+            Some(rate(100))
+        );
+    }
+
+    #[test]
+    fn test_consecutive_changes() {
+        let rate = |n: i64| BigRational::from_integer(n.into());
+        let rates = [(1, rate(100)), (2, rate(110)), (3, rate(99))];
+        assert_eq!(
+            consecutive_changes(&rates),
+            VecDeque::from(vec![(2, rate(10)), (3, rate(-10))])
+        );
+        assert!(consecutive_changes(&rates[..1]).is_empty());
+    }
+
+    #[test]
+    fn test_clamp_change() {
+        let current = BigRational::from_integer(100.into());
+        let max_percent = BigRational::from_integer(10.into());
+        assert_eq!(
+            clamp_change(&current, &BigRational::from_integer(150.into()), &max_percent),
+            Some(BigRational::from_integer(110.into()))
+        );
+        assert_eq!(
+            clamp_change(&current, &BigRational::from_integer(50.into()), &max_percent),
+            Some(BigRational::from_integer(90.into()))
+        );
+        assert_eq!(
+            clamp_change(&current, &BigRational::from_integer(105.into()), &max_percent),
+            None
+        );
+        assert_eq!(
+            clamp_change(&current, &BigRational::from_integer(90.into()), &max_percent),
+            None
+        );
+    }
+
+    #[test]
+    fn test_convert_with_max_error() {
+        let epsilon = BigRational::new(1.into(), 1_000_000_000.into());
+        // Fractions of u64's are converted exactly, even with no error allowed.
+        let third = BigRational::new(1.into(), 3.into());
+        let rate = convert_with_max_error(&third, &BigRational::zero()).unwrap();
+        assert_eq!((rate.numerator(), rate.denominator()), (1, 3));
+        // Rates with too many digits can only be approximated.
+        let big = BigRational::new(
+            BigInt::from(u64::MAX) * BigInt::from(u64::MAX) + 1,
+            BigInt::from(u64::MAX) * BigInt::from(3) + 1,
+        );
+        assert!(convert_with_max_error(&big, &BigRational::zero()).is_err());
+        assert!(convert_with_max_error(&big, &epsilon).is_ok());
+        // Rates far above u64::MAX cannot be approximated.
+        let huge = BigRational::from_integer(BigInt::from(u64::MAX) * 1000);
+        assert!(convert_with_max_error(&huge, &epsilon).is_err());
+        assert!(convert_with_max_error(&BigRational::zero(), &epsilon).is_err());
+    }
+
+    #[test]
+    fn test_convert_with_max_error_random() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(7);
+        let ten = BigInt::from(10);
+        for _ in 0..2000 {
+            let epsilon = BigRational::new(1.into(), ten.pow(rng.gen_range(0..20)));
+            // Targets between 10^-40 and 10^40, with up to 40 significant digits.
+            let numerator = BigInt::from(rng.gen_range(1..=u64::MAX))
+                * BigInt::from(rng.gen_range(1..=u64::MAX))
+                * ten.pow(rng.gen_range(0..20));
+            let denominator =
+                BigInt::from(rng.gen_range(1..=u64::MAX)) * ten.pow(rng.gen_range(0..40));
+            let target = BigRational::new(numerator, denominator);
+            if let Ok(rate) = convert_with_max_error(&target, &epsilon) {
+                assert!(
+                    relative_error(&target, &rate) <= epsilon,
+                    "{}/{} is not within {} of {}",
+                    rate.numerator(),
+                    rate.denominator(),
+                    epsilon,
+                    target
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rational_from_decimal_str() {
+        let parse = |s| rational_from_decimal_str(s);
+        assert_eq!(parse("0.0123"), Some(BigRational::new(123.into(), 10000.into())));
+        assert_eq!(parse("12"), Some(BigRational::from_integer(12.into())));
+        assert_eq!(parse("-1.5"), Some(BigRational::new((-3).into(), 2.into())));
+        assert_eq!(parse("1.2e-3"), Some(BigRational::new(3.into(), 2500.into())));
+        assert_eq!(parse("5E2"), Some(BigRational::from_integer(500.into())));
+        assert_eq!(parse(".5"), Some(BigRational::new(1.into(), 2.into())));
+        // The exact decimal, unlike the binary fraction of the float:
+        assert_ne!(parse("0.1"), BigRational::from_float(0.1));
+        for invalid in ["", ".", "-", "abc", "1.2.3", "1e", "1e2.5", "0x10", "1 000", "NaN"] {
+            assert_eq!(parse(invalid), None, "{} should not parse", invalid);
+        }
+        // Huge exponents are rejected, rather than computing the power:
+        assert_eq!(parse("1e64"), Some(BigRational::from_integer(BigInt::from(10).pow(64))));
+        assert_eq!(parse("1e-64"), Some(BigRational::new(1.into(), BigInt::from(10).pow(64))));
+        for huge in ["1e65", "1e-65", "1e999999999", "-2.5E-999999999", "1e2147483647"] {
+            assert_eq!(parse(huge), None, "{} should not parse", huge);
+        }
+    }
+
+    #[test]
+    fn test_round_rational() {
+        let round =
+            |n: i64, d: i64, digits| round_rational(&BigRational::new(n.into(), d.into()), digits);
+        // Rounds toward the nearest value:
+        assert_eq!(round(12345, 1, 3), BigRational::from_integer(12300.into()));
+        assert_eq!(round(12351, 1, 3), BigRational::from_integer(12400.into()));
+        assert_eq!(round(1, 3, 2), BigRational::new(33.into(), 100.into()));
+        assert_eq!(round(2, 3, 2), BigRational::new(67.into(), 100.into()));
+        assert_eq!(round(9996, 100, 3), BigRational::from_integer(100.into()));
+        // Ties are rounded away from zero:
+        assert_eq!(round(12350, 1, 3), BigRational::from_integer(12400.into()));
+        assert_eq!(round(12345, 10000000, 4), BigRational::new(1235.into(), 1000000.into()));
+        assert_eq!(round(-12350, 1, 3), BigRational::from_integer((-12400).into()));
+        // Values with fewer digits are unchanged:
+        assert_eq!(round(125, 100, 3), BigRational::new(125.into(), 100.into()));
+        assert_eq!(round(0, 1, 3), BigRational::zero());
+    }
+
     #[test]
     fn test_compute_median() {
         let mut v = VecDeque::new();
@@ -147,10 +1083,98 @@ mod tests {
         v.push_back(BigRational::new(9u32.into(), 1u32.into()));
         v.push_back(BigRational::new(5u32.into(), 1u32.into()));
         v.push_back(BigRational::new(9u32.into(), 1u32.into()));
-        assert_eq!(compute_median(&v), Some(BigRational::new(7u32.into(), 1u32.into())))
+        assert_eq!(
+            compute_median(&v, MedianEvenPolicy::Average),
+            Some(BigRational::new(7u32.into(), 1u32.into()))
+        )
         // (5 + 9) / 2 = 7
     }
 
+    #[test]
+    fn test_median_even_policy() {
+        let v = VecDeque::from(vec![
+            BigRational::from_integer(9.into()),
+            BigRational::from_integer(2.into()),
+            BigRational::from_integer(7.into()),
+            BigRational::from_integer(4.into()),
+        ]);
+        assert_eq!(
+            compute_median(&v, MedianEvenPolicy::Average),
+            Some(BigRational::new(11.into(), 2.into()))
+        );
+        assert_eq!(
+            compute_median(&v, MedianEvenPolicy::Lower),
+            Some(BigRational::from_integer(4.into()))
+        );
+        assert_eq!(
+            compute_median(&v, MedianEvenPolicy::Higher),
+            Some(BigRational::from_integer(7.into()))
+        );
+        // The policy does not affect an odd number of rates:
+        let odd = v.iter().take(3).cloned().collect::<VecDeque<_>>();
+        for policy in [MedianEvenPolicy::Average, MedianEvenPolicy::Lower, MedianEvenPolicy::Higher]
+        {
+            assert_eq!(compute_median(&odd, policy), Some(BigRational::from_integer(7.into())));
+        }
+        assert_eq!(MedianEvenPolicy::from_str("lower").unwrap(), MedianEvenPolicy::Lower);
+        assert!(MedianEvenPolicy::from_str("middle").is_err());
+    }
+
+    #[test]
+    fn test_compute_median_of() {
+        // The median, computed by sorting all the rates.
+        let sorted_median = |rates: &[BigRational], policy: MedianEvenPolicy| {
+            let mut sorted = rates.to_vec();
+            sorted.sort();
+            let len = sorted.len();
+            if len == 0 {
+                None
+            } else if len % 2 == 1 {
+                Some(sorted[len / 2].clone())
+            } else {
+                let (lower, upper) = (&sorted[len / 2 - 1], &sorted[len / 2]);
+                Some(match policy {
+                    MedianEvenPolicy::Average => {
+                        (lower + upper) / BigRational::from_integer(2.into())
+                    }
+                    MedianEvenPolicy::Lower => lower.clone(),
+                    MedianEvenPolicy::Higher => upper.clone(),
+                })
+            }
+        };
+        let rates =
+            (0..25).map(|n| BigRational::new((n * 7 % 11).into(), 3.into())).collect::<Vec<_>>();
+        let mut scratch = Vec::with_capacity(rates.len());
+        for policy in [MedianEvenPolicy::Average, MedianEvenPolicy::Lower, MedianEvenPolicy::Higher]
+        {
+            for len in 0..rates.len() {
+                scratch.clear();
+                scratch.extend(rates[..len].iter());
+                assert_eq!(
+                    compute_median_of(&mut scratch, policy),
+                    sorted_median(&rates[..len], policy)
+                );
+            }
+        }
+
+        let rational = |n: i64, d: i64| BigRational::new(n.into(), d.into());
+        let odd = [rational(3, 1), rational(1, 1), rational(2, 1)];
+        let even = [rational(4, 1), rational(1, 1), rational(3, 1), rational(2, 1)];
+        for policy in [MedianEvenPolicy::Average, MedianEvenPolicy::Lower, MedianEvenPolicy::Higher]
+        {
+            assert_eq!(
+                compute_median_of(&mut odd.iter().collect::<Vec<_>>(), policy),
+                Some(rational(2, 1))
+            );
+        }
+        let median_of_even =
+            |policy| compute_median_of(&mut even.iter().collect::<Vec<_>>(), policy);
+        assert_eq!(median_of_even(MedianEvenPolicy::Average), Some(rational(5, 2)));
+        assert_eq!(median_of_even(MedianEvenPolicy::Lower), Some(rational(2, 1)));
+        assert_eq!(median_of_even(MedianEvenPolicy::Higher), Some(rational(3, 1)));
+        assert_eq!(compute_median_of(&mut [], MedianEvenPolicy::Average), None);
+    }
+
     #[test]
     fn test_compute_median_floats_1() {
         let median_part = BigRational::from_float(0.03878333).unwrap();
@@ -165,7 +1189,11 @@ mod tests {
         v.push_back(BigRational::from_float(0.03878333));
         v.push_back(BigRational::from_float(0.03878333));
         v.push_back(BigRational::from_float(0.03893119));
-        match v.into_iter().collect::<Option<VecDeque<_>>>().and_then(|rm| compute_median(&rm)) {
+        match v
+            .into_iter()
+            .collect::<Option<VecDeque<_>>>()
+            .and_then(|rm| compute_median(&rm, MedianEvenPolicy::Average))
+        {
             Some(v) => assert_eq!(
                 v,
                 (median_part.clone() + median_part) / BigRational::from_integer(2.into())
@@ -189,7 +1217,11 @@ mod tests {
         v.push_back(BigRational::from_float(0.03871499568024753));
         v.push_back(BigRational::from_float(0.03878131780389962));
         v.push_back(BigRational::from_float(0.03882990048880441));
-        match v.into_iter().collect::<Option<VecDeque<_>>>().and_then(|rm| compute_median(&rm)) {
+        match v
+            .into_iter()
+            .collect::<Option<VecDeque<_>>>()
+            .and_then(|rm| compute_median(&rm, MedianEvenPolicy::Average))
+        {
             Some(v) => {
                 assert_eq!(v, (median_part_1 + median_part_2) / BigRational::from_integer(2.into()))
             }
@@ -212,7 +1244,11 @@ mod tests {
         v.push_back(BigRational::from_float(0.0382987008046979));
         v.push_back(BigRational::from_float(0.03829543671546038));
         v.push_back(BigRational::from_float(0.03838764088740058));
-        match v.into_iter().collect::<Option<VecDeque<_>>>().and_then(|rm| compute_median(&rm)) {
+        match v
+            .into_iter()
+            .collect::<Option<VecDeque<_>>>()
+            .and_then(|rm| compute_median(&rm, MedianEvenPolicy::Average))
+        {
             Some(v) => {
                 assert_eq!(v, (median_part_1 + median_part_2) / BigRational::from_integer(2.into()))
             }
@@ -234,7 +1270,7 @@ mod tests {
         v.push_back(median_1);
         v.push_back(median_2.clone());
         v.push_back(median_3);
-        assert_eq!(compute_median(&v), Some(median_2))
+        assert_eq!(compute_median(&v, MedianEvenPolicy::Average), Some(median_2))
     }
 
     #[test]
@@ -244,7 +1280,10 @@ mod tests {
         v.push_back(BigRational::new(100u32.into(), 9u32.into()));
         v.push_back(BigRational::new(1u32.into(), 12u32.into()));
         v.push_back(BigRational::new(1u32.into(), 100u32.into()));
-        assert_eq!(compute_median(&v), Some(BigRational::new(403u32.into(), 72u32.into())))
+        assert_eq!(
+            compute_median(&v, MedianEvenPolicy::Average),
+            Some(BigRational::new(403u32.into(), 72u32.into()))
+        )
     }
 
     #[test]
@@ -255,19 +1294,360 @@ mod tests {
         v.push_back(BigRational::new(100u32.into(), 9u32.into()));
         v.push_back(BigRational::new(1u32.into(), 12u32.into()));
         v.push_back(BigRational::new(1u32.into(), 100u32.into()));
-        assert_eq!(compute_median(&v), Some(BigRational::new(100u32.into(), 9u32.into())))
+        assert_eq!(
+            compute_median(&v, MedianEvenPolicy::Average),
+            Some(BigRational::new(100u32.into(), 9u32.into()))
+        )
+    }
+
+    fn fixture_odd() -> VecDeque<BigRational> {
+        let mut v = VecDeque::new();
+        v.push_back(BigRational::new(20000.into(), 1.into()));
+        v.push_back(BigRational::new(20.into(), 1.into()));
+        v.push_back(BigRational::new(100u32.into(), 9u32.into()));
+        v.push_back(BigRational::new(1u32.into(), 12u32.into()));
+        v.push_back(BigRational::new(1u32.into(), 100u32.into()));
+        v
+    }
+
+    fn fixture_even() -> VecDeque<BigRational> {
+        let mut v = VecDeque::new();
+        v.push_back(BigRational::new(1.into(), 1.into()));
+        v.push_back(BigRational::new(9u32.into(), 1u32.into()));
+        v.push_back(BigRational::new(5u32.into(), 1u32.into()));
+        v.push_back(BigRational::new(9u32.into(), 1u32.into()));
+        v
+    }
+
+    #[test]
+    fn test_aggregation_median() {
+        let aggregation = Aggregation::from_str("median").unwrap();
+        assert_eq!(
+            aggregation.aggregate(&fixture_odd(), MedianEvenPolicy::Average),
+            Some(BigRational::new(100u32.into(), 9u32.into()))
+        );
+        assert_eq!(
+            aggregation.aggregate(&fixture_even(), MedianEvenPolicy::Average),
+            Some(BigRational::new(7u32.into(), 1u32.into()))
+        );
+        assert_eq!(aggregation.aggregate(&VecDeque::new(), MedianEvenPolicy::Average), None);
+    }
+
+    #[test]
+    fn test_aggregation_mean() {
+        let aggregation = Aggregation::from_str("mean").unwrap();
+        // (20000 + 20 + 100/9 + 1/12 + 1/100) / 5
+        assert_eq!(
+            aggregation.aggregate(&fixture_odd(), MedianEvenPolicy::Average),
+            Some(BigRational::new(4507021u32.into(), 1125u32.into()))
+        );
+        assert_eq!(
+            aggregation.aggregate(&fixture_even(), MedianEvenPolicy::Average),
+            Some(BigRational::new(6u32.into(), 1u32.into()))
+        );
+        assert_eq!(aggregation.aggregate(&VecDeque::new(), MedianEvenPolicy::Average), None);
+    }
+
+    #[test]
+    fn test_aggregation_trimmed_mean() {
+        let aggregation = Aggregation::from_str("trimmed-mean:25").unwrap();
+        assert_eq!(aggregation, Aggregation::TrimmedMean(25));
+        // Discards 1 of each end: (20 + 100/9 + 1/12) / 3
+        assert_eq!(
+            aggregation.aggregate(&fixture_odd(), MedianEvenPolicy::Average),
+            Some(BigRational::new(1123u32.into(), 108u32.into()))
+        );
+        // Discards 1 of each end: (5 + 9) / 2
+        assert_eq!(
+            aggregation.aggregate(&fixture_even(), MedianEvenPolicy::Average),
+            Some(BigRational::new(7u32.into(), 1u32.into()))
+        );
+        assert_eq!(aggregation.aggregate(&VecDeque::new(), MedianEvenPolicy::Average), None);
+        // Discards nothing, when the percentage is below one rate.
+        assert_eq!(
+            Aggregation::TrimmedMean(10).aggregate(&fixture_even(), MedianEvenPolicy::Average),
+            Some(BigRational::new(6u32.into(), 1u32.into()))
+        );
+    }
+
+    #[test]
+    fn test_aggregation_mad_filtered_median() {
+        let aggregation = Aggregation::from_str("mad-median:3").unwrap();
+        assert_eq!(aggregation, Aggregation::MadFilteredMedian(3));
+        assert_eq!(aggregation.to_string(), "mad-median:3");
+        // Median 5 and MAD 1, so rates outside [2, 8] are discarded.
+        assert_eq!(
+            aggregation.aggregate(&rates(&[1, 4, 5, 6, 100]), MedianEvenPolicy::Average),
+            Some(BigRational::from_integer(5.into()))
+        );
+        // Median 6.5 and MAD 2, so rates outside [0.5, 12.5] are discarded.
+        assert_eq!(
+            aggregation.aggregate(&rates(&[4, 5, 6, 7, 100, 200]), MedianEvenPolicy::Average),
+            Some(BigRational::new(11.into(), 2.into()))
+        );
+        // The MAD is 0, so only the rates equal to the median are kept.
+        assert_eq!(
+            aggregation.aggregate(&rates(&[5, 5, 5, 9]), MedianEvenPolicy::Average),
+            Some(BigRational::from_integer(5.into()))
+        );
+        assert_eq!(aggregation.aggregate(&VecDeque::new(), MedianEvenPolicy::Average), None);
+    }
+
+    #[test]
+    fn test_ewma_step() {
+        let prev = BigRational::from_integer(10.into());
+        let new = BigRational::from_integer(20.into());
+        let alpha = BigRational::new(1.into(), 4.into());
+        assert_eq!(ewma_step(&prev, &new, &alpha), BigRational::new(25.into(), 2.into()));
+        assert_eq!(ewma_step(&prev, &new, &BigRational::one()), new);
+        assert_eq!(ewma_step(&prev, &new, &BigRational::zero()), prev);
+    }
+
+    #[test]
+    fn test_compute_weighted_median_equal_weights() {
+        for fixture in [fixture_odd(), fixture_even()] {
+            let weighted = fixture.iter().map(|rate| (rate.clone(), 3)).collect::<Vec<_>>();
+            assert_eq!(
+                compute_weighted_median(&weighted, MedianEvenPolicy::Average),
+                compute_median(&fixture, MedianEvenPolicy::Average)
+            );
+        }
+        assert_eq!(compute_weighted_median(&[], MedianEvenPolicy::Average), None);
+        assert_eq!(
+            compute_weighted_median(
+                &[(BigRational::from_integer(1.into()), 0)],
+                MedianEvenPolicy::Average
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_compute_weighted_median_skewed_weights() {
+        let rates = vec![
+            (BigRational::from_integer(1.into()), 1),
+            (BigRational::from_integer(5.into()), 1),
+            (BigRational::from_integer(9.into()), 3),
+        ];
+        assert_eq!(
+            compute_weighted_median(&rates, MedianEvenPolicy::Average),
+            Some(BigRational::from_integer(9.into()))
+        );
+        let rates = vec![
+            (BigRational::from_integer(1.into()), 4),
+            (BigRational::from_integer(5.into()), 1),
+            (BigRational::from_integer(9.into()), 3),
+        ];
+        // The lowest rate has exactly half of the weight.
+        assert_eq!(
+            compute_weighted_median(&rates, MedianEvenPolicy::Average),
+            Some(BigRational::from_integer(3.into()))
+        );
+        let rates = vec![
+            (BigRational::from_integer(1.into()), 4),
+            (BigRational::from_integer(5.into()), 0),
+            (BigRational::from_integer(9.into()), 3),
+        ];
+        assert_eq!(
+            compute_weighted_median(&rates, MedianEvenPolicy::Average),
+            Some(BigRational::from_integer(1.into()))
+        );
+    }
+
+    #[test]
+    fn test_aggregate_weighted() {
+        let rates = vec![
+            (BigRational::from_integer(1.into()), 1),
+            (BigRational::from_integer(5.into()), 1),
+            (BigRational::from_integer(9.into()), 3),
+        ];
+        assert_eq!(
+            Aggregation::WeightedMedian.aggregate_weighted(&rates, MedianEvenPolicy::Average),
+            Some(BigRational::from_integer(9.into()))
+        );
+        assert_eq!(
+            Aggregation::Median.aggregate_weighted(&rates, MedianEvenPolicy::Average),
+            Some(BigRational::from_integer(5.into()))
+        );
+    }
+
+    fn rates(values: &[i64]) -> VecDeque<BigRational> {
+        values.iter().map(|value| BigRational::from_integer((*value).into())).collect()
+    }
+
+    #[test]
+    fn test_filter_iqr() {
+        let multiplier = BigRational::new(3.into(), 2.into());
+        // Q1 = 2.5, Q3 = 6.5, so rates outside [-3.5, 12.5] are removed.
+        assert_eq!(
+            filter_iqr(&rates(&[100, 1, 2, 3, 4, 5, 6, 7]), multiplier.clone()),
+            rates(&[1, 2, 3, 4, 5, 6, 7])
+        );
+        // The median is in neither half: Q1 = 1.5, Q3 = 6.5, so rates outside
+        // [-6, 14] are removed.
+        assert_eq!(
+            filter_iqr(&rates(&[3, -20, 1, 2, 4, 30, 5, 6, 7]), multiplier.clone()),
+            rates(&[3, 1, 2, 4, 5, 6, 7])
+        );
+        assert_eq!(
+            filter_iqr(&rates(&[1, 2, 3, 4, 5, 6, 7, 100]), BigRational::zero()),
+            rates(&[3, 4, 5, 6])
+        );
+    }
+
+    #[test]
+    fn test_filter_iqr_degenerate() {
+        let multiplier = BigRational::new(3.into(), 2.into());
+        let cases: [&[i64]; 4] = [&[], &[1], &[1, 100], &[1, 2, 1000]];
+        for values in cases {
+            assert_eq!(filter_iqr(&rates(values), multiplier.clone()), rates(values));
+        }
+        let equal = rates(&[5, 5, 5, 5, 5]);
+        assert_eq!(filter_iqr(&equal, multiplier.clone()), equal);
+        assert_eq!(filter_iqr(&equal, BigRational::zero()), equal);
+    }
+
+    #[test]
+    fn test_parse_outlier_filter() {
+        assert_eq!(OutlierFilter::from_str("none").unwrap(), OutlierFilter::None);
+        assert_eq!(
+            OutlierFilter::from_str("iqr:1.5").unwrap(),
+            OutlierFilter::Iqr(BigRational::new(3.into(), 2.into()))
+        );
+        assert_eq!(OutlierFilter::from_str("iqr:1.5").unwrap().to_string(), "iqr:1.5");
+        assert!(OutlierFilter::from_str("iqr:-1").is_err());
+        assert!(OutlierFilter::from_str("iqr").is_err());
+        assert!(OutlierFilter::from_str("mad").is_err());
+    }
+
+    #[test]
+    fn test_parse_threshold_baseline() {
+        for baseline in [ThresholdBaseline::LastUpdate, ThresholdBaseline::Trailing24h] {
+            assert_eq!(ThresholdBaseline::from_str(&baseline.to_string()).unwrap(), baseline);
+        }
+        assert_eq!(
+            ThresholdBaseline::from_str("trailing-24h").unwrap(),
+            ThresholdBaseline::Trailing24h
+        );
+        assert!(ThresholdBaseline::from_str("trailing").is_err());
+    }
+
+    #[test]
+    fn test_parse_halt_action() {
+        for action in [HaltAction::Protect, HaltAction::Skip] {
+            assert_eq!(HaltAction::from_str(&action.to_string()).unwrap(), action);
+        }
+        assert!(HaltAction::from_str("halt").is_err());
+    }
+
+    #[test]
+    fn test_parse_aggregation_invalid() {
+        assert!(Aggregation::from_str("mode").is_err());
+        assert!(Aggregation::from_str("trimmed-mean").is_err());
+        assert!(Aggregation::from_str("trimmed-mean:50").is_err());
+        assert!(Aggregation::from_str("trimmed-mean:ten").is_err());
+        assert!(Aggregation::from_str("mad-median").is_err());
+        assert!(Aggregation::from_str("mad-median:0").is_err());
+    }
+
+    #[test]
+    fn test_differs_by_factor() {
+        let rate = BigRational::new(1.into(), 50.into());
+        assert!(!differs_by_factor(&rate, &rate, 100));
+        assert!(!differs_by_factor(&rate, &BigRational::from_integer(2.into()), 100));
+        assert!(differs_by_factor(&rate, &BigRational::from_integer(3.into()), 100));
+        assert!(differs_by_factor(&BigRational::from_integer(50.into()), &rate, 100));
+    }
+
+    #[test]
+    fn test_deviating_rates() {
+        let rate = BigRational::new(1.into(), 50.into());
+        let inverted = BigRational::from_integer(50.into());
+        // With two sources, it is unknown which one is inverted, so none is flagged:
+        assert_eq!(deviating_rates(&[&rate, &inverted], 100), None);
+        assert_eq!(deviating_rates(&[&inverted, &rate], 100), None);
+        // With more sources, only the inverted one is flagged, whichever the order:
+        let other = BigRational::new(1.into(), 49.into());
+        let flags = |rates: &[&BigRational]| {
+            deviating_rates(rates, 100)
+                .unwrap()
+                .into_iter()
+                .map(|(deviates, _)| deviates)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(flags(&[&rate, &inverted, &other]), vec![false, true, false]);
+        assert_eq!(flags(&[&inverted, &rate, &other]), vec![true, false, false]);
+        // Agreeing sources are kept, and compared to the median of the others:
+        let deviations = deviating_rates(&[&rate, &other, &rate], 100).unwrap();
+        assert!(deviations.iter().all(|(deviates, _)| !deviates));
+        assert_eq!(deviations[1].1, rate);
+    }
+
+    #[test]
+    fn test_relative_spread() {
+        let rates = [
+            BigRational::new(45.into(), 1000.into()),
+            BigRational::new(31.into(), 1000.into()),
+            BigRational::new(40.into(), 1000.into()),
+        ];
+        assert_eq!(relative_spread(rates.iter()), Some(BigRational::new(1400.into(), 31.into())));
+        assert_eq!(relative_spread(rates[..1].iter()), Some(BigRational::zero()));
+        assert_eq!(relative_spread([].iter()), None);
+        assert_eq!(relative_spread([BigRational::zero()].iter()), None);
+    }
+
+    #[test]
+    fn test_spread_ratio() {
+        let rates = [
+            BigRational::new(45.into(), 1000.into()),
+            BigRational::new(31.into(), 1000.into()),
+            BigRational::new(40.into(), 1000.into()),
+        ];
+        assert_eq!(spread_ratio(rates.iter()), Some(BigRational::new(14.into(), 40.into())));
+        assert_eq!(spread_ratio(rates[..1].iter()), Some(BigRational::zero()));
+        assert_eq!(spread_ratio([].iter()), None);
+        assert_eq!(spread_ratio([BigRational::zero()].iter()), None);
+    }
+
+    #[test]
+    fn test_count_agreeing() {
+        let rate = |thousandths: i64| BigRational::new(thousandths.into(), 1000.into());
+        let tolerance = BigRational::from_integer(2.into());
+        // The median is 50, and 49 and 51 are within 2 % of it.
+        let rates = [rate(50), rate(49), rate(51), rate(60), rate(10)];
+        assert_eq!(count_agreeing(rates.iter(), &tolerance), 3);
+        // A single compromised source does not agree with the others.
+        let rates = [rate(50), rate(50), rate(100)];
+        assert_eq!(count_agreeing(rates.iter(), &tolerance), 2);
+        // With two sources disagreeing, the median is their mean, so none agree.
+        let rates = [rate(40), rate(60)];
+        assert_eq!(count_agreeing(rates.iter(), &tolerance), 0);
+        assert_eq!(count_agreeing(rates.iter(), &BigRational::from_integer(20.into())), 2);
+        assert_eq!(count_agreeing([rate(50)].iter(), &tolerance), 1);
+        assert_eq!(count_agreeing([].iter(), &tolerance), 0);
+    }
+
+    #[test]
+    fn test_select_by_tier() {
+        let items = vec![(2, "b1"), (1, "a1"), (3, "c1"), (1, "a2"), (2, "b2")];
+        assert_eq!(select_by_tier(items.clone(), 1), vec!["a1", "a2"]);
+        assert_eq!(select_by_tier(items.clone(), 2), vec!["a1", "a2"]);
+        assert_eq!(select_by_tier(items.clone(), 3), vec!["a1", "a2", "b1", "b2"]);
+        assert_eq!(select_by_tier(items.clone(), 5), vec!["a1", "a2", "b1", "b2", "c1"]);
+        assert_eq!(select_by_tier(items, 6), vec!["a1", "a2", "b1", "b2", "c1"]);
     }
 
     fn test_convert_u64(num: u64, den: u64) {
         let result =
-            convert_big_fraction_to_exchange_rate(&BigRational::new(num.into(), den.into()));
+            convert_big_fraction_to_exchange_rate(&BigRational::new(num.into(), den.into()))
+                .unwrap();
         assert_eq!(num, result.numerator());
         assert_eq!(den, result.denominator());
     }
 
     fn test_convert_u128(num: u128, den: u128) {
         let input = BigRational::new(num.into(), den.into());
-        let result = convert_big_fraction_to_exchange_rate(&input);
+        let result = convert_big_fraction_to_exchange_rate(&input).unwrap();
         assert!(
             (input - BigRational::new(result.numerator().into(), result.denominator().into()))
                 .abs()
@@ -312,4 +1692,103 @@ mod tests {
     fn test_convert_128_4() {
         test_convert_u128(96961673726254741664712289u128, 64926407910777421824u128);
     }
+
+    #[test]
+    fn test_convert_out_of_range() {
+        let huge = BigRational::new(BigInt::from(u128::MAX), 3.into());
+        let result = convert_big_fraction_to_exchange_rate(&huge).unwrap();
+        assert_eq!((result.numerator(), result.denominator()), (u64::MAX, 1));
+
+        let small = BigRational::new(5.into(), BigInt::from(u64::MAX) * 2 + 1);
+        let result = convert_big_fraction_to_exchange_rate(&small).unwrap();
+        assert_eq!((result.numerator(), result.denominator()), (1, 7378697629483820646));
+    }
+
+    #[test]
+    fn test_convert_many_terms() {
+        // The continued fraction of a ratio of consecutive Fibonacci numbers only
+        // has terms equal to 1, so it requires the most terms to approximate.
+        let mut fibonacci = vec![BigInt::zero(), BigInt::one()];
+        for n in 2..=200 {
+            let next = &fibonacci[n - 1] + &fibonacci[n - 2];
+            fibonacci.push(next);
+        }
+        let ratio = BigRational::new(fibonacci[200].clone(), fibonacci[199].clone());
+        let result = convert_big_fraction_to_exchange_rate(&ratio).unwrap();
+        assert_eq!(
+            (result.numerator(), result.denominator()),
+            (fibonacci[93].to_u64().unwrap(), fibonacci[92].to_u64().unwrap())
+        );
+        assert!(
+            relative_error(&ratio, &result) < BigRational::new(1.into(), BigInt::from(10).pow(36))
+        );
+        // When the terms are exhausted, the last convergent is used.
+        assert_eq!(approximate_in_u64(&ratio, 5), (8, 5));
+    }
+
+    #[test]
+    fn test_convert_degenerate() {
+        let zero = BigRational::zero();
+        assert!(convert_big_fraction_to_exchange_rate(&zero).is_err());
+        let negative = BigRational::new((-1).into(), 3.into());
+        assert!(convert_big_fraction_to_exchange_rate(&negative).is_err());
+        let negative_denominator = BigRational::new(1.into(), (-3).into());
+        assert!(convert_big_fraction_to_exchange_rate(&negative_denominator).is_err());
+        // Closer to 0 than to 1 / u64::MAX.
+        let tiny = BigRational::new(1.into(), BigInt::from(u128::MAX));
+        assert!(convert_big_fraction_to_exchange_rate(&tiny).is_err());
+        // Closer to 1 / u64::MAX than to 0.
+        let smallest = BigRational::new(2.into(), BigInt::from(u64::MAX) * 3);
+        let result = convert_big_fraction_to_exchange_rate(&smallest).unwrap();
+        assert_eq!((result.numerator(), result.denominator()), (1, u64::MAX));
+    }
+
+    /// The previous conversion, which halves the numerator and denominator
+    /// until both fit.
+    fn convert_by_halving(target: &BigRational) -> (BigInt, BigInt) {
+        let mut numerator: BigInt = target.numer().clone();
+        let mut denominator: BigInt = target.denom().clone();
+        while numerator.to_u64().is_none() || denominator.to_u64().is_none() {
+            numerator /= 2;
+            denominator /= 2;
+            let gcd = numerator.gcd(&denominator);
+            if gcd > 1.into() {
+                numerator /= &gcd;
+                denominator /= gcd;
+            }
+        }
+        (numerator, denominator)
+    }
+
+    #[test]
+    fn test_convert_not_worse_than_halving() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            // Vary the magnitudes, so both the numerator and the denominator are
+            // sometimes the larger one.
+            let num = rng.gen::<u128>() >> rng.gen_range(0..64) | 1;
+            let den = rng.gen::<u128>() >> rng.gen_range(0..64) | 1;
+            let input = BigRational::new(num.into(), den.into());
+            let result = match convert_big_fraction_to_exchange_rate(&input) {
+                Ok(result) => result,
+                Err(_) => {
+                    // Only rates closer to 0 than to 1 / u64::MAX cannot be converted.
+                    assert!(input < BigRational::new(1.into(), BigInt::from(u64::MAX) * 2));
+                    continue;
+                }
+            };
+            let error = (&input
+                - BigRational::new(result.numerator().into(), result.denominator().into()))
+            .abs();
+            let (old_num, old_den) = convert_by_halving(&input);
+            if old_den.is_zero() {
+                // The old conversion did not produce a valid rate at all.
+                continue;
+            }
+            let old_error = (&input - BigRational::new(old_num, old_den)).abs();
+            assert!(error <= old_error, "Worse conversion of {}/{}", num, den);
+        }
+    }
 }
@@ -1,29 +1,66 @@
+mod admin;
 mod config;
 mod database;
 mod helpers;
 mod node;
 mod prometheus;
+mod protected;
 mod secretsmanager;
 mod sources;
 
+use admin::report_outcome;
 use anyhow::{ensure, Context};
 use clap::AppSettings;
-use concordium_rust_sdk::v2::{self, ChainParameters};
-use config::MAX_TIME_CHECK_SUBMISSION;
-use helpers::{compute_median, convert_big_fraction_to_exchange_rate, relative_change};
-use node::{check_update_status, get_node_client, send_update};
+use concordium_rust_sdk::{
+    types::{ExchangeRate, UpdateKeyPair, UpdateKeysIndex},
+    v2::{self, ChainParameters},
+};
+use config::{
+    CANARY_MAD_MULTIPLIER, CANARY_TRIMMED_MEAN_PERCENT, DAILY_CHANGE_WINDOW,
+    DEFAULT_MAX_UPDATE_GAP_INTERVALS, EXIT_CODE_CONSECUTIVE_FAILURES, MAX_INCREASE_THRESHOLD,
+    MAX_SOURCE_DEVIATION_FACTOR, SOURCE_PROBE_TIMEOUT, TRAILING_BASELINE_WINDOW,
+    WARMUP_POLL_INTERVAL,
+};
+use database::{ProtectionEvent, TablePrefix};
+use futures_util::future::join_all;
+use helpers::{
+    abs_relative_change, clamp_change, compute_median, consecutive_changes,
+    convert_big_fraction_to_exchange_rate, convert_with_max_error, count_agreeing, deviating_rates,
+    ewma_step, relative_change, relative_error, relative_spread, round_rational, select_by_tier,
+    spread_ratio, Aggregation, HaltAction, MedianEvenPolicy, OutlierFilter, ThresholdBaseline,
+    MIN_RATES_FOR_DEVIATION_CHECK,
+};
+use node::{
+    check_clock_skew, check_update_status, connect_all, connect_with_backoff, micro_ccd_per_euro,
+    send_update, simulate_update, SubmissionSettings,
+};
 use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use protected::{
+    check_writable, clear_forced_dry_run, force_dry_run, is_dry_run_forced, read_protected_state,
+    ProtectedState,
+};
 use reqwest::Url;
 use secretsmanager::{get_governance_from_aws, get_governance_from_file};
-use sources::{pull_exchange_rate, RateHistory, Source};
+use sources::{
+    get_client, get_client_with_ca_certificates, lock_history, probe_source, pull_exchange_rate,
+    read_sources_file, DerivedSource, GenericSource, HistoryLimit, MaxRatesSaved, ProxyConfig,
+    RateHistory, Source, SourceCaCertificate, SourceHandle, SourceHeader, SourceTier, SourceWeight,
+};
 use std::{
-    collections::VecDeque,
-    fs::File,
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 use structopt::StructOpt;
-use tokio::time::{interval_at, timeout, Duration, Instant};
+use tokio::{
+    signal::unix::{signal, Signal, SignalKind},
+    task::JoinHandle,
+    time::{interval_at, sleep, sleep_until, timeout, Duration, Instant},
+};
 
 #[derive(StructOpt, Debug)]
 struct App {
@@ -58,6 +95,36 @@ struct App {
         default_value = "1800"
     )]
     update_interval:            u32,
+    #[structopt(
+        long = "max-update-gap",
+        help = "Maximum time without a finalized update, while not in dry run, after which the \
+                update is considered overdue. Defaults to 3 times the update interval. (In \
+                seconds)",
+        env = "EUR2CCD_SERVICE_MAX_UPDATE_GAP"
+    )]
+    max_update_gap:             Option<u64>,
+    #[structopt(
+        long = "exit-on-overdue",
+        help = "Exit with an error, when the update is overdue, so the service can be restarted.",
+        env = "EUR2CCD_SERVICE_EXIT_ON_OVERDUE"
+    )]
+    exit_on_overdue:            bool,
+    #[structopt(
+        long = "max-consecutive-failures",
+        help = "If given, the service exits with code 3, after this number of consecutive update \
+                cycles, in which no rate could be computed, or the update could not be submitted \
+                or confirmed.",
+        env = "EUR2CCD_SERVICE_MAX_CONSECUTIVE_FAILURES"
+    )]
+    max_consecutive_failures:   Option<u32>,
+    #[structopt(
+        long = "min-update-spacing",
+        help = "Minimum time from when the last update was finalized, until the update cycle of \
+                the next update. Update cycles starting sooner are skipped. Defaults to half the \
+                update interval. (In seconds)",
+        env = "EUR2CCD_SERVICE_MIN_UPDATE_SPACING"
+    )]
+    min_update_spacing:         Option<u64>,
     #[structopt(
         long = "pull-interval",
         help = "How often to pull new exchange rate from each source. (In seconds)",
@@ -65,6 +132,20 @@ struct App {
         default_value = "60"
     )]
     pull_interval:              u32,
+    #[structopt(
+        long = "pull-jitter",
+        help = "Maximum random delay of each pull from a source, to avoid pulling from all \
+                sources at the same time. (In percentage of the pull interval)",
+        env = "EUR2CCD_SERVICE_PULL_JITTER",
+        default_value = "10"
+    )]
+    pull_jitter:                u8,
+    #[structopt(
+        long = "no-jitter",
+        help = "Disables the random delay of pulls from sources.",
+        env = "EUR2CCD_SERVICE_NO_JITTER"
+    )]
+    no_jitter:                  bool,
     #[structopt(
         long = "log-level",
         default_value = "info",
@@ -72,6 +153,14 @@ struct App {
         env = "EUR2CCD_SERVICE_LOG_LEVEL"
     )]
     log_level:                  log::LevelFilter,
+    #[structopt(
+        long = "log-filter",
+        help = "Log filter in the format of `RUST_LOG`, e.g. \
+                \"concordium_eur2ccd=info,concordium_eur2ccd::sources=debug\", for setting the \
+                log level per module. Takes precedence over `log-level`.",
+        env = "EUR2CCD_SERVICE_LOG_FILTER"
+    )]
+    log_filter:                 Option<String>,
     #[structopt(
         long = "warning-increase-threshold",
         default_value = "30",
@@ -104,6 +193,149 @@ struct App {
         env = "EUR2CCD_SERVICE_HALT_DECREASE_THRESHOLD"
     )]
     halt_decrease_threshold:    u8,
+    #[structopt(
+        long = "halt-action",
+        default_value = "protect",
+        help = "What to do, when an update violates a halt threshold. One of `protect`, which \
+                forces the service into dry run, and `skip`, which skips the update.",
+        env = "EUR2CCD_SERVICE_HALT_ACTION"
+    )]
+    halt_action:                HaltAction,
+    #[structopt(
+        long = "halt-after-consecutive",
+        default_value = "1",
+        help = "Number of consecutive update cycles violating a halt threshold, after which the \
+                service is forced into dry run. Updates violating a halt threshold are skipped \
+                before that.",
+        env = "EUR2CCD_SERVICE_HALT_AFTER_CONSECUTIVE"
+    )]
+    halt_after_consecutive:     u32,
+    #[structopt(
+        long = "threshold-baseline",
+        default_value = "last-update",
+        help = "What the warning and halt thresholds are checked against. One of `last-update` \
+                and `trailing-24h`, which is the median of the updates in the last 24 hours, read \
+                from the database, if given.",
+        env = "EUR2CCD_SERVICE_THRESHOLD_BASELINE"
+    )]
+    threshold_baseline:         ThresholdBaseline,
+    #[structopt(
+        long = "min-change-threshold",
+        default_value = "0",
+        help = "Determines the threshold where an update changing the exchange rate less than it \
+                is skipped (specified in percentage)",
+        env = "EUR2CCD_SERVICE_MIN_CHANGE_THRESHOLD"
+    )]
+    min_change_threshold:       f64,
+    #[structopt(
+        long = "heartbeat-updates",
+        help = "If given, an update is forced after this number of consecutive updates skipped, \
+                because the change was below `min-change-threshold`, or the rate was identical to \
+                the rate on chain.",
+        env = "EUR2CCD_SERVICE_HEARTBEAT_UPDATES"
+    )]
+    heartbeat_updates:          Option<u32>,
+    #[structopt(
+        long = "clamp-change",
+        help = "If given, updates changing the exchange rate more than this are clamped to this \
+                change, instead of being submitted as is (specified in percentage, between 0 and \
+                100). The halt thresholds are checked against the clamped rate.",
+        env = "EUR2CCD_SERVICE_CLAMP_CHANGE"
+    )]
+    clamp_change:               Option<f64>,
+    #[structopt(
+        long = "max-daily-change",
+        help = "If given, updates are skipped, when the sum of the changes of the updates in the \
+                last 24 hours would exceed this, in either direction (specified in percentage). \
+                If `clamp-change` is given, the updates are limited to this instead.",
+        env = "EUR2CCD_SERVICE_MAX_DAILY_CHANGE"
+    )]
+    max_daily_change:           Option<f64>,
+    #[structopt(
+        long = "max-source-spread",
+        help = "If given, updates are skipped, when the relative spread between the lowest and \
+                the highest median of the used sources exceeds this (specified in percentage).",
+        env = "EUR2CCD_SERVICE_MAX_SOURCE_SPREAD"
+    )]
+    max_source_spread:          Option<f64>,
+    #[structopt(
+        long = "halt-source-spread",
+        help = "If given, protected mode is engaged, when the relative spread between the lowest \
+                and the highest median of the used sources exceeds this (specified in \
+                percentage). Must be higher than `max-source-spread`.",
+        env = "EUR2CCD_SERVICE_HALT_SOURCE_SPREAD"
+    )]
+    halt_source_spread:         Option<f64>,
+    #[structopt(
+        long = "min-agreeing-sources",
+        help = "If given, updates are skipped, when fewer than this number of the used sources \
+                have a median within `agreement-tolerance` of the median of all of them.",
+        env = "EUR2CCD_SERVICE_MIN_AGREEING_SOURCES"
+    )]
+    min_agreeing_sources:       Option<usize>,
+    #[structopt(
+        long = "agreement-tolerance",
+        help = "How far the median of a source may be from the median of all used sources, for \
+                the source to agree (specified in percentage).",
+        env = "EUR2CCD_SERVICE_AGREEMENT_TOLERANCE",
+        default_value = "1"
+    )]
+    agreement_tolerance:        f64,
+    #[structopt(
+        long = "rate-precision",
+        help = "If given, the rate is rounded to this number of significant decimal digits, \
+                before it is checked against the thresholds and submitted.",
+        env = "EUR2CCD_SERVICE_RATE_PRECISION"
+    )]
+    rate_precision:             Option<u32>,
+    #[structopt(
+        long = "max-conversion-error",
+        help = "If given, an update is skipped, if the submitted fraction would deviate from the \
+                rate by more than this relative error, e.g. 1e-9.",
+        env = "EUR2CCD_SERVICE_MAX_CONVERSION_ERROR"
+    )]
+    max_conversion_error:       Option<f64>,
+    #[structopt(
+        long = "min-rate-eur",
+        help = "If given, an update is not submitted, and protected mode is engaged, if it would \
+                value a CCD at less than this. (In EUR)",
+        env = "EUR2CCD_SERVICE_MIN_RATE_EUR"
+    )]
+    min_rate_eur:               Option<f64>,
+    #[structopt(
+        long = "max-rate-eur",
+        help = "If given, an update is not submitted, and protected mode is engaged, if it would \
+                value a CCD at more than this. (In EUR)",
+        env = "EUR2CCD_SERVICE_MAX_RATE_EUR"
+    )]
+    max_rate_eur:               Option<f64>,
+    #[structopt(
+        long = "ewma-alpha",
+        default_value = "0",
+        help = "Smoothing factor (between 0 and 1) of the exponentially weighted moving average \
+                of the computed rates, which is used for updates instead of the computed rate. A \
+                lower value smooths more. 0 disables the smoothing.",
+        env = "EUR2CCD_SERVICE_EWMA_ALPHA"
+    )]
+    ewma_alpha:                 f64,
+    #[structopt(
+        long = "smoothing-alpha",
+        default_value = "0",
+        help = "Smoothing factor (between 0 and 1) of updates towards the last update, i.e. an \
+                update is alpha * computed rate + (1 - alpha) * last update. Not applied to the \
+                first update after startup or protected mode. 0 disables the smoothing.",
+        env = "EUR2CCD_SERVICE_SMOOTHING_ALPHA"
+    )]
+    smoothing_alpha:            f64,
+    #[structopt(
+        long = "stale-fallback-cycles",
+        help = "Number of consecutive update cycles, in which no rate can be computed, where the \
+                last computed rate is used for the update instead. After this many cycles, no \
+                updates are performed until a rate can be computed again. If not given, no \
+                fallback is used.",
+        env = "EUR2CCD_SERVICE_STALE_FALLBACK_CYCLES"
+    )]
+    stale_fallback_cycles:      Option<u32>,
     #[structopt(
         long = "prometheus-port",
         default_value = "8112",
@@ -113,11 +345,84 @@ struct App {
     prometheus_port:            u16,
     #[structopt(
         long = "max-rates-saved",
-        help = "Determines the size of the history of rates from the exchange",
+        help = "Determines the size of the history of rates from the exchange. Comma separated \
+                list of the size for every source, and of sizes for specific sources, in the \
+                format \"<label>=<size>\", which override it (ex. \"60,coin_gecko=120\"). \
+                [default: 60]",
         env = "EUR2CCD_SERVICE_MAX_RATES_SAVED",
-        default_value = "60"
+        use_delimiter = true
+    )]
+    max_rates_saved:            Vec<MaxRatesSaved>,
+    #[structopt(
+        long = "history-window",
+        help = "If given, the history of each source holds the readings of this many latest \
+                seconds, instead of a fixed number of readings. Cannot be combined with \
+                `max-rates-saved` or the options overriding it for specific sources.",
+        env = "EUR2CCD_SERVICE_HISTORY_WINDOW"
+    )]
+    history_window:             Option<u32>,
+    #[structopt(
+        long = "max-identical-readings",
+        help = "Number of consecutive identical readings from a source, after which the source is \
+                considered frozen and excluded from updates",
+        env = "EUR2CCD_SERVICE_MAX_IDENTICAL_READINGS",
+        default_value = "15"
+    )]
+    max_identical_readings:     u32,
+    #[structopt(
+        long = "min-readings-per-source",
+        help = "Minimum number of readings in the history of a source, for it to be used in an \
+                update.",
+        env = "EUR2CCD_SERVICE_MIN_READINGS_PER_SOURCE",
+        default_value = "1"
+    )]
+    min_readings_per_source:    usize,
+    #[structopt(
+        long = "max-reading-age",
+        help = "If given, readings older than this are removed from the history of each source, \
+                before its median is computed. (In seconds)",
+        env = "EUR2CCD_SERVICE_MAX_READING_AGE"
+    )]
+    max_reading_age:            Option<u32>,
+    #[structopt(
+        long = "outlier-filter",
+        help = "Filter for removing outliers from the history of each source, before computing \
+                its median. One of `none` and `iqr:<multiplier>`, which removes rates further \
+                than the multiplier times the interquartile range from the quartiles.",
+        env = "EUR2CCD_SERVICE_OUTLIER_FILTER",
+        default_value = "none"
+    )]
+    outlier_filter:             OutlierFilter,
+    #[structopt(
+        long = "median-even-policy",
+        help = "Which value is the median of an even number of rates, when computing the median \
+                of the history of each source, and when aggregating the medians of the sources \
+                with a median. One of `average`, which is the average of the two middle rates, \
+                `lower` and `higher`, which are the lower and the higher of them, so the median \
+                is a rate actually quoted.",
+        env = "EUR2CCD_SERVICE_MEDIAN_EVEN_POLICY",
+        default_value = "average"
+    )]
+    median_even_policy:         MedianEvenPolicy,
+    #[structopt(
+        long = "aggregation",
+        help = "How the medians of the sources are aggregated into the update. One of `median`, \
+                `mean`, `trimmed-mean:<percent>`, where percent is the percentage of the lowest \
+                and of the highest medians to discard, `weighted-median`, which uses the weights \
+                of the sources, and `mad-median:<multiplier>`, which discards medians further \
+                than the multiplier times the median absolute deviation from the median.",
+        env = "EUR2CCD_SERVICE_AGGREGATION",
+        default_value = "median"
+    )]
+    aggregation:                Aggregation,
+    #[structopt(
+        long = "aggregation-canary",
+        help = "If this flag is enabled, the medians of the sources are also aggregated using \
+                every other aggregation strategy, for comparison. The results are logged and \
+                exposed as metrics, but only the rate of `aggregation` is used for updates.",
+        env = "EUR2CCD_SERVICE_AGGREGATION_CANARY"
     )]
-    max_rates_saved:            usize,
+    aggregation_canary:         bool,
     #[structopt(
         long = "test-sources",
         help = "If set to true, pulls exchange rate from each of the given locations (see \
@@ -127,6 +432,59 @@ struct App {
         group = "testing"
     )]
     test_sources:               Vec<Url>,
+    #[structopt(
+        long = "generic-source",
+        help = "Source, which is pulled with a GET request, and whose rate is extracted from the \
+                JSON response with a JSON pointer, in the format \"<url>,<pointer>,<label>\" \
+                (ex. \"https://example.com/rate,/data/CCD/EUR,example\"). Can be given multiple \
+                times. In the environment variable, multiple values are separated by newlines.",
+        env = "EUR2CCD_SERVICE_GENERIC_SOURCES",
+        require_delimiter = true,
+        value_delimiter = "\n"
+    )]
+    generic_sources:            Vec<GenericSource>,
+    #[structopt(
+        long = "generic-source-method",
+        help = "HTTP method to use for requests to a generic source, in the format \
+                \"<label>=<method>\". Defaults to POST, if a body is given, and GET otherwise. In \
+                the environment variable, multiple values are separated by newlines.",
+        env = "EUR2CCD_SERVICE_GENERIC_SOURCE_METHODS",
+        require_delimiter = true,
+        value_delimiter = "\n"
+    )]
+    generic_source_methods:     Vec<GenericSourceMethod>,
+    #[structopt(
+        long = "generic-source-body",
+        help = "JSON body to send in requests to a generic source, in the format \
+                \"<label>=<json>\". In the environment variable, multiple values are separated by \
+                newlines.",
+        env = "EUR2CCD_SERVICE_GENERIC_SOURCE_BODIES",
+        require_delimiter = true,
+        value_delimiter = "\n"
+    )]
+    generic_source_bodies:      Vec<GenericSourceBody>,
+    #[structopt(
+        long = "derived-source",
+        help = "Source, whose rate is derived from the price of CCD in another currency and the \
+                price of EUR in that currency, which are pulled with GET requests and extracted \
+                from the JSON responses with JSON pointers, in the format \"<label>=<base \
+                url>,<base pointer>|<fx url>,<fx pointer>\". Can be given multiple times. In the \
+                environment variable, multiple values are separated by newlines.",
+        env = "EUR2CCD_SERVICE_DERIVED_SOURCES",
+        require_delimiter = true,
+        value_delimiter = "\n"
+    )]
+    derived_sources:            Vec<DerivedSource>,
+    #[structopt(
+        long = "sources-file",
+        help = "File with generic sources, one on each line, in the format of `generic-source`. \
+                Empty lines and lines starting with # are ignored. The file is read again, when \
+                the service receives SIGHUP, and the sources removed from it are stopped, and the \
+                sources added to it are started, without restarting the service. Only this file \
+                is reloaded, changes of other options require a restart.",
+        env = "EUR2CCD_SERVICE_SOURCES_FILE"
+    )]
+    sources_file:               Option<PathBuf>,
     #[structopt(
         long = "local-keys",
         help = "If given, the service uses local governance keys in specified file instead of \
@@ -140,12 +498,28 @@ struct App {
         env = "EUR2CCD_DRY_RUN"
     )]
     dry_run:                    bool,
+    #[structopt(
+        long = "simulate-submit",
+        help = "Load the keys, fetch the sequence number and sign the updates, but do not send \
+                them, only log the update that would be sent.",
+        env = "EUR2CCD_SERVICE_SIMULATE_SUBMIT",
+        conflicts_with = "dry-run"
+    )]
+    simulate_submit:            bool,
     #[structopt(
         long = "database-url",
         help = "MySQL Connection url for a database, where every reading and update is inserted",
         env = "EUR2CCD_SERVICE_DATABASE_URL"
     )]
     database_url:               Option<String>,
+    #[structopt(
+        long = "db-table-prefix",
+        default_value = "",
+        help = "Prefix of the names of the tables in the database, so multiple instances can \
+                share a database. May only contain ASCII letters, digits and underscores.",
+        env = "EUR2CCD_SERVICE_DB_TABLE_PREFIX"
+    )]
+    db_table_prefix:            TablePrefix,
     #[structopt(
         long = "coin-gecko",
         help = "If this flag is enabled, Coin Gecko is added to the list of sources",
@@ -172,222 +546,2064 @@ struct App {
         env = "EUR2CCD_SERVICE_BITFINEX"
     )]
     bitfinex:                   bool,
+    #[structopt(
+        long = "bitfinex-ws",
+        help = "If this flag is enabled, rates streamed from the ticker of BitFinex over a \
+                websocket are added to the list of sources. Uses the `bitfinex-max-age` and \
+                `bitfinex-max-rates` options.",
+        env = "EUR2CCD_SERVICE_BITFINEX_WS"
+    )]
+    bitfinex_ws:                bool,
+    #[structopt(
+        long = "coin-gecko-max-age",
+        help = "Maximum age of the last reading from Coin Gecko, for it to be used in an update. \
+                If not given, a reading since the last update is required. (In seconds)",
+        env = "EUR2CCD_SERVICE_COIN_GECKO_MAX_AGE"
+    )]
+    coin_gecko_max_age:         Option<u32>,
+    #[structopt(
+        long = "coin-market-cap-max-age",
+        help = "Maximum age of the last reading from Coin Market Cap, for it to be used in an \
+                update. If not given, a reading since the last update is required. (In seconds)",
+        env = "EUR2CCD_SERVICE_COIN_MARKET_CAP_MAX_AGE"
+    )]
+    coin_market_cap_max_age:    Option<u32>,
+    #[structopt(
+        long = "live-coin-watch-max-age",
+        help = "Maximum age of the last reading from Live Coin Watch, for it to be used in an \
+                update. If not given, a reading since the last update is required. (In seconds)",
+        env = "EUR2CCD_SERVICE_LIVE_COIN_WATCH_MAX_AGE"
+    )]
+    live_coin_watch_max_age:    Option<u32>,
+    #[structopt(
+        long = "bitfinex-max-age",
+        help = "Maximum age of the last reading from BitFinex, for it to be used in an update. If \
+                not given, a reading since the last update is required. (In seconds)",
+        env = "EUR2CCD_SERVICE_BITFINEX_MAX_AGE"
+    )]
+    bitfinex_max_age:           Option<u32>,
+    #[structopt(
+        long = "test-sources-max-age",
+        help = "Maximum age of the last reading from each test source, for it to be used in an \
+                update. If not given, a reading since the last update is required. (In seconds)",
+        env = "EUR2CCD_SERVICE_TEST_SOURCES_MAX_AGE"
+    )]
+    test_sources_max_age:       Option<u32>,
+    #[structopt(
+        long = "coin-gecko-max-rates",
+        help = "Size of the history of rates from Coin Gecko. Overrides `max-rates-saved`.",
+        env = "EUR2CCD_SERVICE_COIN_GECKO_MAX_RATES"
+    )]
+    coin_gecko_max_rates:       Option<usize>,
+    #[structopt(
+        long = "coin-market-cap-max-rates",
+        help = "Size of the history of rates from Coin Market Cap. Overrides `max-rates-saved`.",
+        env = "EUR2CCD_SERVICE_COIN_MARKET_CAP_MAX_RATES"
+    )]
+    coin_market_cap_max_rates:  Option<usize>,
+    #[structopt(
+        long = "live-coin-watch-max-rates",
+        help = "Size of the history of rates from Live Coin Watch. Overrides `max-rates-saved`.",
+        env = "EUR2CCD_SERVICE_LIVE_COIN_WATCH_MAX_RATES"
+    )]
+    live_coin_watch_max_rates:  Option<usize>,
+    #[structopt(
+        long = "bitfinex-max-rates",
+        help = "Size of the history of rates from BitFinex. Overrides `max-rates-saved`.",
+        env = "EUR2CCD_SERVICE_BITFINEX_MAX_RATES"
+    )]
+    bitfinex_max_rates:         Option<usize>,
+    #[structopt(
+        long = "test-sources-max-rates",
+        help = "Size of the history of rates from each test source. Overrides `max-rates-saved`.",
+        env = "EUR2CCD_SERVICE_TEST_SOURCES_MAX_RATES"
+    )]
+    test_sources_max_rates:     Option<usize>,
+    #[structopt(
+        long = "source-header",
+        help = "Additional header to attach to every request to a source, in the format \
+                \"<label>:<Header-Name>: <value>\", where label is the label of the source. Can \
+                be given multiple times. In the environment variable, multiple values are \
+                separated by newlines.",
+        env = "EUR2CCD_SERVICE_SOURCE_HEADERS",
+        require_delimiter = true,
+        value_delimiter = "\n"
+    )]
+    source_headers:             Vec<SourceHeader>,
+    #[structopt(
+        long = "probe-sources-at-startup",
+        help = "Request the exchange rate once from every source at startup, and log whether it \
+                succeeded.",
+        env = "EUR2CCD_SERVICE_PROBE_SOURCES_AT_STARTUP"
+    )]
+    probe_sources_at_startup:   bool,
+    #[structopt(
+        long = "require-sources-at-startup",
+        help = "Probe every source at startup, like `probe-sources-at-startup`, and fail startup, \
+                if any source cannot be reached.",
+        env = "EUR2CCD_SERVICE_REQUIRE_SOURCES_AT_STARTUP"
+    )]
+    require_sources_at_startup: bool,
+    #[structopt(
+        long = "node-connect-timeout",
+        help = "How long to wait when connecting to a node, before moving on to the next one. (In \
+                seconds)",
+        env = "EUR2CCD_SERVICE_NODE_CONNECT_TIMEOUT",
+        default_value = "10"
+    )]
+    node_connect_timeout:       u64,
+    #[structopt(
+        long = "max-node-lag",
+        help = "Nodes, whose last finalized block is older than this, are not used, unless every \
+                reachable node lags. Of the other nodes, the one with the most recent finalized \
+                block is used. (In seconds)",
+        env = "EUR2CCD_SERVICE_MAX_NODE_LAG",
+        default_value = "60"
+    )]
+    max_node_lag:               u64,
+    #[structopt(
+        long = "max-connect-attempts",
+        help = "Number of attempts of connecting to the nodes, at startup and after an update \
+                could not be sent, with exponential backoff between them.",
+        env = "EUR2CCD_SERVICE_MAX_CONNECT_ATTEMPTS",
+        default_value = "5"
+    )]
+    max_connect_attempts:       u32,
+    #[structopt(
+        long = "broadcast-all",
+        help = "Send updates to all nodes concurrently, instead of only to the node in use. An \
+                update succeeds, if any node accepts it.",
+        env = "EUR2CCD_SERVICE_BROADCAST_ALL"
+    )]
+    broadcast_all:              bool,
+    #[structopt(
+        long = "clock-skew-allowance",
+        help = "Number of seconds, which are added to the expiry of updates, to tolerate the \
+                clock of the node being ahead of the local clock. Together with the update \
+                expiry, it must be less than the confirmation timeout.",
+        env = "EUR2CCD_SERVICE_CLOCK_SKEW_ALLOWANCE",
+        default_value = "0"
+    )]
+    clock_skew_allowance:       u64,
+    #[structopt(
+        long = "update-expiry",
+        help = "Number of seconds after its submission, where an update expires. Must be less \
+                than the confirmation timeout.",
+        env = "EUR2CCD_SERVICE_UPDATE_EXPIRY",
+        default_value = "100"
+    )]
+    update_expiry:              u64,
+    #[structopt(
+        long = "effective-delay",
+        help = "Number of seconds after its submission, where an update takes effect. If 0, \
+                updates take effect immediately. Otherwise, it must be at least the update expiry \
+                plus the clock skew allowance, as the chain rejects updates taking effect before \
+                they expire.",
+        env = "EUR2CCD_SERVICE_EFFECTIVE_DELAY",
+        default_value = "0"
+    )]
+    effective_delay:            u64,
+    #[structopt(
+        long = "submission-retry-interval",
+        help = "Number of seconds between attempts of submitting an update, which was not \
+                accepted.",
+        env = "EUR2CCD_SERVICE_SUBMISSION_RETRY_INTERVAL",
+        default_value = "10"
+    )]
+    submission_retry_interval:  u64,
+    #[structopt(
+        long = "confirmation-timeout",
+        help = "Number of seconds to wait for a submitted update to be finalized, before it is \
+                submitted again in the next update cycle.",
+        env = "EUR2CCD_SERVICE_CONFIRMATION_TIMEOUT",
+        default_value = "120"
+    )]
+    confirmation_timeout:       u64,
+    #[structopt(
+        long = "source-ca-cert",
+        help = "CA certificate to trust for requests to a source, instead of the built-in root \
+                certificates, in the format \"<label>=<path>\", where label is the label of the \
+                source and path points to a PEM encoded certificate. Any certificate issued by it \
+                is accepted, so this does not pin the certificate of the source. Can be given \
+                multiple times, also for the same source, in which case any of the certificates \
+                are trusted. In the environment variable, multiple values are separated by \
+                newlines.",
+        env = "EUR2CCD_SERVICE_SOURCE_CA_CERTS",
+        require_delimiter = true,
+        value_delimiter = "\n"
+    )]
+    source_ca_certs:            Vec<SourceCaCertificate>,
+    #[structopt(
+        long = "source-tier",
+        help = "Tier of a source, in the format \"<label>=<tier>\", where label is the label of \
+                the source. Sources of higher tiers are only used, if there are not enough \
+                healthy sources of lower tiers. Sources not given a tier are in tier 1. Can be \
+                given multiple times. In the environment variable, multiple values are separated \
+                by newlines.",
+        env = "EUR2CCD_SERVICE_SOURCE_TIERS",
+        require_delimiter = true,
+        value_delimiter = "\n"
+    )]
+    source_tiers:               Vec<SourceTier>,
+    #[structopt(
+        long = "source-weight",
+        help = "Weight of a source, in the format \"<label>=<weight>\", where label is the label \
+                of the source. Only used when aggregating using the weighted median. Sources not \
+                given a weight have weight 1. Can be given multiple times. In the environment \
+                variable, multiple values are separated by newlines.",
+        env = "EUR2CCD_SERVICE_SOURCE_WEIGHTS",
+        require_delimiter = true,
+        value_delimiter = "\n"
+    )]
+    source_weights:             Vec<SourceWeight>,
+    #[structopt(
+        long = "min-sources",
+        help = "Minimum number of healthy sources required for an update. Sources of higher tiers \
+                are included until this is met.",
+        env = "EUR2CCD_SERVICE_MIN_SOURCES",
+        default_value = "1"
+    )]
+    min_sources:                usize,
+    #[structopt(
+        long = "user-agent",
+        help = "User agent to identify the service with, in requests to sources.",
+        env = "EUR2CCD_SERVICE_USER_AGENT",
+        default_value = config::DEFAULT_USER_AGENT
+    )]
+    user_agent:                 String,
+    #[structopt(
+        long = "http-proxy",
+        help = "Proxy to use for requests to sources over http.",
+        env = "HTTP_PROXY"
+    )]
+    http_proxy:                 Option<Url>,
+    #[structopt(
+        long = "https-proxy",
+        help = "Proxy to use for requests to sources over https.",
+        env = "HTTPS_PROXY"
+    )]
+    https_proxy:                Option<Url>,
+    #[structopt(
+        long = "inverted-sources",
+        help = "Comma separated labels of sources, which quote the exchange rate in CCD/EUR \
+                instead of EUR/CCD.",
+        env = "EUR2CCD_SERVICE_INVERTED_SOURCES",
+        use_delimiter = true
+    )]
+    inverted_sources:           Vec<String>,
+    #[structopt(
+        long = "admin-port",
+        help = "Port where the admin endpoints are served. Requires `admin-token`.",
+        env = "EUR2CCD_SERVICE_ADMIN_PORT"
+    )]
+    admin_port:                 Option<u16>,
+    #[structopt(
+        long = "admin-token",
+        help = "Token, which must be given as a bearer token to use the admin endpoints. Must not \
+                be empty.",
+        env = "EUR2CCD_SERVICE_ADMIN_TOKEN",
+        hide_env_values = true
+    )]
+    admin_token:                Option<String>,
+    #[structopt(
+        long = "require-confirmation",
+        help = "Hold updates violating the warning threshold, until they are confirmed through \
+                the admin endpoints. Updates, which are not confirmed before the next update \
+                cycle, are skipped. Requires `admin-port`.",
+        env = "EUR2CCD_SERVICE_REQUIRE_CONFIRMATION"
+    )]
+    require_confirmation:       bool,
+    #[structopt(
+        long = "warmup-seconds",
+        help = "If given, the first update is performed this many seconds after startup, once \
+                every source has at least one reading, instead of after one update interval.",
+        env = "EUR2CCD_SERVICE_WARMUP_SECONDS"
+    )]
+    warmup_seconds:             Option<u64>,
+    #[structopt(
+        long = "protected-state-file",
+        help = "Path of the file, which forces the service into dry run, when the thresholds are \
+                violated.",
+        env = "EUR2CCD_SERVICE_PROTECTED_STATE_FILE",
+        default_value = config::FORCED_DRY_RUN_FILE
+    )]
+    protected_state_file:       PathBuf,
+    #[structopt(
+        long = "clear-protected",
+        help = "Prints why the service was forced into dry run, removes the file forcing it, and \
+                exits."
+    )]
+    clear_protected:            bool,
+    #[structopt(
+        long = "skip-warmup-check",
+        help = "Arm the signer at startup, even if the first computed rate deviates from the rate \
+                on chain by more than the warning threshold.",
+        env = "EUR2CCD_SERVICE_SKIP_WARMUP_CHECK"
+    )]
+    skip_warmup_check:          bool,
+    #[structopt(
+        long = "once",
+        help = "Pulls from the sources for `once-pull-cycles` pull intervals, prints the rate, \
+                which would be submitted, and exits without connecting to the node."
+    )]
+    run_once:                   bool,
+    #[structopt(
+        long = "once-pull-cycles",
+        help = "Number of readings to wait for from every source with `once`.",
+        env = "EUR2CCD_SERVICE_ONCE_PULL_CYCLES",
+        default_value = "3"
+    )]
+    once_pull_cycles:           u32,
 }
 
-/// Attempts to create a file, signalling that the service should be forced into
-/// dry run mode.
-fn force_dry_run() {
-    if let Err(e) = File::create(config::FORCED_DRY_RUN_FILE) {
-        log::error!("Failed creating file to force dry run: {}", e)
-    }
-}
-
-/// Checks if the file, which force_dry_run creates, exists.
-fn is_dry_run_forced() -> bool {
-    std::path::Path::exists(std::path::Path::new(config::FORCED_DRY_RUN_FILE))
-}
-
-/// This main program loop.
-/// The program is structured into two tasks. A background task is spawned that
-/// continuously polls the exchange for the current exchange rate and saves the
-/// last [config::MAX_RATES_SAVED] queries.
-/// In the main task the service attempts to update the exchange rate every
-/// `update-interval` seconds. It does this by looking at the last
-/// [config::MAX_RATES_SAVED] exchange rates and deriving the update
-/// exchange rate from those, by ignoring outliers, etc. This exchange rate is
-/// then submitted to the chain, and queried until the transaction is finalized
-
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let app: App = {
-        let app = App::clap().global_setting(AppSettings::ColoredHelp);
-        let matches = app.get_matches();
-        App::from_clap(&matches)
-    };
-    let max_rates_saved = app.max_rates_saved;
-    let pull_interval = app.pull_interval;
-
-    // Setup
-    // (Stop if error occurs)
-    let mut log_builder = env_logger::Builder::new();
-
-    log_builder.filter_module(module_path!(), app.log_level);
-    log_builder.init();
-
-    log::debug!("Updating every {} seconds)", app.update_interval);
-    log::debug!(
-        "Warnings will be triggered when updates increase by {}% or decrease by {}%",
-        app.warning_increase_threshold,
-        app.warning_decrease_threshold
-    );
-    log::debug!(
-        "Protected mode will be engaged when updates increase by {}% or decrease by {}%",
-        app.halt_increase_threshold,
-        app.halt_decrease_threshold
-    );
-    log::debug!(
-        "Pulling rates every {} seconds. (Max {} rates are saved at a time)",
-        pull_interval,
-        max_rates_saved
+/// Checks that the thresholds are positive and within their allowed ranges,
+/// and that each warning threshold is lower than the corresponding halt
+/// threshold.
+fn validate_thresholds(app: &App) -> anyhow::Result<()> {
+    ensure!(app.warning_increase_threshold >= 1, "Warning threshold (increase) must be positive");
+    ensure!(
+        (1..=MAX_INCREASE_THRESHOLD).contains(&app.halt_increase_threshold),
+        "Halt threshold (increase) outside of allowed range (1-{}): {}",
+        MAX_INCREASE_THRESHOLD,
+        app.halt_increase_threshold
     );
-
-    ensure!(!app.endpoint.is_empty(), "At least one node must be provided.");
     ensure!(
         app.halt_increase_threshold > app.warning_increase_threshold,
         "Warning threshold must be lower than halt threshold (increase)"
     );
+    ensure!(app.warning_decrease_threshold >= 1, "Warning threshold (decrease) must be positive");
     ensure!(
         (1..=100).contains(&app.halt_decrease_threshold),
-        "Halt threshold (decrease) outside of allowed range (1-100): {} ",
+        "Halt threshold (decrease) outside of allowed range (1-100): {}",
         app.halt_decrease_threshold
     );
     ensure!(
         app.halt_decrease_threshold > app.warning_decrease_threshold,
         "Warning threshold must be lower than halt threshold (decrease)"
     );
+    Ok(())
+}
 
-    let million = BigRational::from_integer(1000000.into()); // 1000000 microCCD/CCD
-
-    let db_conn_pool = {
-        if let Some(url) = app.database_url {
-            let pool = database::establish_connection_pool(&url)?;
-            let mut main_conn = pool.get_conn()?;
-            database::create_tables(&mut main_conn)?;
-            Some(pool)
-        } else {
-            log::warn!(
-                "No database url provided, service will not save to read and updated rates!"
-            );
+/// Computes the median of the updates since the given time. The updates are
+/// read from the database, if one is given, and otherwise taken from the given
+/// updates performed since startup, which are pruned to the given time.
+/// Returns None, if there are no such updates.
+fn trailing_baseline(
+    db: Option<&database::Database>,
+    recent_updates: &mut VecDeque<(chrono::DateTime<chrono::Utc>, BigRational)>,
+    since: chrono::DateTime<chrono::Utc>,
+) -> Option<BigRational> {
+    while recent_updates.front().map_or(false, |(time, _)| *time < since) {
+        recent_updates.pop_front();
+    }
+    let from_db = db.and_then(|db| match database::read_updates_since(db, since.naive_utc()) {
+        Ok(updates) => Some(
+            updates
+                .into_iter()
+                .filter(|(_, denominator)| *denominator != 0)
+                .map(|(numerator, denominator)| {
+                    BigRational::new(numerator.into(), denominator.into())
+                })
+                .collect(),
+        ),
+        Err(e) => {
+            log::error!("Unable to read the updates from the database, due to: {}", e);
             None
         }
-    };
+    });
+    let updates =
+        from_db.unwrap_or_else(|| recent_updates.iter().map(|(_, rate)| rate.clone()).collect());
+    compute_median(&updates, MedianEvenPolicy::Average)
+}
 
-    let warning_increase_threshold =
-        BigRational::from_integer(app.warning_increase_threshold.into());
-    let halt_increase_threshold = BigRational::from_integer(app.halt_increase_threshold.into());
-    let warning_decrease_threshold =
-        BigRational::from_integer(app.warning_decrease_threshold.into());
-    let halt_decrease_threshold = BigRational::from_integer(app.halt_decrease_threshold.into());
+/// Reads the changes of the updates in the last 24 hours from the database,
+/// each paired with the time of the update.
+fn load_daily_changes(
+    db: &database::Database,
+) -> anyhow::Result<VecDeque<(chrono::DateTime<chrono::Utc>, BigRational)>> {
+    let since = chrono::offset::Utc::now() - chrono::Duration::seconds(DAILY_CHANGE_WINDOW);
+    let updates = database::read_update_history(db, since.naive_utc())
+        .context("Unable to read the updates from the database.")?;
+    let rates = updates
+        .into_iter()
+        .filter(|(_, _, denominator)| *denominator != 0)
+        .map(|(time, numerator, denominator)| {
+            (time.and_utc(), BigRational::new(numerator.into(), denominator.into()))
+        })
+        .collect::<Vec<_>>();
+    Ok(consecutive_changes(&rates))
+}
 
-    let (registry, mut stats) =
-        prometheus::initialize().await.context("Failed to start the prometheus server.")?;
-    tokio::spawn(prometheus::serve_prometheus(registry, app.prometheus_port));
-    log::debug!("Started prometheus");
+/// Converts the given positive percentage, e.g. a source spread, into a
+/// rational, if it is given.
+fn parse_positive_percent(percent: Option<f64>, name: &str) -> anyhow::Result<Option<BigRational>> {
+    match percent {
+        Some(percent) => match BigRational::from_float(percent) {
+            Some(bound) if percent > 0.0 => Ok(Some(bound)),
+            _ => anyhow::bail!("Invalid {}: {}", name, percent),
+        },
+        None => Ok(None),
+    }
+}
 
-    let mut node_client = get_node_client(app.endpoint.clone()).await?;
-    let parameters = node_client.get_block_chain_parameters(v2::BlockIdentifier::LastFinal).await?;
-    let mut seq_number = node_client
-        .get_next_update_sequence_numbers(parameters.block_hash)
-        .await?
-        .response
-        .micro_ccd_per_euro;
-    let initial_rate = {
-        match &parameters.response {
-            ChainParameters::V0(params) => params.micro_ccd_per_euro,
-            ChainParameters::V1(params) => params.micro_ccd_per_euro,
-            ChainParameters::V2(params) => params.micro_ccd_per_euro,
-            ChainParameters::V3(params) => params.micro_ccd_per_euro,
+/// Converts a smoothing factor into a rational, which is None if the factor is
+/// 0, i.e. smoothing is disabled.
+fn parse_smoothing_factor(alpha: f64) -> anyhow::Result<Option<BigRational>> {
+    match BigRational::from_float(alpha) {
+        Some(rational) if (0.0..=1.0).contains(&alpha) => {
+            Ok(Some(rational).filter(|rational| !rational.is_zero()))
         }
-    };
-    let mut prev_rate =
-        BigRational::new(initial_rate.numerator().into(), initial_rate.denominator().into());
-    log::debug!(
-        "Loaded initial block summary, current exchange rate: {}/{}  (~ {}) microCCD/EUR",
-        initial_rate.numerator(),
-        initial_rate.denominator(),
-        initial_rate.numerator() as f64 / initial_rate.denominator() as f64
-    );
+        _ => anyhow::bail!("Invalid smoothing factor (alpha): {}", alpha),
+    }
+}
 
-    // Vector that stores the rate history for each source. Each history is a queue
-    // in a mutex.
-    let mut rate_histories: Vec<Arc<Mutex<RateHistory>>> = Vec::new();
-    let mut last_update_timestamp: i64 = 1;
+/// Converts a price of a CCD in EUR into the exchange rate in microCCD/EUR.
+fn price_to_micro_ccd_per_euro(price: f64) -> anyhow::Result<BigRational> {
+    match BigRational::from_float(price) {
+        Some(price) if price.is_positive() => Ok(BigRational::from_integer(1000000.into()) / price),
+        _ => anyhow::bail!("Invalid price of a CCD: {} EUR", price),
+    }
+}
 
-    let mut add_source = |source: Source| -> anyhow::Result<()> {
-        let rates_mutex = Arc::new(Mutex::new(RateHistory {
-            rates:                  VecDeque::with_capacity(max_rates_saved),
-            last_reading_timestamp: 0,
-        }));
-        rate_histories.push(rates_mutex.clone());
-
-        tokio::spawn(pull_exchange_rate(
-            stats.clone(),
-            source,
-            rates_mutex,
-            pull_interval,
-            max_rates_saved,
-            db_conn_pool.clone(),
-        ));
-        Ok(())
+/// Formats a relative change, rounded to whole percentages, with its sign.
+fn format_change(change: &BigRational) -> String {
+    if change.is_positive() {
+        format!("+{}", change.round())
+    } else {
+        change.round().to_string()
+    }
+}
+
+/// Creates the logger builder, which logs messages of this crate up to the
+/// given level, or according to the filter in the format of `RUST_LOG`, if
+/// given.
+fn log_builder(log_level: log::LevelFilter, log_filter: Option<&str>) -> env_logger::Builder {
+    let mut builder = env_logger::Builder::new();
+    match log_filter {
+        Some(filter) => builder.parse_filters(filter),
+        None => builder.filter_module(module_path!(), log_level),
+    };
+    builder
+}
+
+/// Aggregates the medians of the sources using every aggregation strategy, and
+/// logs and exposes the results in microCCD/EUR, so the strategies can be
+/// compared.
+fn compare_aggregations(
+    stats: &prometheus::Stats,
+    medians: &[(BigRational, u32)],
+    primary: Aggregation,
+    median_policy: MedianEvenPolicy,
+) {
+    let million = BigRational::from_integer(1000000.into());
+    let results = [
+        Aggregation::Median,
+        Aggregation::Mean,
+        Aggregation::TrimmedMean(CANARY_TRIMMED_MEAN_PERCENT),
+        Aggregation::WeightedMedian,
+        Aggregation::MadFilteredMedian(CANARY_MAD_MULTIPLIER),
+    ]
+    .into_iter()
+    .filter_map(|aggregation| {
+        let rate = aggregation.aggregate_weighted(medians, median_policy)? * &million;
+        stats.update_canary_rate(&rate, &aggregation.to_string());
+        Some(format!("{}: {}", aggregation, rate))
+    })
+    .collect::<Vec<_>>();
+    log::info!("Canary aggregations (using {}): {}", primary, results.join(", "));
+}
+
+/// Forces the service into dry run, and records it in the database, if one is
+/// given.
+fn engage_protection(
+    path: &Path,
+    db: Option<&database::Database>,
+    stats: &prometheus::Stats,
+    state: ProtectedState,
+) {
+    force_dry_run(path, &state);
+    record_protection_event(db, stats, ProtectionEvent::Engaged, &state);
+}
+
+/// Records that forced dry run was engaged or cleared in the database, if one
+/// is given. Failing to do so is only logged.
+fn record_protection_event(
+    db: Option<&database::Database>,
+    stats: &prometheus::Stats,
+    event: ProtectionEvent,
+    state: &ProtectedState,
+) {
+    if let Some(db) = db {
+        if let Err(e) = database::write_protection_event(db, event, state) {
+            stats.increment_failed_database_updates();
+            log::error!("Unable to INSERT protection event ({}): {}, due to: {}", event, state, e)
+        }
+    }
+}
+
+/// Counts a failed update cycle, and exits the service with
+/// [EXIT_CODE_CONSECUTIVE_FAILURES], once the given maximum number of
+/// consecutive cycles have failed, so it is restarted.
+fn record_failed_cycle(stats: &prometheus::Stats, failures: &mut u32, max_failures: Option<u32>) {
+    *failures += 1;
+    stats.set_consecutive_failures(*failures);
+    if let Some(max_failures) = max_failures.filter(|max_failures| *failures >= *max_failures) {
+        log::error!("Exiting, as {} consecutive update cycles have failed", max_failures);
+        std::process::exit(EXIT_CODE_CONSECUTIVE_FAILURES);
+    }
+}
+
+/// Prints why the service was forced into dry run, and removes the file forcing
+/// it. Refuses to remove a file, whose contents are invalid. If a database is
+/// given, the removal is recorded in it.
+fn clear_protected(path: &Path, db: Option<&database::Database>) -> anyhow::Result<()> {
+    if !is_dry_run_forced(path) {
+        println!("The service is not forced into dry run.");
+        return Ok(());
+    }
+    let state = read_protected_state(path)?;
+    match state.as_ref() {
+        Some(state) => println!(
+            "The service was forced into dry run, as {}:\n{}",
+            state,
+            serde_json::to_string_pretty(state)?
+        ),
+        None => println!("The service was forced into dry run, for an unknown reason."),
+    }
+    clear_forced_dry_run(path).context("Unable to remove the file forcing dry run.")?;
+    println!("Removed the file forcing dry run: {}", path.display());
+    if let (Some(db), Some(state)) = (db, state) {
+        if let Err(e) = database::write_protection_event(db, ProtectionEvent::Cleared, &state) {
+            log::error!("Unable to INSERT protection event (cleared): {}, due to: {}", state, e)
+        }
+    }
+    Ok(())
+}
+
+/// Reads the exchange rate on chain from the last finalized block of the
+/// given node.
+async fn read_chain_rate(client: &mut v2::Client) -> anyhow::Result<ExchangeRate> {
+    let parameters = client.get_block_chain_parameters(v2::BlockIdentifier::LastFinal).await?;
+    Ok(micro_ccd_per_euro(&parameters.response))
+}
+
+/// Returns the option, which bounds the rate and is violated by it, if any.
+fn violated_rate_bound(
+    rate: &BigRational,
+    min_rate_bound: Option<&BigRational>,
+    max_rate_bound: Option<&BigRational>,
+) -> Option<&'static str> {
+    if min_rate_bound.map_or(false, |bound| rate < bound) {
+        Some("max-rate-eur")
+    } else if max_rate_bound.map_or(false, |bound| rate > bound) {
+        Some("min-rate-eur")
+    } else {
+        None
+    }
+}
+
+/// Obtains the governance keys, from the given local files, or otherwise from
+/// the given secrets on AWS, and constructs the signer of exchange rate updates
+/// from them.
+async fn load_signer(
+    local_keys: &[PathBuf],
+    region: &str,
+    secret_names: &[String],
+    parameters: &ChainParameters,
+) -> anyhow::Result<BTreeMap<UpdateKeysIndex, UpdateKeyPair>> {
+    let secret_keys = if local_keys.is_empty() {
+        ensure!(
+            !secret_names.is_empty(),
+            "If `dry-run` is not used then one of `secret-names` and `local-keys` must be \
+             provided."
+        );
+        get_governance_from_aws(region.to_string(), secret_names.to_vec()).await
+    } else {
+        get_governance_from_file(local_keys)
+    }
+    .context("Could not obtain keys.")?;
+    parameters
+        .common_update_keys()
+        .construct_update_signer(&parameters.common_update_keys().micro_gtu_per_euro, secret_keys)
+        .context("Failed to obtain keys.")
+}
+
+/// Waits until the given deadline has passed, and every enabled source has at
+/// least one reading. Stops waiting for sources `max_wait` after the deadline,
+/// so a broken source cannot block updates indefinitely.
+async fn wait_for_warmup(sources: &[SourceHandle], deadline: Instant, max_wait: Duration) {
+    sleep_until(deadline).await;
+    let give_up = deadline + max_wait;
+    loop {
+        let waiting = sources
+            .iter()
+            .filter(|source| {
+                let history = lock_history(&source.history, &source.label);
+                !history.disabled && history.last_reading_timestamp == 0
+            })
+            .map(|source| source.label.as_str())
+            .collect::<Vec<_>>();
+        if waiting.is_empty() {
+            return;
+        }
+        if Instant::now() >= give_up {
+            log::warn!("Ending warmup without readings from sources: {:?}", waiting);
+            return;
+        }
+        log::debug!("Warmup is waiting for readings from sources: {:?}", waiting);
+        sleep(Duration::from_secs(WARMUP_POLL_INTERVAL)).await;
+    }
+}
+
+/// Waits until every enabled source has the given number of readings, or that
+/// many pull intervals (plus one) have passed, and prints the median of every
+/// used source, and the rate computed from them like for the first update,
+/// along with the exchange rate it would be submitted as. The node is not
+/// contacted, so the change is not clamped.
+async fn print_rate_once(
+    sources: &[SourceHandle],
+    stats: &prometheus::Stats,
+    rate_pipeline: &RatePipeline,
+    last_update_timestamp: i64,
+    cycles: u32,
+    pull_interval: u32,
+) -> anyhow::Result<()> {
+    ensure!(cycles >= 1, "At least one pull cycle must be performed");
+    let give_up =
+        Instant::now() + Duration::from_secs(u64::from(cycles + 1) * u64::from(pull_interval));
+    loop {
+        let waiting = sources
+            .iter()
+            .filter(|source| {
+                let history = lock_history(&source.history, &source.label);
+                !history.disabled && history.rates.len() < cycles as usize
+            })
+            .map(|source| source.label.as_str())
+            .collect::<Vec<_>>();
+        if waiting.is_empty() {
+            break;
+        }
+        if Instant::now() >= give_up {
+            log::warn!("Stopped waiting for {} readings from sources: {:?}", cycles, waiting);
+            break;
+        }
+        log::debug!("Waiting for {} readings from sources: {:?}", cycles, waiting);
+        sleep(Duration::from_secs(WARMUP_POLL_INTERVAL)).await;
+    }
+    let candidate = rate_pipeline
+        .candidate(sources, stats, last_update_timestamp)
+        .context("No rate could be computed.")?;
+    for (label, median) in candidate.medians.iter() {
+        println!("{}: {} CCD/EUR", label, median);
+    }
+    println!(
+        "Aggregated rate ({}): {} (~ {}) microCCD/EUR",
+        rate_pipeline.aggregation,
+        candidate.rate,
+        candidate.rate.to_f64().unwrap_or(f64::NAN)
+    );
+    let rate = rate_pipeline.smooth(candidate.rate, &mut None, None);
+    let (rate, _) = rate_pipeline.limit(rate, None);
+    let exchange_rate = convert_big_fraction_to_exchange_rate(&rate)?;
+    println!(
+        "Exchange rate: {}/{} microCCD/EUR",
+        exchange_rate.numerator(),
+        exchange_rate.denominator()
+    );
+    Ok(())
+}
+
+/// How the rate of an update is computed from the histories of the sources,
+/// shared by the update cycles, the check at startup and `once`.
+struct RatePipeline {
+    max_reading_age:         Option<u32>,
+    max_identical_readings:  u32,
+    min_readings_per_source: usize,
+    outlier_filter:          OutlierFilter,
+    median_even_policy:      MedianEvenPolicy,
+    min_sources:             usize,
+    aggregation:             Aggregation,
+    aggregation_canary:      bool,
+    /// How far the median of a source may be from the median of all used
+    /// sources, for the source to agree. (In percentage)
+    agreement_tolerance:     BigRational,
+    ewma_alpha:              Option<BigRational>,
+    smoothing_alpha:         Option<BigRational>,
+    clamp_change_percent:    Option<BigRational>,
+    rate_precision:          Option<u32>,
+}
+
+/// A rate computed from the medians of the sources.
+struct Candidate {
+    /// The aggregated rate. (In microCCD/EUR)
+    rate:             BigRational,
+    /// The medians of the used sources, by their labels. (In CCD/EUR)
+    medians:          Vec<(String, BigRational)>,
+    /// The relative spread between the lowest and the highest median. (In
+    /// percentage)
+    source_spread:    Option<BigRational>,
+    /// The number of used sources agreeing with the median of all of them.
+    agreeing_sources: usize,
+}
+
+impl Candidate {
+    /// The labels of the used sources, separated by commas.
+    fn used_sources(&self) -> String {
+        self.medians.iter().map(|(label, _)| label.as_str()).collect::<Vec<_>>().join(",")
+    }
+}
+
+impl RatePipeline {
+    /// Computes the median of every healthy source, excludes inverted sources,
+    /// selects the sources by their tiers, and aggregates their medians.
+    /// Returns None, if too few sources are usable, or the medians cannot be
+    /// aggregated.
+    fn candidate(
+        &self,
+        sources: &[SourceHandle],
+        stats: &prometheus::Stats,
+        last_update_timestamp: i64,
+    ) -> Option<Candidate> {
+        let now = chrono::offset::Utc::now().timestamp();
+        let healthy_sources = sources
+            .iter()
+            .filter(|source| {
+                let rates_history = lock_history(&source.history, &source.label);
+                !rates_history.disabled
+                    && rates_history.last_reading_timestamp != 0
+                    && !rates_history.is_stale(source.max_age, last_update_timestamp, now)
+            })
+            .count();
+        stats.set_sources_healthy(healthy_sources);
+        // For each source, we compute the median of their history:
+        let mut healthy_medians = sources
+            .iter()
+            .filter_map(|source| {
+                let mut rates_history = lock_history(&source.history, &source.label);
+                if let Some(max_reading_age) = self.max_reading_age {
+                    let pruned = rates_history.prune_older_than(now - i64::from(max_reading_age));
+                    if pruned > 0 {
+                        log::info!(
+                            "{}: Removed {} readings older than {} seconds from the history",
+                            source.label,
+                            pruned,
+                            max_reading_age
+                        );
+                    }
+                }
+                stats.set_history_length(&source.label, rates_history.rates.len());
+                if rates_history.disabled {
+                    log::info!("{}: Source was dropped for update, it is disabled", source.label);
+                    None
+                } else if rates_history.last_reading_timestamp == 0 {
+                    log::warn!(
+                        "{}: Source was dropped for update, no successful readings",
+                        source.label
+                    );
+                    None
+                } else if rates_history.is_stale(source.max_age, last_update_timestamp, now) {
+                    let datetime =
+                        chrono::DateTime::from_timestamp(rates_history.last_reading_timestamp, 0)
+                            .map_or_else(
+                                || {
+                                    format!(
+                                        "{} (timestamp due to conversion error)",
+                                        rates_history.last_reading_timestamp
+                                    )
+                                },
+                                |dt| dt.to_string(),
+                            );
+
+                    log::warn!(
+                        "{}: Source was dropped for update, last succesful reading was at {}",
+                        source.label,
+                        datetime,
+                    );
+                    None
+                } else if rates_history.is_frozen(self.max_identical_readings) {
+                    log::warn!(
+                        "{}: Source was dropped for update, its last {} readings were identical",
+                        source.label,
+                        rates_history.identical_readings + 1
+                    );
+                    None
+                } else if rates_history.rates.len() < self.min_readings_per_source {
+                    log::warn!(
+                        "{}: Source was dropped for update, it only has {} readings ({} required)",
+                        source.label,
+                        rates_history.rates.len(),
+                        self.min_readings_per_source
+                    );
+                    None
+                } else {
+                    let rates = self.outlier_filter.apply(&rates_history.rates);
+                    if rates.len() < rates_history.rates.len() {
+                        log::info!(
+                            "{}: Removed {} outliers from the history",
+                            source.label,
+                            rates_history.rates.len() - rates.len()
+                        );
+                    }
+                    compute_median(&rates, self.median_even_policy)
+                        .map(|median| (source.tier, (source.label.as_str(), median)))
+                }
+            })
+            .collect::<Vec<_>>();
+        // Sources far from the median of the other sources most likely quote the
+        // rate inverted, so we exclude them:
+        let deviations = deviating_rates(
+            &healthy_medians.iter().map(|(_, (_, median))| median).collect::<Vec<_>>(),
+            MAX_SOURCE_DEVIATION_FACTOR,
+        );
+        match deviations {
+            Some(deviations) => {
+                let mut deviations = deviations.into_iter();
+                healthy_medians.retain(|(_, (label, median))| {
+                    let (deviates, others_median) =
+                        deviations.next().expect("A deviation per source");
+                    if deviates {
+                        log::error!(
+                            "{}: Source was dropped for update, its rate {} differs from the \
+                             median of the other sources {} by more than a factor {}. Check \
+                             whether the source should be inverted.",
+                            label,
+                            median,
+                            others_median,
+                            MAX_SOURCE_DEVIATION_FACTOR
+                        );
+                    }
+                    !deviates
+                });
+            }
+            None => log::debug!(
+                "Not checking for inverted sources, as only {} sources have new readings ({} \
+                 required)",
+                healthy_medians.len(),
+                MIN_RATES_FOR_DEVIATION_CHECK
+            ),
+        }
+        // Then we select which sources to use, by their tier:
+        let used_medians = select_by_tier(healthy_medians, self.min_sources);
+        for source in sources.iter() {
+            stats.set_source_used(
+                &source.label,
+                used_medians.iter().any(|(label, _)| *label == source.label),
+            );
+        }
+        let used_labels =
+            used_medians.iter().map(|(label, _)| label.to_string()).collect::<Vec<_>>();
+        log::info!("Using sources: {:?}", used_labels);
+        if used_medians.len() < self.min_sources {
+            log::error!(
+                "Skipping update, due to only {} sources having new readings ({} required)",
+                used_medians.len(),
+                self.min_sources
+            );
+            return None;
+        }
+        let rate_medians = used_medians
+            .iter()
+            .map(|(label, median)| {
+                let weight = sources
+                    .iter()
+                    .find(|source| source.label == *label)
+                    .map_or(1, |source| source.weight);
+                (median.clone(), weight)
+            })
+            .collect::<Vec<_>>();
+        // Then we compute how much the sources disagree:
+        let source_spread = relative_spread(rate_medians.iter().map(|(median, _)| median));
+        if let Some(spread) = source_spread.as_ref() {
+            log::debug!("Spread of the sources: ~{} %", spread.round());
+            stats.update_source_spread(spread);
+        }
+        if let Some(ratio) = spread_ratio(rate_medians.iter().map(|(median, _)| median)) {
+            stats.set_source_spread(&ratio);
+        }
+        // And how many of them agree:
+        let agreeing_sources = count_agreeing(
+            rate_medians.iter().map(|(median, _)| median),
+            &self.agreement_tolerance,
+        );
+        log::debug!("Sources agreeing within {} %: {}", self.agreement_tolerance, agreeing_sources);
+        stats.set_agreeing_sources(agreeing_sources);
+        // Compare the aggregations on the same medians, if the canary is enabled:
+        if self.aggregation_canary {
+            compare_aggregations(stats, &rate_medians, self.aggregation, self.median_even_policy);
+        }
+        // Then we aggregate the medians, and multiply with 1000000 microCCD/CCD to
+        // convert the unit to microCCD/Eur:
+        let rate = self.aggregation.aggregate_weighted(&rate_medians, self.median_even_policy)?
+            * BigRational::from_integer(1000000.into());
+        Some(Candidate {
+            rate,
+            medians: used_medians
+                .into_iter()
+                .map(|(label, median)| (label.to_string(), median))
+                .collect(),
+            source_spread,
+            agreeing_sources,
+        })
+    }
+
+    /// Smooths the computed rate with the moving average of the computed
+    /// rates, and then towards the given rate of the last update, if any.
+    fn smooth(
+        &self,
+        rate: BigRational,
+        smoothed_rate: &mut Option<BigRational>,
+        towards: Option<&BigRational>,
+    ) -> BigRational {
+        let computed_rate = rate.clone();
+        // Smooth the rate, so the rate on chain does not follow the noise of the
+        // market:
+        let rate = match self.ewma_alpha.as_ref() {
+            Some(alpha) => {
+                let smoothed = match smoothed_rate.take() {
+                    Some(prev_smoothed) => ewma_step(&prev_smoothed, &rate, alpha),
+                    None => rate,
+                };
+                log::debug!("Smoothed rate: {} microCCD/Eur", smoothed);
+                *smoothed_rate = Some(smoothed.clone());
+                smoothed
+            }
+            None => rate,
+        };
+        // Smooth the rate towards the last update, to avoid updates zig-zagging
+        // around the price:
+        match (self.smoothing_alpha.as_ref(), towards) {
+            (Some(alpha), Some(prev_rate)) => {
+                let smoothed = ewma_step(prev_rate, &rate, alpha);
+                log::info!(
+                    "Smoothed rate from {} to {} microCCD/Eur (computed rate: {})",
+                    rate,
+                    smoothed,
+                    computed_rate
+                );
+                smoothed
+            }
+            (Some(_), None) => {
+                log::info!(
+                    "Not smoothing rate {} microCCD/Eur, as no update has been performed since \
+                     startup or protected mode",
+                    rate
+                );
+                rate
+            }
+            (None, _) => rate,
+        }
+    }
+
+    /// Clamps the change from the given rate on chain, if known, and rounds the
+    /// rate. Returns the rate, and whether it was clamped.
+    fn limit(&self, rate: BigRational, prev_rate: Option<&BigRational>) -> (BigRational, bool) {
+        let (rate, clamped) = match (self.clamp_change_percent.as_ref(), prev_rate) {
+            (Some(max_percent), Some(prev_rate)) => {
+                match clamp_change(prev_rate, &rate, max_percent) {
+                    Some(clamped) => {
+                        log::warn!(
+                            "Clamped rate from {} to {} microCCD/Eur, as it changes more than {} \
+                             % from {}",
+                            rate,
+                            clamped,
+                            max_percent,
+                            prev_rate
+                        );
+                        (clamped, true)
+                    }
+                    None => (rate, false),
+                }
+            }
+            _ => (rate, false),
+        };
+        // Round the rate, so the submitted fraction stays small:
+        let rate = match self.rate_precision {
+            Some(digits) => {
+                let rounded = round_rational(&rate, digits);
+                log::debug!("Rounded rate from {} to {} microCCD/Eur", rate, rounded);
+                rounded
+            }
+            None => rate,
+        };
+        (rate, clamped)
+    }
+
+    /// The rate, which the first update cycle after startup would check against
+    /// the thresholds, given the rate on chain. Returns None, if no rate can be
+    /// computed.
+    fn first_rate(
+        &self,
+        sources: &[SourceHandle],
+        stats: &prometheus::Stats,
+        last_update_timestamp: i64,
+        prev_rate: &BigRational,
+    ) -> Option<BigRational> {
+        let candidate = self.candidate(sources, stats, last_update_timestamp)?;
+        // No update has been performed since startup, so the rate is not smoothed
+        // towards the rate on chain.
+        let rate = self.smooth(candidate.rate, &mut None, None);
+        Some(self.limit(rate, Some(prev_rate)).0)
+    }
+}
+
+/// The tasks pulling the sources, by the labels of the sources.
+type SourceTasks = HashMap<String, JoinHandle<anyhow::Result<()>>>;
+
+/// The generic sources read from the sources file, along with what is needed
+/// to start the sources added to the file, when it is reloaded.
+struct SourcesFile {
+    path:                   PathBuf,
+    /// The sources currently read from the file.
+    sources:                Vec<GenericSource>,
+    client:                 reqwest::Client,
+    history_limit:          HistoryLimit,
+    pull_interval:          u32,
+    max_jitter:             Duration,
+    max_identical_readings: u32,
+    db_conn_pool:           Option<database::Database>,
+}
+
+impl SourcesFile {
+    /// Re-reads the sources file. The sources removed from the file are
+    /// stopped, and the sources added to it are started with the default
+    /// tier, weight and history size. The histories of the remaining sources
+    /// are kept. A source, whose url or pointer changed, is restarted with an
+    /// empty history.
+    fn reload(
+        &mut self,
+        stats: &prometheus::Stats,
+        sources: &mut Vec<SourceHandle>,
+        tasks: &mut SourceTasks,
+        histories: &admin::SourceHistories,
+    ) -> anyhow::Result<()> {
+        let new_sources = read_sources_file(&self.path)?;
+        let mut histories = histories.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for removed in self.sources.iter().filter(|source| !new_sources.contains(source)) {
+            if let Some(task) = tasks.remove(&removed.label) {
+                task.abort();
+            }
+            sources.retain(|handle| handle.label != removed.label);
+            histories.remove(&removed.label);
+            stats.set_source_used(&removed.label, false);
+            log::warn!("{}: Source was removed from the sources file", removed.label);
+        }
+        self.sources.retain(|source| new_sources.contains(source));
+        for added in new_sources {
+            if self.sources.contains(&added) {
+                continue;
+            }
+            if sources.iter().any(|handle| handle.label == added.label) {
+                log::error!(
+                    "{}: Source was not added from the sources file, as another source has the \
+                     same label",
+                    added.label
+                );
+                continue;
+            }
+            let history = Arc::new(Mutex::new(RateHistory::with_capacity(
+                self.history_limit.expected_readings(self.pull_interval),
+            )));
+            sources.push(SourceHandle {
+                label:   added.label.clone(),
+                max_age: None,
+                tier:    1,
+                weight:  1,
+                history: history.clone(),
+            });
+            histories.insert(added.label.clone(), history.clone());
+            tasks.insert(
+                added.label.clone(),
+                tokio::spawn(pull_exchange_rate(
+                    stats.clone(),
+                    added.clone().into_source(),
+                    self.client.clone(),
+                    reqwest::header::HeaderMap::new(),
+                    false,
+                    history,
+                    self.pull_interval,
+                    self.max_jitter,
+                    self.history_limit,
+                    self.max_identical_readings,
+                    self.db_conn_pool.clone(),
+                )),
+            );
+            log::warn!(
+                "{}: Source was added from the sources file: {}, with pointer {}",
+                added.label,
+                added.url,
+                added.json_pointer
+            );
+            self.sources.push(added);
+        }
+        stats.set_sources_configured(sources.len());
+        Ok(())
+    }
+}
+
+/// An update held until it is confirmed or rejected through the admin
+/// endpoint. The main loop keeps running update cycles, while it is held.
+struct HeldUpdate {
+    id:       u64,
+    /// The rate, which was held. (In microCCD/EUR)
+    rate:     BigRational,
+    /// Receives whether the update was confirmed (true) or rejected (false).
+    decision: tokio::sync::oneshot::Receiver<bool>,
+    /// The update is dropped, if it is not confirmed before this time.
+    expires:  Instant,
+}
+
+/// Waits for the decision on the held update, if an update is held, and
+/// otherwise waits forever.
+async fn held_decision(held: &mut Option<HeldUpdate>) -> bool {
+    match held {
+        // The sender is only dropped along with the held update.
+        Some(held) => (&mut held.decision).await.unwrap_or(false),
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the next SIGHUP, if the service listens for it, and otherwise
+/// waits forever.
+async fn hangup(listener: &mut Option<Signal>) -> Option<()> {
+    match listener {
+        Some(listener) => listener.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// This main program loop.
+/// The program is structured into two tasks. A background task is spawned that
+/// continuously polls the exchange for the current exchange rate and saves the
+/// last [config::MAX_RATES_SAVED] queries.
+/// In the main task the service attempts to update the exchange rate every
+/// `update-interval` seconds. It does this by looking at the last
+/// [config::MAX_RATES_SAVED] exchange rates and deriving the update
+/// exchange rate from those, by ignoring outliers, etc. This exchange rate is
+/// then submitted to the chain, and queried until the transaction is finalized
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let start = Instant::now();
+    let app: App = {
+        let app = App::clap().global_setting(AppSettings::ColoredHelp);
+        let matches = app.get_matches();
+        App::from_clap(&matches)
+    };
+    // The size of the history of every source, and the sizes of specific sources,
+    // by the label of the source.
+    let mut max_rates_saved = None;
+    let mut source_max_rates: HashMap<String, usize> = HashMap::new();
+    for entry in app.max_rates_saved.iter() {
+        match &entry.label {
+            Some(label) => ensure!(
+                source_max_rates.insert(label.clone(), entry.max_rates).is_none(),
+                "Multiple numbers of rates saved given for source {}",
+                label
+            ),
+            None => {
+                ensure!(
+                    max_rates_saved.replace(entry.max_rates).is_none(),
+                    "Multiple numbers of rates saved given for every source"
+                )
+            }
+        }
+    }
+    let max_rates_saved = max_rates_saved.unwrap_or(config::DEFAULT_MAX_RATES_SAVED);
+    if let Some(window) = app.history_window {
+        ensure!(
+            app.max_rates_saved.is_empty()
+                && [
+                    app.coin_gecko_max_rates,
+                    app.coin_market_cap_max_rates,
+                    app.live_coin_watch_max_rates,
+                    app.bitfinex_max_rates,
+                    app.test_sources_max_rates,
+                ]
+                .iter()
+                .all(Option::is_none),
+            "`history-window` cannot be combined with the number of rates saved"
+        );
+        ensure!(window >= app.pull_interval, "History window must be at least the pull interval");
+    }
+    let pull_interval = app.pull_interval;
+    let update_interval = app.update_interval;
+    let max_identical_readings = app.max_identical_readings;
+    let node_connect_timeout = Duration::from_secs(app.node_connect_timeout);
+    let max_node_lag = Duration::from_secs(app.max_node_lag);
+
+    // Setup
+    // (Stop if error occurs)
+    log_builder(app.log_level, app.log_filter.as_deref()).init();
+
+    if app.clear_protected {
+        let db = match app.database_url.as_deref() {
+            Some(url) => {
+                let db = database::establish_connection_pool(url, app.db_table_prefix.clone())?;
+                database::create_tables(&mut db.pool.get_conn()?, &db.prefix)?;
+                Some(db)
+            }
+            None => None,
+        };
+        return clear_protected(&app.protected_state_file, db.as_ref());
+    }
+
+    log::debug!("Updating every {} seconds)", app.update_interval);
+    log::debug!(
+        "Warnings will be triggered when updates increase by {}% or decrease by {}%",
+        app.warning_increase_threshold,
+        app.warning_decrease_threshold
+    );
+    log::info!("Action on halt threshold violations: {}", app.halt_action);
+    match app.halt_action {
+        HaltAction::Protect => log::debug!(
+            "Protected mode will be engaged when updates increase by {}% or decrease by {}%",
+            app.halt_increase_threshold,
+            app.halt_decrease_threshold
+        ),
+        HaltAction::Skip => log::debug!(
+            "Updates will be skipped when they increase by {}% or decrease by {}%",
+            app.halt_increase_threshold,
+            app.halt_decrease_threshold
+        ),
+    }
+    log::info!("Checking the thresholds against the baseline: {}", app.threshold_baseline);
+    log::info!("Aggregating the medians of the sources using: {}", app.aggregation);
+    log::info!("Filtering outliers from the histories using: {}", app.outlier_filter);
+    if app.aggregation_canary {
+        log::info!("Comparing all aggregations of the medians of the sources in the canary");
+    }
+    match app.history_window {
+        Some(window) => log::debug!(
+            "Pulling rates every {} seconds. (Rates of the last {} seconds are saved)",
+            pull_interval,
+            window
+        ),
+        None => log::debug!(
+            "Pulling rates every {} seconds. (Max {} rates are saved at a time)",
+            pull_interval,
+            max_rates_saved
+        ),
+    }
+
+    ensure!(!app.endpoint.is_empty(), "At least one node must be provided.");
+    ensure!(app.max_connect_attempts > 0, "At least one attempt of connecting must be made.");
+    ensure!(app.update_expiry > 0, "Update expiry must be positive");
+    ensure!(app.submission_retry_interval > 0, "Submission retry interval must be positive");
+    // The update must expire before we stop waiting for it, otherwise it might be
+    // finalized after it was given up on.
+    ensure!(
+        app.update_expiry.saturating_add(app.clock_skew_allowance) < app.confirmation_timeout,
+        "Update expiry ({} seconds) plus clock skew allowance ({} seconds) must be less than the \
+         confirmation timeout ({} seconds)",
+        app.update_expiry,
+        app.clock_skew_allowance,
+        app.confirmation_timeout
+    );
+    // The chain rejects updates, whose effective time is before their expiry,
+    // unless they take effect immediately.
+    ensure!(
+        app.effective_delay == 0
+            || app.effective_delay >= app.update_expiry.saturating_add(app.clock_skew_allowance),
+        "Effective delay ({} seconds) must be 0, or at least the update expiry ({} seconds) plus \
+         clock skew allowance ({} seconds)",
+        app.effective_delay,
+        app.update_expiry,
+        app.clock_skew_allowance
+    );
+    let submission_settings = SubmissionSettings {
+        expiry_offset:        app.update_expiry,
+        clock_skew_allowance: app.clock_skew_allowance,
+        retry_interval:       Duration::from_secs(app.submission_retry_interval),
+        effective_delay:      app.effective_delay,
+    };
+    ensure!(app.min_sources >= 1, "At least one source must be required for updates.");
+    ensure!(
+        app.pull_jitter <= 100,
+        "Pull jitter outside of allowed range (0-100): {}",
+        app.pull_jitter
+    );
+    if let Some(max_reading_age) = app.max_reading_age {
+        ensure!(
+            max_reading_age >= pull_interval,
+            "Maximum reading age must be at least the pull interval"
+        );
+        log::info!("Removing readings older than {} seconds from the histories", max_reading_age);
+    }
+    let pull_jitter = if app.no_jitter {
+        Duration::ZERO
+    } else {
+        Duration::from_secs(pull_interval.into()) * u32::from(app.pull_jitter) / 100
+    };
+    validate_thresholds(&app)?;
+    ensure!(
+        app.halt_after_consecutive >= 1,
+        "At least one violation of a halt threshold must be required to force dry run."
+    );
+    let min_change_threshold = match BigRational::from_float(app.min_change_threshold) {
+        Some(threshold) if app.min_change_threshold >= 0.0 => threshold,
+        _ => anyhow::bail!("Invalid minimum change threshold: {}", app.min_change_threshold),
+    };
+    let clamp_change_percent = match app.clamp_change {
+        Some(percent) => match BigRational::from_float(percent) {
+            Some(bound) if percent > 0.0 && percent < 100.0 => {
+                log::info!("Clamping updates to a change of {} %", percent);
+                Some(bound)
+            }
+            _ => anyhow::bail!("Invalid clamp change: {}", percent),
+        },
+        None => None,
+    };
+    let max_daily_change = parse_positive_percent(app.max_daily_change, "max-daily-change")?;
+    let max_source_spread = parse_positive_percent(app.max_source_spread, "max-source-spread")?;
+    let halt_source_spread = parse_positive_percent(app.halt_source_spread, "halt-source-spread")?;
+    if let (Some(max_spread), Some(halt_spread)) = (&max_source_spread, &halt_source_spread) {
+        ensure!(
+            halt_spread > max_spread,
+            "The halt source spread must be higher than the maximum source spread"
+        );
+    }
+    let agreement_tolerance = match BigRational::from_float(app.agreement_tolerance) {
+        Some(tolerance) if app.agreement_tolerance > 0.0 => tolerance,
+        _ => anyhow::bail!("Invalid agreement-tolerance: {}", app.agreement_tolerance),
+    };
+    ensure!(
+        app.max_consecutive_failures != Some(0),
+        "The maximum number of consecutive failures must be at least 1"
+    );
+    if let Some(min_agreeing) = app.min_agreeing_sources {
+        ensure!(min_agreeing >= 1, "At least one source must be required to agree");
+        log::info!(
+            "Requiring {} sources to agree within {} % for an update",
+            min_agreeing,
+            app.agreement_tolerance
+        );
+    }
+    if let Some(digits) = app.rate_precision {
+        ensure!(digits > 0, "Rate precision must be at least 1 digit");
+        log::info!("Rounding updates to {} significant digits", digits);
+    }
+    let max_conversion_error = match app.max_conversion_error {
+        Some(error) => match BigRational::from_float(error) {
+            Some(epsilon) if error >= 0.0 => {
+                log::info!("Limiting the relative conversion error to {}", error);
+                Some(epsilon)
+            }
+            _ => anyhow::bail!("Invalid maximum conversion error: {}", error),
+        },
+        None => None,
+    };
+    // A higher price of a CCD means fewer microCCD per EUR, so the minimum price
+    // bounds the rate from above, and the maximum price bounds it from below:
+    let max_rate_bound = app.min_rate_eur.map(price_to_micro_ccd_per_euro).transpose()?;
+    let min_rate_bound = app.max_rate_eur.map(price_to_micro_ccd_per_euro).transpose()?;
+    if let (Some(min_price), Some(max_price)) = (app.min_rate_eur, app.max_rate_eur) {
+        ensure!(
+            min_price < max_price,
+            "The minimum rate must be lower than the maximum rate (in EUR)"
+        );
+    }
+    if let Some(min_price) = app.min_rate_eur {
+        log::info!("Halting updates valuing a CCD at less than {} EUR", min_price);
+    }
+    if let Some(max_price) = app.max_rate_eur {
+        log::info!("Halting updates valuing a CCD at more than {} EUR", max_price);
+    }
+    let ewma_alpha = parse_smoothing_factor(app.ewma_alpha)?;
+    if ewma_alpha.is_some() {
+        log::info!("Smoothing the computed rates with alpha: {}", app.ewma_alpha);
+    }
+    let smoothing_alpha = parse_smoothing_factor(app.smoothing_alpha)?;
+    if smoothing_alpha.is_some() {
+        log::info!("Smoothing updates towards the last update with alpha: {}", app.smoothing_alpha);
+    }
+
+    let million = BigRational::from_integer(1000000.into()); // 1000000 microCCD/CCD
+    let rate_pipeline = RatePipeline {
+        max_reading_age: app.max_reading_age,
+        max_identical_readings,
+        min_readings_per_source: app.min_readings_per_source,
+        outlier_filter: app.outlier_filter.clone(),
+        median_even_policy: app.median_even_policy,
+        min_sources: app.min_sources,
+        aggregation: app.aggregation,
+        aggregation_canary: app.aggregation_canary,
+        agreement_tolerance,
+        ewma_alpha,
+        smoothing_alpha,
+        clamp_change_percent,
+        rate_precision: app.rate_precision,
+    };
+
+    let db_conn_pool = {
+        if let Some(url) = app.database_url {
+            let db = database::establish_connection_pool(&url, app.db_table_prefix)?;
+            let mut main_conn = db.pool.get_conn()?;
+            database::create_tables(&mut main_conn, &db.prefix)?;
+            Some(db)
+        } else {
+            log::warn!(
+                "No database url provided, service will not save to read and updated rates!"
+            );
+            None
+        }
+    };
+
+    let warning_increase_threshold =
+        BigRational::from_integer(app.warning_increase_threshold.into());
+    let halt_increase_threshold = BigRational::from_integer(app.halt_increase_threshold.into());
+    let warning_decrease_threshold =
+        BigRational::from_integer(app.warning_decrease_threshold.into());
+    let halt_decrease_threshold = BigRational::from_integer(app.halt_decrease_threshold.into());
+
+    let (registry, mut stats) =
+        prometheus::initialize().await.context("Failed to start the prometheus server.")?;
+    tokio::spawn(prometheus::serve_prometheus(registry, app.prometheus_port));
+    log::debug!("Started prometheus");
+    stats.set_halt_action(&app.halt_action.to_string());
+
+    // Vector that stores the rate history for each source. Each history is a queue
+    // in a mutex.
+    let mut sources: Vec<SourceHandle> = Vec::new();
+    // The tasks pulling the sources, which are stopped when a source is removed
+    // from the sources file.
+    let mut source_tasks: SourceTasks = HashMap::new();
+    // The sources to probe at startup, along with their clients and headers.
+    let probe_sources = app.probe_sources_at_startup || app.require_sources_at_startup;
+    let mut probes: Vec<(Source, reqwest::Client, reqwest::header::HeaderMap)> = Vec::new();
+    // The longest duration covered by the history of a source (in seconds).
+    let mut history_window: u64 = 0;
+    let mut last_update_timestamp: i64 = 1;
+    // The exponentially weighted moving average of the computed rates, if smoothing
+    // is enabled.
+    let mut smoothed_rate: Option<BigRational> = None;
+    // Whether to smooth the next update towards the last update. This is only done
+    // once an update has been performed since startup or protected mode.
+    let mut smooth_towards_prev_rate = false;
+    // The last successfully computed rate, and the number of consecutive cycles
+    // since, in which no rate could be computed.
+    let mut last_computed_rate: Option<(BigRational, String)> = None;
+    let mut failed_cycles: u32 = 0;
+    // The number of consecutive cycles, in which the halt threshold was violated.
+    let mut halt_violation_streak: u32 = 0;
+    // The number of consecutive updates skipped, because the rate did not change
+    // enough.
+    let mut consecutive_skips: u32 = 0;
+    // The number of consecutive update cycles, which failed.
+    let mut consecutive_failures: u32 = 0;
+    // Identifies the updates, which are held until they are confirmed.
+    let mut confirmation_id: u64 = 0;
+    // The update awaiting confirmation, if any.
+    let mut held_update: Option<HeldUpdate> = None;
+    // The id and rate of the last confirmed update, and until when the
+    // confirmation is valid. The confirmation covers a recomputed rate, which
+    // changes no more than the confirmed rate.
+    let mut confirmed_update: Option<(u64, BigRational, Instant)> = None;
+    // The updates performed since startup, and when they were performed, for
+    // the trailing threshold baseline without a database.
+    let mut recent_updates: VecDeque<(chrono::DateTime<chrono::Utc>, BigRational)> =
+        VecDeque::new();
+    // The changes of the updates in the last 24 hours, and when they were
+    // performed, for limiting the cumulative change. They are read from the
+    // database, if given, so they survive restarts.
+    let mut daily_changes = match (&max_daily_change, &db_conn_pool) {
+        (Some(_), Some(pool)) => load_daily_changes(pool)?,
+        _ => VecDeque::new(),
+    };
+
+    // Additional headers for each source, grouped by the label of the source.
+    let mut source_headers: HashMap<String, reqwest::header::HeaderMap> = HashMap::new();
+    for header in app.source_headers {
+        log::debug!("{}: Attaching header {}", header.label, header);
+        source_headers.entry(header.label).or_default().append(header.name, header.value);
+    }
+
+    // CA certificates to trust for each source, grouped by the label of the source.
+    let mut source_ca_certs: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for cert in app.source_ca_certs {
+        source_ca_certs.entry(cert.label).or_default().push(cert.path);
+    }
+
+    // Tier of each source, by the label of the source.
+    let mut source_tiers: HashMap<String, u8> = HashMap::new();
+    for tier in app.source_tiers {
+        ensure!(
+            source_tiers.insert(tier.label.clone(), tier.tier).is_none(),
+            "Multiple tiers given for source {}",
+            tier.label
+        );
+    }
+
+    // Weight of each source, by the label of the source.
+    let mut source_weights: HashMap<String, u32> = HashMap::new();
+    for weight in app.source_weights {
+        ensure!(
+            source_weights.insert(weight.label.clone(), weight.weight).is_none(),
+            "Multiple weights given for source {}",
+            weight.label
+        );
+    }
+    if !source_weights.is_empty() && !app.aggregation.is_weighted() {
+        log::warn!(
+            "Source weights are ignored, as the aggregation {} does not use them",
+            app.aggregation
+        );
+    }
+
+    log::info!("Using user agent: {}", app.user_agent);
+    let proxies = ProxyConfig {
+        http:  app.http_proxy,
+        https: app.https_proxy,
+    };
+    if let Some(url) = &proxies.http {
+        log::info!("Using http proxy: {}", url);
+    }
+    if let Some(url) = &proxies.https {
+        log::info!("Using https proxy: {}", url);
+    }
+
+    // Labels of the sources, which quote the rate in CCD/EUR.
+    let mut inverted_sources: HashSet<String> = app.inverted_sources.into_iter().collect();
+
+    let mut add_source = |source: Source,
+                          max_age: Option<u32>,
+                          max_rates: Option<usize>|
+     -> anyhow::Result<()> {
+        ensure!(
+            sources.iter().all(|handle| handle.label != source.to_string()),
+            "Multiple sources with the label {}",
+            source
+        );
+        let tier = source_tiers.remove(&source.to_string()).unwrap_or(1);
+        log::info!("{}: Source is in tier {}", source, tier);
+        let weight = source_weights.remove(&source.to_string()).unwrap_or(1);
+        if app.aggregation.is_weighted() {
+            log::info!("{}: Source has weight {}", source, weight);
+        }
+        let history_limit = match app.history_window {
+            Some(window) => {
+                log::info!("{}: Rates of the last {} seconds are saved", source, window);
+                HistoryLimit::Window(window)
+            }
+            None => {
+                let max_rates = match (max_rates, source_max_rates.remove(&source.to_string())) {
+                    (Some(_), Some(_)) => anyhow::bail!(
+                        "{}: Multiple numbers of rates saved given for the source",
+                        source
+                    ),
+                    (max_rates, override_max_rates) => {
+                        max_rates.or(override_max_rates).unwrap_or(max_rates_saved)
+                    }
+                };
+                log::info!("{}: Max {} rates are saved at a time", source, max_rates);
+                ensure!(max_rates > 0, "{}: At least one rate must be saved", source);
+                HistoryLimit::Count(max_rates)
+            }
+        };
+        let expected_readings = history_limit.expected_readings(pull_interval);
+        ensure!(
+            expected_readings >= app.min_readings_per_source,
+            "{}: At least `min-readings-per-source` rates must be saved",
+            source
+        );
+        // The history should cover a good part of the update interval, otherwise
+        // the median does little to protect against outliers.
+        let history_duration = history_limit.expected_duration(pull_interval);
+        history_window = history_window.max(history_duration);
+        if 2 * history_duration < u64::from(update_interval) {
+            log::warn!(
+                "{}: The history only covers {} seconds, which is much shorter than the update \
+                 interval of {} seconds",
+                source,
+                history_duration,
+                update_interval
+            );
+        }
+        match max_age {
+            Some(max_age) => {
+                ensure!(
+                    max_age >= pull_interval,
+                    "{}: Maximum age of readings ({}) must be at least the pull interval ({})",
+                    source,
+                    max_age,
+                    pull_interval
+                );
+                log::info!("{}: Readings are used for {} seconds", source, max_age);
+            }
+            None => log::info!("{}: Readings are used until the next update", source),
+        }
+        // The jitter must not delay readings past the maximum age.
+        let max_jitter = match max_age {
+            Some(max_age) => pull_jitter.min(Duration::from_secs((max_age - pull_interval).into())),
+            None => pull_jitter,
+        };
+        let client = match source_ca_certs.remove(&source.to_string()) {
+            Some(paths) => {
+                for path in paths.iter() {
+                    log::info!("{}: Trusting CA certificate {}", source, path.display());
+                }
+                get_client_with_ca_certificates(&paths, &app.user_agent, &proxies)?
+            }
+            None => get_client(&app.user_agent, &proxies)?,
+        };
+        let rates_mutex = Arc::new(Mutex::new(RateHistory::with_capacity(expected_readings)));
+        sources.push(SourceHandle {
+            label: source.to_string(),
+            max_age,
+            tier,
+            weight,
+            history: rates_mutex.clone(),
+        });
+
+        let extra_headers = source_headers.remove(&source.to_string()).unwrap_or_default();
+        let inverted = inverted_sources.remove(&source.to_string());
+        if inverted {
+            log::info!("{}: Source quotes the exchange rate in CCD/EUR", source);
+        }
+        if probe_sources {
+            probes.push((source.clone(), client.clone(), extra_headers.clone()));
+        }
+        source_tasks.insert(
+            source.to_string(),
+            tokio::spawn(pull_exchange_rate(
+                stats.clone(),
+                source,
+                client,
+                extra_headers,
+                inverted,
+                rates_mutex,
+                pull_interval,
+                max_jitter,
+                history_limit,
+                max_identical_readings,
+                db_conn_pool.clone(),
+            )),
+        );
+        Ok(())
     };
 
     if app.coin_gecko {
         log::info!("Using \"Coin Gecko\" as a source");
-        add_source(Source::CoinGecko)?
+        add_source(Source::CoinGecko, app.coin_gecko_max_age, app.coin_gecko_max_rates)?
     }
 
     if app.bitfinex {
         log::info!("Using \"BitFinex\" as a source");
-        add_source(Source::Bitfinex)?
+        add_source(Source::Bitfinex, app.bitfinex_max_age, app.bitfinex_max_rates)?
+    }
+
+    if app.bitfinex_ws {
+        log::info!("Using \"BitFinex\" websocket as a source");
+        add_source(Source::BitfinexWs, app.bitfinex_max_age, app.bitfinex_max_rates)?
+    }
+
+    if let Some(api_key) = app.coin_market_cap {
+        log::info!("Using \"Coin Market Cap\" as a source");
+        add_source(
+            Source::CoinMarketCap(api_key),
+            app.coin_market_cap_max_age,
+            app.coin_market_cap_max_rates,
+        )?
+    }
+
+    if let Some(api_key) = app.live_coin_watch {
+        log::info!("Using \"Live Coin Watch\" as a source");
+        add_source(
+            Source::LiveCoinWatch(api_key),
+            app.live_coin_watch_max_age,
+            app.live_coin_watch_max_rates,
+        )?
+    }
+
+    for (i, url) in app.test_sources.into_iter().enumerate() {
+        log::info!("Using test source: {}, as test{}", url, i);
+        add_source(
+            Source::Test {
+                url,
+                label: format!("test{}", i),
+            },
+            app.test_sources_max_age,
+            app.test_sources_max_rates,
+        )?
+    }
+
+    // Methods and bodies of the requests to generic sources, by the label of the
+    // source.
+    let mut generic_methods: HashMap<String, reqwest::Method> = HashMap::new();
+    for method in app.generic_source_methods {
+        ensure!(
+            generic_methods.insert(method.label.clone(), method.method).is_none(),
+            "Multiple methods given for source {}",
+            method.label
+        );
+    }
+    let mut generic_bodies: HashMap<String, serde_json::Value> = HashMap::new();
+    for body in app.generic_source_bodies {
+        ensure!(
+            generic_bodies.insert(body.label.clone(), body.body).is_none(),
+            "Multiple bodies given for source {}",
+            body.label
+        );
+    }
+
+    for generic in app.generic_sources {
+        let body = generic_bodies.remove(&generic.label);
+        let method = generic_methods.remove(&generic.label).unwrap_or(
+            if body.is_some() {
+                reqwest::Method::POST
+            } else {
+                reqwest::Method::GET
+            },
+        );
+        log::info!(
+            "Using generic source: {} {}, with pointer {}, as {}",
+            method,
+            generic.url,
+            generic.json_pointer,
+            generic.label
+        );
+        add_source(
+            Source::Generic {
+                url: generic.url,
+                json_pointer: generic.json_pointer,
+                label: generic.label,
+                method,
+                body,
+            },
+            None,
+            None,
+        )?
+    }
+    for derived in app.derived_sources {
+        log::info!(
+            "Using derived source: {}, with pointer {}, in the currency of {}, with pointer {}, \
+             as {}",
+            derived.base_url,
+            derived.base_pointer,
+            derived.fx_url,
+            derived.fx_pointer,
+            derived.label
+        );
+        add_source(derived.into_source(), None, None)?
+    }
+    let file_sources = match &app.sources_file {
+        Some(path) => read_sources_file(path)?,
+        None => Vec::new(),
+    };
+    for generic in file_sources.iter() {
+        log::info!(
+            "Using generic source from the sources file: {}, with pointer {}, as {}",
+            generic.url,
+            generic.json_pointer,
+            generic.label
+        );
+        add_source(generic.clone().into_source(), None, None)?
+    }
+    ensure!(
+        generic_methods.is_empty(),
+        "Methods were given for unknown generic sources: {:?}",
+        generic_methods.keys().collect::<Vec<_>>()
+    );
+    ensure!(
+        generic_bodies.is_empty(),
+        "Bodies were given for unknown generic sources: {:?}",
+        generic_bodies.keys().collect::<Vec<_>>()
+    );
+
+    ensure!(!sources.is_empty(), "At least one source must be chosen.");
+    stats.set_sources_configured(sources.len());
+    ensure!(
+        source_headers.is_empty(),
+        "Headers were given for unknown sources: {:?}",
+        source_headers.keys().collect::<Vec<_>>()
+    );
+    ensure!(
+        source_ca_certs.is_empty(),
+        "CA certificates were given for unknown sources: {:?}",
+        source_ca_certs.keys().collect::<Vec<_>>()
+    );
+    ensure!(
+        inverted_sources.is_empty(),
+        "Unknown sources were given as inverted: {:?}",
+        inverted_sources
+    );
+    ensure!(
+        source_max_rates.is_empty(),
+        "Numbers of rates saved were given for unknown sources: {:?}",
+        source_max_rates.keys().collect::<Vec<_>>()
+    );
+    ensure!(
+        source_tiers.is_empty(),
+        "Tiers were given for unknown sources: {:?}",
+        source_tiers.keys().collect::<Vec<_>>()
+    );
+    ensure!(
+        source_weights.is_empty(),
+        "Weights were given for unknown sources: {:?}",
+        source_weights.keys().collect::<Vec<_>>()
+    );
+    if probe_sources {
+        let results = join_all(probes.iter().map(|(source, client, extra_headers)| {
+            probe_source(
+                &stats,
+                source,
+                client.clone(),
+                extra_headers,
+                Duration::from_secs(SOURCE_PROBE_TIMEOUT),
+            )
+        }))
+        .await;
+        let unreachable = probes
+            .iter()
+            .zip(results)
+            .filter(|(_, reachable)| !reachable)
+            .map(|((source, _, _), _)| source.to_string())
+            .collect::<Vec<_>>();
+        ensure!(
+            unreachable.is_empty() || !app.require_sources_at_startup,
+            "Sources were not reachable at startup: {:?}",
+            unreachable
+        );
     }
+    // The sources file is read again on SIGHUP. Sources added to it are started
+    // with the default settings.
+    let mut sources_file = match app.sources_file {
+        Some(path) => Some(SourcesFile {
+            path,
+            sources: file_sources,
+            client: get_client(&app.user_agent, &proxies)?,
+            history_limit: match app.history_window {
+                Some(window) => HistoryLimit::Window(window),
+                None => HistoryLimit::Count(max_rates_saved),
+            },
+            pull_interval,
+            max_jitter: pull_jitter,
+            max_identical_readings,
+            db_conn_pool: db_conn_pool.clone(),
+        }),
+        None => None,
+    };
+    let mut hangup_listener = match sources_file {
+        Some(_) => Some(signal(SignalKind::hangup()).context("Unable to listen for SIGHUP.")?),
+        None => None,
+    };
 
-    if let Some(api_key) = app.coin_market_cap {
-        log::info!("Using \"Coin Market Cap\" as a source");
-        add_source(Source::CoinMarketCap(api_key))?
+    if app.run_once {
+        return print_rate_once(
+            &sources,
+            &stats,
+            &rate_pipeline,
+            last_update_timestamp,
+            app.once_pull_cycles,
+            pull_interval,
+        )
+        .await;
     }
 
-    if let Some(api_key) = app.live_coin_watch {
-        log::info!("Using \"Live Coin Watch\" as a source");
-        add_source(Source::LiveCoinWatch(api_key))?
+    let (mut node_client, mut node_index) = connect_with_backoff(
+        &stats,
+        &app.endpoint,
+        node_connect_timeout,
+        max_node_lag,
+        None,
+        app.max_connect_attempts,
+    )
+    .await?;
+    if let Err(e) = check_clock_skew(&mut node_client).await {
+        log::warn!("Unable to compare the local clock with the time of the node: {}", e);
     }
+    let parameters = node_client
+        .get_block_chain_parameters(v2::BlockIdentifier::LastFinal)
+        .await
+        .context("Unable to read the chain parameters of the last finalized block.")?;
+    let initial_rate = micro_ccd_per_euro(&parameters.response);
+    let mut prev_rate =
+        BigRational::new(initial_rate.numerator().into(), initial_rate.denominator().into());
+    log::debug!(
+        "Loaded initial block summary, current exchange rate: {}/{}  (~ {}) microCCD/EUR",
+        initial_rate.numerator(),
+        initial_rate.denominator(),
+        initial_rate.numerator() as f64 / initial_rate.denominator() as f64
+    );
+    stats.update_on_chain_rate(&prev_rate);
 
-    for (i, url) in app.test_sources.into_iter().enumerate() {
-        log::info!("Using test source: {}, as test{}", url, i);
-        add_source(Source::Test {
-            url,
-            label: format!("test{}", i),
-        })?
+    // Channel for triggering update cycles through the admin endpoint.
+    let (update_now_sender, mut update_now_receiver) = tokio::sync::mpsc::channel(1);
+    // Updates violating the warning threshold, which await confirmation.
+    let pending_confirmation: admin::PendingConfirmation = Arc::new(Mutex::new(None));
+    // Whether submitting updates has been paused through the admin endpoint.
+    let paused: admin::Paused = Arc::new(AtomicBool::new(false));
+    ensure!(
+        !app.require_confirmation || app.admin_port.is_some(),
+        "An admin port must be given to require confirmation of updates."
+    );
+    // The histories of the sources, shared with the admin endpoints.
+    let source_histories: admin::SourceHistories = Arc::new(Mutex::new(
+        sources.iter().map(|source| (source.label.clone(), source.history.clone())).collect(),
+    ));
+    match (app.admin_port, app.admin_token) {
+        (Some(port), Some(token)) => {
+            ensure!(!token.trim().is_empty(), "The admin token must not be empty.");
+            tokio::spawn(admin::serve_admin(
+                source_histories.clone(),
+                stats.clone(),
+                update_now_sender,
+                pending_confirmation.clone(),
+                paused.clone(),
+                token,
+                port,
+            ));
+            log::info!("Serving admin endpoints on port {}", port);
+        }
+        (Some(_), None) => anyhow::bail!("An admin token must be given to serve admin endpoints."),
+        (None, _) => (),
     }
 
-    ensure!(!rate_histories.is_empty(), "At least one source must be chosen.");
-
-    let forced_dry_run = is_dry_run_forced();
+    let forced_dry_run = is_dry_run_forced(&app.protected_state_file);
+    if let Err(e) = check_writable(&app.protected_state_file) {
+        // Without the file, the service cannot stay in protected mode across restarts.
+        ensure!(
+            app.dry_run || forced_dry_run,
+            "The file forcing dry run cannot be created at {}: {:#}",
+            app.protected_state_file.display(),
+            e
+        );
+        log::warn!(
+            "The file forcing dry run cannot be created at {}: {:#}",
+            app.protected_state_file.display(),
+            e
+        );
+    }
     if forced_dry_run {
-        log::warn!("Entering forced dry run. (No updates will performed)");
+        match read_protected_state(&app.protected_state_file) {
+            Ok(Some(state)) => {
+                log::warn!("Entering forced dry run, as {}. (No updates will performed)", state)
+            }
+            Ok(None) => log::warn!("Entering forced dry run. (No updates will performed)"),
+            Err(e) => log::error!("Entering forced dry run, for an unknown reason: {:#}", e),
+        }
     }
 
-    let mut signer = if app.dry_run || forced_dry_run {
+    if app.simulate_submit {
+        log::info!("Simulating submission, updates will be signed, but not sent.");
+    }
+    // Before arming the signer, check that the sources agree with the rate on
+    // chain, so a misconfigured source cannot cause the first update to halt:
+    let warmup_check_passed = if app.dry_run || forced_dry_run || app.skip_warmup_check {
+        true
+    } else {
+        log::info!(
+            "Waiting {} seconds for the histories to fill, before comparing the computed rate \
+             with the rate on chain",
+            history_window
+        );
+        wait_for_warmup(
+            &sources,
+            start + Duration::from_secs(history_window),
+            Duration::from_secs(update_interval.into()),
+        )
+        .await;
+        match rate_pipeline.first_rate(&sources, &stats, last_update_timestamp, &prev_rate) {
+            Some(candidate) => {
+                let change = relative_change(&prev_rate, &candidate);
+                let warning_threshold = if change.is_positive() {
+                    &warning_increase_threshold
+                } else {
+                    &warning_decrease_threshold
+                };
+                if &change.abs() > warning_threshold {
+                    log::error!(
+                        "The computed rate {} microCCD/Eur is a ~{} % change from the rate on \
+                         chain {} microCCD/Eur, which violates the warning threshold. Staying in \
+                         dry run, check the configuration of the sources, or restart with \
+                         `skip-warmup-check`.",
+                        candidate,
+                        format_change(&change),
+                        prev_rate
+                    );
+                    false
+                } else {
+                    log::info!(
+                        "The computed rate {} microCCD/Eur is a ~{} % change from the rate on \
+                         chain {} microCCD/Eur",
+                        candidate,
+                        format_change(&change),
+                        prev_rate
+                    );
+                    true
+                }
+            }
+            None => {
+                log::error!(
+                    "No rate could be computed to compare with the rate on chain. Staying in dry \
+                     run, check the sources, or restart with `skip-warmup-check`."
+                );
+                false
+            }
+        }
+    };
+    let mut signer = if app.dry_run || forced_dry_run || !warmup_check_passed {
         log::debug!("Running dry run!");
-        stats.set_protected();
+        let reason = if forced_dry_run {
+            "forced_file"
+        } else if app.dry_run {
+            "dry_run_flag"
+        } else {
+            "warmup_check"
+        };
+        stats.set_protected_with_reason(reason);
         None
     } else {
         log::debug!("Running wet run!");
-        let secret_keys = if app.local_keys.is_empty() {
-            ensure!(
-                !app.secret_names.is_empty(),
-                "If `dry-run` is not used then one of `secret-names` and `local-keys` must be \
-                 provided."
-            );
-            get_governance_from_aws(app.region, app.secret_names).await
-        } else {
-            get_governance_from_file(&app.local_keys)
-        }
-        .context("Could not obtain keys.")?;
         Some(
-            parameters
-                .response
-                .common_update_keys()
-                .construct_update_signer(
-                    &parameters.response.common_update_keys().micro_gtu_per_euro,
-                    secret_keys,
-                )
-                .context("Failed to obtain keys.")?,
+            load_signer(&app.local_keys, &app.region, &app.secret_names, &parameters.response)
+                .await?,
         )
     };
+    // The last rate read from chain, as the exact fraction stored there.
+    let mut last_on_chain_rate = initial_rate;
+    // The rate on chain, when protected mode was engaged. Protected mode is left,
+    // once the rate on chain has been corrected. This is not done, when dry run
+    // is requested with `dry-run`.
+    let mut protected_at_rate = if forced_dry_run && !app.dry_run {
+        Some(initial_rate)
+    } else {
+        None
+    };
 
     let update_interval_duration = Duration::from_secs(app.update_interval.into());
-    let mut interval =
-        interval_at(Instant::now() + update_interval_duration, update_interval_duration);
+    let max_update_gap = Duration::from_secs(
+        app.max_update_gap
+            .unwrap_or(DEFAULT_MAX_UPDATE_GAP_INTERVALS * u64::from(app.update_interval)),
+    );
+    ensure!(
+        max_update_gap >= update_interval_duration,
+        "The maximum update gap must be at least the update interval"
+    );
+    // Finalization takes a while after the update cycle started, so the default
+    // leaves room for it within the update interval.
+    let min_update_spacing = Duration::from_secs(
+        app.min_update_spacing.unwrap_or_else(|| (app.update_interval / 2).into()),
+    );
+    // When the last update was finalized.
+    let mut last_update_finalized: Option<Instant> = None;
+    // When the last update was finalized, or the service was last in dry run, or
+    // skipped an update, as the change was below the minimum change threshold.
+    let mut last_finalized_update = Instant::now();
+    let first_update = match app.warmup_seconds {
+        Some(warmup_seconds) => {
+            log::info!("Warming up for at least {} seconds", warmup_seconds);
+            wait_for_warmup(
+                &sources,
+                start + Duration::from_secs(warmup_seconds),
+                update_interval_duration,
+            )
+            .await;
+            log::info!("Warmup took {} seconds", start.elapsed().as_secs());
+            Instant::now()
+        }
+        None => Instant::now() + update_interval_duration,
+    };
+    let mut interval = interval_at(first_update, update_interval_duration);
     interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
     // Main Loop
@@ -396,197 +2612,1122 @@ async fn main() -> anyhow::Result<()> {
     log::info!("Entering main loop");
     'main: loop {
         log::debug!("Starting new main loop cycle: waiting for interval");
-        interval.tick().await;
-        let rate = {
-            // For each source, we compute the median of their history:
-            let rate_medians = rate_histories
-                .iter()
-                .map(|rates_mutex| {
-                    let rates_history = rates_mutex.lock().unwrap();
-                    if rates_history.last_reading_timestamp == 0 {
-                        log::warn!("A source was dropped for update, no successful readings");
-                        None
-                    } else if rates_history.last_reading_timestamp < last_update_timestamp {
-                        let datetime = chrono::DateTime::from_timestamp(
-                            rates_history.last_reading_timestamp,
-                            0,
-                        )
-                        .map_or_else(
-                            || {
-                                format!(
-                                    "{} (timestamp due to conversion error)",
-                                    rates_history.last_reading_timestamp
-                                )
-                            },
-                            |dt| dt.to_string(),
+        // If the cycle is triggered through the admin endpoint, the outcome is
+        // reported back.
+        let (cycle_start, mut trigger) = tokio::select! {
+            tick = interval.tick() => (tick, None),
+            Some(reply) = update_now_receiver.recv() => (Instant::now(), Some(reply)),
+            // Reloads are performed between update cycles, so they never overlap each
+            // other or an update cycle.
+            Some(()) = hangup(&mut hangup_listener) => {
+                if let Some(sources_file) = sources_file.as_mut() {
+                    log::info!("Reloading the sources file {}", sources_file.path.display());
+                    if let Err(e) = sources_file.reload(
+                        &stats,
+                        &mut sources,
+                        &mut source_tasks,
+                        &source_histories,
+                    ) {
+                        log::error!("Unable to reload the sources, keeping them: {:#}", e);
+                    }
+                }
+                continue 'main;
+            }
+            // A decision on the held update starts an update cycle right away, in which
+            // the rate is recomputed and checked again, before it is submitted.
+            confirmed = held_decision(&mut held_update) => {
+                let Some(held) = held_update.take() else {
+                    continue 'main;
+                };
+                if !confirmed {
+                    log::warn!("Update {} was rejected, skipping it", held.id);
+                    stats.increment_confirmations("rejected");
+                    continue 'main;
+                }
+                log::info!("Update {} was confirmed, recomputing the update", held.id);
+                stats.increment_confirmations("approved");
+                confirmed_update = Some((held.id, held.rate, held.expires));
+                (Instant::now(), None)
+            }
+        };
+        // Drop the held or confirmed update, once it has expired:
+        if held_update.as_ref().map_or(false, |held| held.expires <= cycle_start) {
+            if let Some(held) = held_update.take() {
+                pending_confirmation.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+                log::warn!("Update {} expired without confirmation, dropping it", held.id);
+                stats.increment_confirmations("expired");
+            }
+        }
+        if confirmed_update.as_ref().map_or(false, |(_, _, expires)| *expires <= cycle_start) {
+            if let Some((id, _, _)) = confirmed_update.take() {
+                log::warn!("Update {} was confirmed, but not submitted before it expired", id);
+            }
+        }
+        // Check whether the update is overdue:
+        if signer.is_none() || paused.load(Ordering::SeqCst) {
+            last_finalized_update = Instant::now();
+        }
+        let overdue = last_finalized_update.elapsed() > max_update_gap;
+        stats.set_update_overdue(overdue);
+        if overdue {
+            log::error!(
+                "No update has been finalized for {} seconds (maximum update gap: {} seconds)",
+                last_finalized_update.elapsed().as_secs(),
+                max_update_gap.as_secs()
+            );
+            ensure!(!app.exit_on_overdue, "Exiting, as the update is overdue");
+        }
+        // Reconcile the rate with the chain, in case another party has updated the
+        // rate. (In protected mode, the rate on chain is checked separately)
+        if protected_at_rate.is_none() {
+            match read_chain_rate(&mut node_client).await {
+                Ok(on_chain) => {
+                    last_on_chain_rate = on_chain;
+                    let on_chain_rate = BigRational::new(
+                        on_chain.numerator().into(),
+                        on_chain.denominator().into(),
+                    );
+                    stats.update_on_chain_rate(&on_chain_rate);
+                    if on_chain_rate != prev_rate {
+                        log::warn!(
+                            "THE RATE ON CHAIN IS {} microCCD/Eur, BUT {} WAS EXPECTED. It was \
+                             updated by another party, or by an update of ours, whose \
+                             finalization was not confirmed. Using the rate on chain.",
+                            on_chain_rate,
+                            prev_rate
                         );
+                        prev_rate = on_chain_rate;
+                    }
+                }
+                Err(e) => log::warn!("Unable to read the rate on chain: {}", e),
+            }
+        }
+        let (rate, used_sources, source_spread, agreeing_sources) =
+            match rate_pipeline.candidate(&sources, &stats, last_update_timestamp) {
+                Some(candidate) => {
+                    failed_cycles = 0;
+                    stats.set_failed_cycles(failed_cycles);
+                    let used_sources = candidate.used_sources();
+                    last_computed_rate = Some((candidate.rate.clone(), used_sources.clone()));
+                    (
+                        candidate.rate,
+                        used_sources,
+                        candidate.source_spread,
+                        Some(candidate.agreeing_sources),
+                    )
+                }
+                None => {
+                    failed_cycles += 1;
+                    stats.set_failed_cycles(failed_cycles);
+                    match (&last_computed_rate, app.stale_fallback_cycles) {
+                        (Some((last_rate, last_sources)), Some(max_cycles))
+                            if failed_cycles <= max_cycles =>
+                        {
+                            log::warn!(
+                                "Unable to compute rate for update, using the last computed rate \
+                                 {} instead ({} of {} fallback cycles)",
+                                last_rate,
+                                failed_cycles,
+                                max_cycles
+                            );
+                            (last_rate.clone(), last_sources.clone(), None, None)
+                        }
+                        _ => {
+                            log::error!(
+                                "Unable to compute rate for update ({} consecutive cycles)",
+                                failed_cycles
+                            );
+                            report_outcome(&mut trigger, Err("Unable to compute rate.".into()));
+                            record_failed_cycle(
+                                &stats,
+                                &mut consecutive_failures,
+                                app.max_consecutive_failures,
+                            );
+                            continue;
+                        }
+                    }
+                }
+            };
+        log::debug!("Computed rate: {} microCCD/Eur", rate);
+        let computed_rate = rate.clone();
+
+        // Check whether the sources disagree too much, which indicates that some of
+        // them are broken:
+        if let Some(spread) = source_spread.as_ref() {
+            if halt_source_spread.as_ref().map_or(false, |bound| spread > bound) {
+                log::error!(
+                    "Sources disagree too much, their medians spread ~{} %, which exceeds \
+                     `halt-source-spread` (forcing dry run)",
+                    spread.round()
+                );
+                stats.increment_source_spread_halts();
+                engage_protection(
+                    &app.protected_state_file,
+                    db_conn_pool.as_ref(),
+                    &stats,
+                    ProtectedState {
+                        timestamp:     chrono::offset::Utc::now(),
+                        previous_rate: prev_rate.to_string(),
+                        proposed_rate: rate.to_string(),
+                        change:        format_change(&relative_change(&prev_rate, &rate)),
+                        violated:      "halt-source-spread".into(),
+                    },
+                );
+                signer = None;
+                smooth_towards_prev_rate = false;
+                if !app.dry_run {
+                    protected_at_rate.get_or_insert(last_on_chain_rate);
+                }
+                stats.set_protected_with_reason("source_spread");
+                report_outcome(&mut trigger, Err("Halt source spread exceeded.".into()));
+                continue;
+            }
+            if max_source_spread.as_ref().map_or(false, |bound| spread > bound) {
+                log::error!(
+                    "Skipping update, sources disagree too much, their medians spread ~{} %, \
+                     which exceeds `max-source-spread`",
+                    spread.round()
+                );
+                stats.increment_source_spread_skips();
+                report_outcome(&mut trigger, Err("Maximum source spread exceeded.".into()));
+                continue;
+            }
+        }
+        // Require enough sources to agree, so a single compromised source cannot move
+        // the rate:
+        if let (Some(agreeing), Some(min_agreeing)) = (agreeing_sources, app.min_agreeing_sources) {
+            if agreeing < min_agreeing {
+                log::warn!(
+                    "Skipping update, only {} sources agree within {} % of the median of the \
+                     sources ({} required)",
+                    agreeing,
+                    app.agreement_tolerance,
+                    min_agreeing
+                );
+                stats.increment_agreement_skips();
+                report_outcome(&mut trigger, Err("Too few sources agree.".into()));
+                continue;
+            }
+        }
 
+        let rate = rate_pipeline.smooth(
+            rate,
+            &mut smoothed_rate,
+            smooth_towards_prev_rate.then_some(&prev_rate),
+        );
+        stats.update_computed_rate(&computed_rate);
+        stats.update_smoothed_rate(&rate);
+
+        // While in protected mode, check whether the rate on chain has been corrected,
+        // in which case updates are resumed:
+        if let Some(halted) = protected_at_rate {
+            match node_client.get_block_chain_parameters(v2::BlockIdentifier::LastFinal).await {
+                Ok(parameters) => {
+                    let on_chain = micro_ccd_per_euro(&parameters.response);
+                    last_on_chain_rate = on_chain;
+                    let halted_rate =
+                        BigRational::new(halted.numerator().into(), halted.denominator().into());
+                    let on_chain_rate = BigRational::new(
+                        on_chain.numerator().into(),
+                        on_chain.denominator().into(),
+                    );
+                    let change = relative_change(&on_chain_rate, &rate);
+                    let warning_threshold = if change.is_positive() {
+                        &warning_increase_threshold
+                    } else {
+                        &warning_decrease_threshold
+                    };
+                    let violated_bound = violated_rate_bound(
+                        &rate,
+                        min_rate_bound.as_ref(),
+                        max_rate_bound.as_ref(),
+                    );
+                    let spread_exceeded =
+                        match (source_spread.as_ref(), halt_source_spread.as_ref()) {
+                            (Some(spread), Some(bound)) => spread > bound,
+                            _ => false,
+                        };
+                    if on_chain == halted {
+                        log::warn!(
+                            "In protected mode, the rate on chain is still {} microCCD/Eur",
+                            on_chain_rate
+                        );
+                    } else if let Some(bound) = violated_bound {
+                        log::warn!(
+                            "In protected mode, the rate on chain was changed to {}, but the \
+                             computed rate {} still violates `{}`",
+                            on_chain_rate,
+                            rate,
+                            bound
+                        );
+                    } else if spread_exceeded {
+                        log::warn!(
+                            "In protected mode, the rate on chain was changed to {}, but the \
+                             sources still spread more than `halt-source-spread`",
+                            on_chain_rate
+                        );
+                    } else if &abs_relative_change(&on_chain_rate, &rate) > warning_threshold {
                         log::warn!(
-                            "A source was dropped for update, last succesful reading was at {}",
-                            datetime,
+                            "In protected mode, the rate on chain was changed to {}, but the \
+                             computed rate {} is a ~{} % change from it",
+                            on_chain_rate,
+                            rate,
+                            format_change(&change)
                         );
-                        None
                     } else {
-                        compute_median(&rates_history.rates)
+                        let recovery = async {
+                            let keys = load_signer(
+                                &app.local_keys,
+                                &app.region,
+                                &app.secret_names,
+                                &parameters.response,
+                            )
+                            .await?;
+                            let cleared = read_protected_state(&app.protected_state_file)
+                                .unwrap_or_else(|e| {
+                                    log::warn!("{:#}", e);
+                                    None
+                                });
+                            clear_forced_dry_run(&app.protected_state_file)
+                                .context("Unable to remove the file forcing dry run.")?;
+                            anyhow::Ok((keys, cleared))
+                        };
+                        match recovery.await {
+                            Ok((keys, cleared)) => {
+                                log::warn!(
+                                    "LEAVING PROTECTED MODE: the rate on chain was corrected from \
+                                     {} to {} microCCD/Eur, and the computed rate {} is within \
+                                     the warning threshold of it and the rate bounds. Resuming \
+                                     updates.",
+                                    halted_rate,
+                                    on_chain_rate,
+                                    rate
+                                );
+                                signer = Some(keys);
+                                prev_rate = on_chain_rate;
+                                // The state is unknown, if the service was forced into dry
+                                // run by an earlier version.
+                                let cleared = cleared.unwrap_or_else(|| ProtectedState {
+                                    timestamp:     chrono::offset::Utc::now(),
+                                    previous_rate: halted_rate.to_string(),
+                                    proposed_rate: prev_rate.to_string(),
+                                    change:        format_change(&relative_change(
+                                        &halted_rate,
+                                        &prev_rate,
+                                    )),
+                                    violated:      "unknown".into(),
+                                });
+                                record_protection_event(
+                                    db_conn_pool.as_ref(),
+                                    &stats,
+                                    ProtectionEvent::Cleared,
+                                    &cleared,
+                                );
+                                protected_at_rate = None;
+                                stats.leave_protected();
+                            }
+                            Err(e) => log::error!(
+                                "The rate on chain was corrected, but protected mode could not be \
+                                 left: {:#}",
+                                e
+                            ),
+                        }
                     }
-                })
-                .filter(|rm| rm.is_some())
-                .collect::<Option<VecDeque<_>>>();
-            // Then we determine the median of the medians:
-            match rate_medians.and_then(|rm| {
-                if rm.is_empty() {
-                    log::error!("Skipping update, due to no sources having new readings");
-                    None
-                } else {
-                    compute_median(&rm)
                 }
-            }) {
-                Some(r) => r * &million, /* multiply with 1000000 microCCD/CCD to convert the */
-                // unit to microCCD/Eur
-                None => {
-                    log::error!("Unable to compute median for update");
-                    continue;
+                Err(e) => {
+                    log::error!("In protected mode, unable to query the rate on chain: {}", e)
                 }
             }
-        }; // drop lock
-        log::debug!("Computed median: {} microCCD/Eur", rate);
+        }
 
         // Update the timestamp for the next update
         last_update_timestamp = chrono::offset::Utc::now().timestamp();
 
+        // Clamp the change, before it is checked against the thresholds, and round the
+        // rate:
+        let (rate, clamped) = rate_pipeline.limit(rate, Some(&prev_rate));
+        if clamped {
+            stats.increment_clamped_updates();
+        }
+
         // Calculates the relative change from the prev_rate, which should be the
         // current exchange rate on chain, and our proposed update:
-        let diff = relative_change(&prev_rate, &rate);
-        if rate > prev_rate {
-            // Rate has increased
-            if diff > halt_increase_threshold {
+        let change = relative_change(&prev_rate, &rate);
+        let diff = abs_relative_change(&prev_rate, &rate);
+        // Calculates the relative change from the baseline, which the thresholds are
+        // checked against:
+        let baseline = match app.threshold_baseline {
+            ThresholdBaseline::LastUpdate => prev_rate.clone(),
+            ThresholdBaseline::Trailing24h => trailing_baseline(
+                db_conn_pool.as_ref(),
+                &mut recent_updates,
+                chrono::offset::Utc::now() - chrono::Duration::seconds(TRAILING_BASELINE_WINDOW),
+            )
+            .unwrap_or_else(|| prev_rate.clone()),
+        };
+        let baseline_change = relative_change(&baseline, &rate);
+        let baseline_diff = abs_relative_change(&baseline, &rate);
+        log::info!(
+            "Threshold baseline ({}): {} microCCD/Eur, changing to {} is a ~{} % change",
+            app.threshold_baseline,
+            baseline,
+            rate,
+            format_change(&baseline_change)
+        );
+        let (halt_threshold, warning_threshold, halt_reason) = if baseline_change.is_positive() {
+            (&halt_increase_threshold, &warning_increase_threshold, "halt_increase")
+        } else {
+            (&halt_decrease_threshold, &warning_decrease_threshold, "halt_decrease")
+        };
+        if &baseline_diff <= halt_threshold {
+            halt_violation_streak = 0;
+            stats.set_halt_violation_streak(halt_violation_streak);
+        }
+        // Force an update to prove the service is alive, after too many skipped
+        // updates:
+        let heartbeat_due = app.heartbeat_updates.map_or(false, |n| consecutive_skips >= n);
+        let mut heartbeat = false;
+        if diff < min_change_threshold && heartbeat_due {
+            heartbeat = true;
+        } else if diff < min_change_threshold {
+            log::info!(
+                "Change below threshold, skipping: changing from {} to {} is a ~{} % change",
+                prev_rate,
+                rate,
+                format_change(&change)
+            );
+            stats.increment_skipped_updates();
+            consecutive_skips += 1;
+            last_finalized_update = Instant::now();
+            report_outcome(&mut trigger, Err("Change below threshold, skipping.".into()));
+            continue;
+        }
+        if &baseline_diff > halt_threshold {
+            stats.increment_halt_threshold_violations();
+            halt_violation_streak += 1;
+            stats.set_halt_violation_streak(halt_violation_streak);
+            if app.halt_action == HaltAction::Skip {
                 log::error!(
-                    "New update violates halt threshold, changing from {} to {} is an ~{} % \
-                     increase (forcing dry run)",
-                    prev_rate,
+                    "New update violates halt threshold, changing from {} to {} is a ~{} % change \
+                     (skipping this update)",
+                    baseline,
                     rate,
-                    diff.round()
+                    format_change(&baseline_change)
                 );
-                force_dry_run();
-                signer = None;
-                stats.set_protected();
-                continue;
-            } else if diff > warning_increase_threshold {
-                log::warn!(
-                    "New update violates warning threshold, changing from {} to {} has ~{} % \
-                     increase",
-                    prev_rate,
-                    rate,
-                    diff.round()
+                report_outcome(
+                    &mut trigger,
+                    Err("Halt threshold violated, skipping this update.".into()),
                 );
-                stats.increment_warning_threshold_violations();
+                continue;
             }
-        } else {
-            // Rate has decreased
-            if diff > halt_decrease_threshold {
+            if halt_violation_streak < app.halt_after_consecutive {
                 log::error!(
-                    "New update violates halt threshold, changing from {} to {} has ~{} % \
-                     decrease (forcing dry run)",
-                    prev_rate,
+                    "New update violates halt threshold, changing from {} to {} is a ~{} % change \
+                     (skipping this update, {} of {} consecutive violations force dry run)",
+                    baseline,
                     rate,
-                    diff.round()
+                    format_change(&baseline_change),
+                    halt_violation_streak,
+                    app.halt_after_consecutive
                 );
-                force_dry_run();
-                signer = None;
-                stats.set_protected();
+                report_outcome(
+                    &mut trigger,
+                    Err("Halt threshold violated, skipping this update.".into()),
+                );
+                continue;
+            }
+            log::error!(
+                "New update violates halt threshold, changing from {} to {} is a ~{} % change \
+                 (forcing dry run)",
+                baseline,
+                rate,
+                format_change(&baseline_change)
+            );
+            engage_protection(
+                &app.protected_state_file,
+                db_conn_pool.as_ref(),
+                &stats,
+                ProtectedState {
+                    timestamp:     chrono::offset::Utc::now(),
+                    previous_rate: prev_rate.to_string(),
+                    proposed_rate: rate.to_string(),
+                    change:        format_change(&change),
+                    violated:      halt_reason.replace('_', "-") + "-threshold",
+                },
+            );
+            signer = None;
+            smooth_towards_prev_rate = false;
+            if !app.dry_run {
+                protected_at_rate.get_or_insert(last_on_chain_rate);
+            }
+            stats.set_protected_with_reason(halt_reason);
+            report_outcome(&mut trigger, Err("Halt threshold violated.".into()));
+            continue;
+        }
+        let violates_warning_threshold = &baseline_diff > warning_threshold;
+        if violates_warning_threshold {
+            log::warn!(
+                "New update violates warning threshold, changing from {} to {} is a ~{} % change",
+                baseline,
+                rate,
+                format_change(&baseline_change)
+            );
+            stats.increment_warning_threshold_violations();
+        }
+
+        // Limit the sum of the changes of the updates in the last 24 hours:
+        let rate = match max_daily_change.as_ref() {
+            Some(max_percent) => {
+                let since =
+                    chrono::offset::Utc::now() - chrono::Duration::seconds(DAILY_CHANGE_WINDOW);
+                while daily_changes.front().map_or(false, |(time, _)| *time < since) {
+                    daily_changes.pop_front();
+                }
+                let cumulative =
+                    daily_changes.iter().fold(BigRational::zero(), |sum, (_, change)| sum + change);
+                stats.update_daily_change(&cumulative);
+                match limit_cumulative_change(&prev_rate, &rate, &cumulative, max_percent) {
+                    None => rate,
+                    Some(limited)
+                        if rate_pipeline.clamp_change_percent.is_some() && limited != prev_rate =>
+                    {
+                        log::warn!(
+                            "Limited rate from {} to {} microCCD/Eur, as the changes in the last \
+                             24 hours would exceed {} % (cumulative change: ~{} %)",
+                            rate,
+                            limited,
+                            max_percent,
+                            format_change(&cumulative)
+                        );
+                        stats.increment_daily_change_limited();
+                        match app.rate_precision {
+                            Some(digits) => round_rational(&limited, digits),
+                            None => limited,
+                        }
+                    }
+                    Some(_) => {
+                        log::warn!(
+                            "Skipping update, changing from {} to {} would make the changes in \
+                             the last 24 hours exceed {} % (cumulative change: ~{} %)",
+                            prev_rate,
+                            rate,
+                            max_percent,
+                            format_change(&cumulative)
+                        );
+                        stats.increment_daily_change_limited();
+                        report_outcome(
+                            &mut trigger,
+                            Err("Maximum daily change exceeded, skipping.".into()),
+                        );
+                        continue;
+                    }
+                }
+            }
+            None => rate,
+        };
+
+        // Convert the rate into an ExchangeRate (i.e. convert the bigints to u64's).
+        let conversion = match max_conversion_error.as_ref() {
+            Some(epsilon) => convert_with_max_error(&rate, epsilon),
+            None => convert_big_fraction_to_exchange_rate(&rate),
+        };
+        let new_rate = match conversion {
+            Ok(new_rate) => new_rate,
+            Err(e) => {
+                log::error!("Unable to convert rate for update, skipping this update: {}", e);
+                stats.increment_failed_conversions();
+                report_outcome(&mut trigger, Err(format!("Unable to convert rate: {}", e)));
+                continue;
+            }
+        };
+        log::debug!(
+            "Converted new_rate: {:?}, with relative approximation error: {}",
+            new_rate,
+            relative_error(&rate, &new_rate)
+        );
+        // Exposed also in dry run, where no update is performed:
+        stats.update_candidate_rate(&rate);
+        // Compare the fractions, as they are stored on chain, since the previous rate
+        // may be more precise than the converted rate:
+        if new_rate == last_on_chain_rate {
+            if heartbeat_due {
+                heartbeat = true;
+            } else {
+                log::info!(
+                    "Rate is identical to the rate on chain, skipping: {}/{} microCCD/Eur",
+                    new_rate.numerator(),
+                    new_rate.denominator()
+                );
+                stats.increment_identical_updates_skipped();
+                consecutive_skips += 1;
+                last_finalized_update = Instant::now();
+                report_outcome(&mut trigger, Err("Rate is identical, skipping.".into()));
                 continue;
-            } else if diff > warning_decrease_threshold {
+            }
+        }
+        if heartbeat {
+            log::info!("Forcing a heartbeat update, after {} skipped updates", consecutive_skips);
+            stats.increment_heartbeat_updates();
+        }
+
+        // Check the absolute bounds, right before the update is submitted:
+        if let Some(bound) =
+            violated_rate_bound(&rate, min_rate_bound.as_ref(), max_rate_bound.as_ref())
+        {
+            log::error!(
+                "New update violates `{}`, a rate of {} microCCD/Eur values a CCD at ~{} EUR \
+                 (forcing dry run)",
+                bound,
+                rate,
+                (&million / &rate).to_f64().unwrap_or(f64::NAN)
+            );
+            stats.increment_rate_bound_violations();
+            engage_protection(
+                &app.protected_state_file,
+                db_conn_pool.as_ref(),
+                &stats,
+                ProtectedState {
+                    timestamp:     chrono::offset::Utc::now(),
+                    previous_rate: prev_rate.to_string(),
+                    proposed_rate: rate.to_string(),
+                    change:        format_change(&relative_change(&prev_rate, &rate)),
+                    violated:      bound.into(),
+                },
+            );
+            signer = None;
+            smooth_towards_prev_rate = false;
+            if !app.dry_run {
+                protected_at_rate.get_or_insert(last_on_chain_rate);
+            }
+            stats.set_protected_with_reason("rate_bound");
+            report_outcome(&mut trigger, Err("Rate bound violated.".into()));
+            continue;
+        }
+
+        // Guard against submitting updates too close to each other:
+        if let Some(last_update_finalized) = last_update_finalized.filter(|_| signer.is_some()) {
+            let spacing = cycle_start.saturating_duration_since(last_update_finalized);
+            if spacing < min_update_spacing {
                 log::warn!(
-                    "New update violates warning threshold, changing from {} to {} has ~{} % \
-                     decrease",
-                    prev_rate,
-                    rate,
-                    diff.round()
+                    "Skipping update, as the last update was only {} seconds ago (minimum update \
+                     spacing: {} seconds)",
+                    spacing.as_secs(),
+                    min_update_spacing.as_secs()
                 );
-                stats.increment_warning_threshold_violations();
+                report_outcome(
+                    &mut trigger,
+                    Err("Minimum update spacing not reached, skipping.".into()),
+                );
+                continue;
             }
         }
 
-        // Convert the rate into an ExchangeRate (i.e. convert the bigints to u64's).
-        let new_rate = convert_big_fraction_to_exchange_rate(&rate);
-        log::debug!("Converted new_rate: {:?}", new_rate);
+        // Skip submitting the update, while paused through the admin endpoint:
+        if paused.load(Ordering::SeqCst) && signer.is_some() {
+            log::warn!("Submitting updates is paused, skipping update to {} microCCD/Eur", rate);
+            report_outcome(&mut trigger, Err("Submitting updates is paused.".into()));
+            continue;
+        }
+
+        // Hold updates violating the warning threshold, until they are confirmed. The
+        // update cycles go on while an update is held, and a confirmed update is only
+        // submitted, once it has been recomputed and has passed all checks again.
+        if app.require_confirmation && violates_warning_threshold && signer.is_some() {
+            let confirmed = confirmed_update.take().filter(|(id, confirmed_rate, _)| {
+                let confirmed_change = relative_change(&baseline, confirmed_rate);
+                let covered = baseline_change.signum() == confirmed_change.signum()
+                    && baseline_change.abs() <= confirmed_change.abs();
+                if !covered {
+                    log::warn!(
+                        "The recomputed rate {} microCCD/Eur changes more than the rate {} of the \
+                         confirmed update {}, so it must be confirmed again",
+                        rate,
+                        confirmed_rate,
+                        id
+                    );
+                }
+                covered
+            });
+            match (confirmed, held_update.as_ref()) {
+                (Some((id, confirmed_rate, _)), _) => log::info!(
+                    "Submitting the recomputed rate {} microCCD/Eur of update {}, which was \
+                     confirmed at {} microCCD/Eur",
+                    rate,
+                    id,
+                    confirmed_rate
+                ),
+                (None, Some(held)) => {
+                    log::warn!(
+                        "Update {} is still awaiting confirmation, skipping this update",
+                        held.id
+                    );
+                    report_outcome(
+                        &mut trigger,
+                        Err(format!("Update {} is awaiting confirmation.", held.id)),
+                    );
+                    continue;
+                }
+                (None, None) => {
+                    confirmation_id += 1;
+                    let (decision_sender, decision) = tokio::sync::oneshot::channel();
+                    let update = admin::PendingUpdate {
+                        id:      confirmation_id,
+                        rate:    rate.to_string(),
+                        change:  format_change(&baseline_change),
+                        expires: chrono::offset::Utc::now()
+                            + chrono::Duration::seconds(update_interval.into()),
+                    };
+                    log::warn!(
+                        "Holding update {} to {} microCCD/Eur (~{} %) until it is confirmed, or \
+                         it expires at {}",
+                        update.id,
+                        update.rate,
+                        update.change,
+                        update.expires
+                    );
+                    *pending_confirmation.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) =
+                        Some((update, decision_sender));
+                    held_update = Some(HeldUpdate {
+                        id: confirmation_id,
+                        rate: rate.clone(),
+                        decision,
+                        expires: cycle_start + update_interval_duration,
+                    });
+                    report_outcome(
+                        &mut trigger,
+                        Err(format!("Update {} is held until it is confirmed.", confirmation_id)),
+                    );
+                    continue;
+                }
+            }
+        } else if let Some(held) = held_update.take() {
+            // The recomputed update no longer needs confirmation:
+            pending_confirmation.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+            log::info!(
+                "Update {} no longer needs confirmation, withdrawing it, and submitting the \
+                 recomputed rate",
+                held.id
+            );
+        }
 
         if let Some(signer) = signer.as_ref() {
+            if app.simulate_submit {
+                match simulate_update(signer, new_rate, &submission_settings, &mut node_client)
+                    .await
+                {
+                    Ok(block_item) => {
+                        // A simulated update stands in for a finalized one, so the update is
+                        // not reported as overdue:
+                        last_finalized_update = Instant::now();
+                        consecutive_failures = 0;
+                        stats.set_consecutive_failures(consecutive_failures);
+                        log::info!(
+                            "Simulating submission, so not sending the update with transaction \
+                             hash {}. New rate: {}/{}",
+                            block_item.hash(),
+                            new_rate.numerator(),
+                            new_rate.denominator()
+                        );
+                        log::debug!("Update, which was not sent: {:?}", block_item);
+                        report_outcome(
+                            &mut trigger,
+                            Ok(format!(
+                                "Simulated update with transaction hash {}",
+                                block_item.hash()
+                            )),
+                        );
+                    }
+                    Err(e) => {
+                        log::error!("Unable to simulate the update: {:#}", e);
+                        report_outcome(
+                            &mut trigger,
+                            Err(format!("Unable to simulate the update: {:#}", e)),
+                        );
+                    }
+                }
+                continue;
+            }
             // Send the update to a node. This loop only terminates if the node accepts the
-            // transaction or we can't connect to any node
-            let (submission_id, new_seq_number) = {
+            // transaction, the sequence number cannot be obtained, or we can't connect to
+            // any node
+            let broadcast_clients = if app.broadcast_all {
+                connect_all(app.endpoint.clone(), node_connect_timeout).await
+            } else {
+                Vec::new()
+            };
+            let (submission_id, effective_time) = {
                 loop {
                     // Try to send the update
-                    if let Some(result) =
-                        send_update(&stats, seq_number, signer, new_rate, node_client.clone()).await
+                    match send_update(
+                        &stats,
+                        signer,
+                        new_rate,
+                        &submission_settings,
+                        node_client.clone(),
+                        &broadcast_clients,
+                    )
+                    .await
                     {
-                        break result;
-                    };
+                        Ok(Some(result)) => break result,
+                        Ok(None) => (),
+                        Err(e) => {
+                            log::error!("{:#}, skipping this update", e);
+                            report_outcome(&mut trigger, Err(format!("{:#}", e)));
+                            record_failed_cycle(
+                                &stats,
+                                &mut consecutive_failures,
+                                app.max_consecutive_failures,
+                            );
+                            continue 'main;
+                        }
+                    }
                     // We expect that connection/authentication problems would be the reason sending
                     // the update failed, so we try to connect to a new node.
                     // (Any other problem would be have to be fixed manually)
-                    node_client = match get_node_client(app.endpoint.clone()).await {
-                        Ok(client) => client,
+                    node_client = match connect_with_backoff(
+                        &stats,
+                        &app.endpoint,
+                        node_connect_timeout,
+                        max_node_lag,
+                        Some(node_index),
+                        app.max_connect_attempts,
+                    )
+                    .await
+                    {
+                        Ok((client, index)) => {
+                            node_index = index;
+                            client
+                        }
                         Err(e) => {
                             log::error!(
                                 "Unable to connect to any node: {}, skipping this update",
                                 e
                             );
+                            report_outcome(
+                                &mut trigger,
+                                Err(format!("Unable to connect to any node: {}", e)),
+                            );
+                            record_failed_cycle(
+                                &stats,
+                                &mut consecutive_failures,
+                                app.max_consecutive_failures,
+                            );
                             continue 'main;
                         }
                     };
                 }
             };
-            log::info!("Sent update with submission id: {}", submission_id);
+            // An effective time of 0 means that the update takes effect immediately.
+            let effective_at = if effective_time.seconds == 0 {
+                None
+            } else {
+                chrono::DateTime::from_timestamp(effective_time.seconds as i64, 0)
+            };
+            match effective_at {
+                Some(at) => log::info!(
+                    "Sent update with submission id: {}, taking effect at {}",
+                    submission_id,
+                    at
+                ),
+                None => log::info!(
+                    "Sent update with submission id: {}, taking effect immediately",
+                    submission_id
+                ),
+            }
 
             match timeout(
-                Duration::from_secs(MAX_TIME_CHECK_SUBMISSION),
-                check_update_status(submission_id, &mut node_client),
+                Duration::from_secs(app.confirmation_timeout),
+                check_update_status(&stats, submission_id, &mut node_client),
             )
             .await
             {
                 Ok(submission_result) => {
-                    // if we fail to confirm the transaction finalized, we retry with the same
-                    // sequence number next update. if the previous transaction
-                    // is already finalized this submission will fail,
-                    // and send_update will retry with a new sequence number.
-                    if let Err(e) = submission_result {
-                        log::error!("Could not query submission status: {}.", e);
-                    } else {
-                        // new_seq_number is the sequence number, which was used to successfully
-                        // send the update.
-                        seq_number = new_seq_number.next();
-                        stats.update_updated_rate(&rate);
-                        prev_rate = rate;
-                        log::info!(
-                            "Succesfully updated exchange rate to: {:?} microCCD/CCD, with id {}",
-                            new_rate,
-                            submission_id
-                        );
-                        if let Some(ref pool) = db_conn_pool {
-                            if let Err(e) = database::write_update_rate(pool, new_rate) {
-                                stats.increment_failed_database_updates();
-                                log::error!(
-                                    "Unable to INSERT new update: {:?}, due to: {}",
+                    // if we fail to confirm the transaction finalized, the next update uses the
+                    // next sequence number on chain, which is the same sequence number, unless
+                    // the transaction was finalized after all.
+                    match submission_result {
+                        Err(e) => {
+                            log::error!("Could not query submission status: {}.", e);
+                            report_outcome(
+                                &mut trigger,
+                                Err(format!(
+                                    "Could not query status of submission {}: {}",
+                                    submission_id, e
+                                )),
+                            );
+                            record_failed_cycle(
+                                &stats,
+                                &mut consecutive_failures,
+                                app.max_consecutive_failures,
+                            );
+                        }
+                        Ok(on_chain) => {
+                            last_on_chain_rate = on_chain;
+                            last_finalized_update = Instant::now();
+                            last_update_finalized = Some(last_finalized_update);
+                            consecutive_skips = 0;
+                            consecutive_failures = 0;
+                            stats.set_consecutive_failures(consecutive_failures);
+                            stats.update_updated_rate(&rate);
+                            recent_updates.push_back((chrono::offset::Utc::now(), rate.clone()));
+                            if max_daily_change.is_some() {
+                                daily_changes.push_back((
+                                    chrono::offset::Utc::now(),
+                                    relative_change(&prev_rate, &rate),
+                                ));
+                            }
+                            // The rate on chain should be the converted rate, unless the update was
+                            // superseded. (An update with a delayed effective time has not taken
+                            // effect yet, when it is finalized)
+                            let on_chain_rate = BigRational::new(
+                                on_chain.numerator().into(),
+                                on_chain.denominator().into(),
+                            );
+                            let submitted_rate = BigRational::new(
+                                new_rate.numerator().into(),
+                                new_rate.denominator().into(),
+                            );
+                            if effective_at.is_some() {
+                                prev_rate = submitted_rate;
+                            } else {
+                                if on_chain_rate != submitted_rate {
+                                    log::error!(
+                                        "The rate on chain is {} microCCD/Eur after the update \
+                                         was finalized, but {} was submitted. Using the rate on \
+                                         chain.",
+                                        on_chain_rate,
+                                        submitted_rate
+                                    );
+                                    stats.increment_update_mismatches();
+                                }
+                                prev_rate = on_chain_rate;
+                            }
+                            smooth_towards_prev_rate = true;
+                            log::info!(
+                                "Succesfully updated exchange rate to: {:?} microCCD/CCD, with id \
+                                 {}",
+                                new_rate,
+                                submission_id
+                            );
+                            report_outcome(&mut trigger, Ok(submission_id.to_string()));
+                            if let Some(ref pool) = db_conn_pool {
+                                if let Err(e) = database::write_update_rate(
+                                    pool,
                                     new_rate,
-                                    e
-                                )
-                            };
+                                    &used_sources,
+                                    effective_at.map(|at| at.naive_utc()),
+                                ) {
+                                    stats.increment_failed_database_updates();
+                                    log::error!(
+                                        "Unable to INSERT new update: {:?}, due to: {}",
+                                        new_rate,
+                                        e
+                                    )
+                                };
+                            }
                         }
                     }
                 }
-                Err(e) => log::error!(
-                    "Was unable to confirm update with id {} within allocated timeframe due to: {}",
-                    submission_id,
-                    e
-                ),
+                Err(e) => {
+                    log::error!(
+                        "Was unable to confirm update with id {} within allocated timeframe due \
+                         to: {}",
+                        submission_id,
+                        e
+                    );
+                    report_outcome(
+                        &mut trigger,
+                        Err(format!("Unable to confirm submission {} in time.", submission_id)),
+                    );
+                    record_failed_cycle(
+                        &stats,
+                        &mut consecutive_failures,
+                        app.max_consecutive_failures,
+                    );
+                }
             };
         } else {
+            consecutive_failures = 0;
+            stats.set_consecutive_failures(consecutive_failures);
             log::info!(
                 "Dry run enabled, so skipping the update. New rate: {}/{}",
                 new_rate.numerator(),
                 new_rate.denominator()
             );
+            report_outcome(
+                &mut trigger,
+                Ok(format!(
+                    "Dry run enabled, so skipping the update. New rate: {}/{}",
+                    new_rate.numerator(),
+                    new_rate.denominator()
+                )),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::{Level, Log, Metadata};
+
+    fn enabled(logger: &env_logger::Logger, target: &str, level: Level) -> bool {
+        logger.enabled(&Metadata::builder().target(target).level(level).build())
+    }
+
+    fn thresholds(
+        warning_increase: u16,
+        halt_increase: u16,
+        warning_decrease: u8,
+        halt_decrease: u8,
+    ) -> anyhow::Result<()> {
+        let app = App::from_iter([
+            "eur2ccd".to_string(),
+            format!("--warning-increase-threshold={}", warning_increase),
+            format!("--halt-increase-threshold={}", halt_increase),
+            format!("--warning-decrease-threshold={}", warning_decrease),
+            format!("--halt-decrease-threshold={}", halt_decrease),
+        ]);
+        validate_thresholds(&app)
+    }
+
+    #[test]
+    fn test_validate_thresholds() {
+        assert!(thresholds(30, 100, 15, 50).is_ok());
+        assert!(thresholds(1, 2, 1, 2).is_ok());
+        assert!(thresholds(999, 1000, 99, 100).is_ok());
+    }
+
+    #[test]
+    fn test_validate_thresholds_not_positive() {
+        assert!(thresholds(0, 100, 15, 50).is_err());
+        assert!(thresholds(30, 100, 0, 50).is_err());
+        assert!(thresholds(0, 1, 0, 1).is_err());
+    }
+
+    #[test]
+    fn test_validate_thresholds_out_of_range() {
+        assert!(thresholds(30, 0, 15, 50).is_err());
+        assert!(thresholds(30, 1001, 15, 50).is_err());
+        assert!(thresholds(30, 100, 15, 0).is_err());
+        assert!(thresholds(30, 100, 15, 101).is_err());
+    }
+
+    #[test]
+    fn test_validate_thresholds_warning_above_halt() {
+        assert!(thresholds(100, 100, 15, 50).is_err());
+        assert!(thresholds(120, 100, 15, 50).is_err());
+        assert!(thresholds(30, 100, 50, 50).is_err());
+        assert!(thresholds(30, 100, 60, 50).is_err());
+    }
+
+    #[test]
+    fn test_trailing_baseline() {
+        let now = chrono::Utc::now();
+        let rate = |n: i64| BigRational::from_integer(n.into());
+        let mut recent_updates = VecDeque::from(vec![
+            (now - chrono::Duration::hours(30), rate(1000)),
+            (now - chrono::Duration::hours(20), rate(10)),
+            (now - chrono::Duration::hours(10), rate(30)),
+            (now - chrono::Duration::hours(1), rate(20)),
+        ]);
+        let since = now - chrono::Duration::hours(24);
+        assert_eq!(trailing_baseline(None, &mut recent_updates, since), Some(rate(20)));
+        assert_eq!(recent_updates.len(), 3);
+        assert_eq!(trailing_baseline(None, &mut recent_updates, now), None);
+        assert!(recent_updates.is_empty());
+    }
+
+    #[test]
+    fn test_log_builder_level() {
+        let logger = log_builder(log::LevelFilter::Info, None).build();
+        assert!(enabled(&logger, "concordium_eur2ccd::node", Level::Info));
+        assert!(!enabled(&logger, "concordium_eur2ccd::sources", Level::Debug));
+        assert!(!enabled(&logger, "reqwest", Level::Info));
+    }
+
+    #[test]
+    fn test_log_builder_filter() {
+        let logger = log_builder(
+            log::LevelFilter::Error,
+            Some("concordium_eur2ccd=info,concordium_eur2ccd::sources=debug"),
+        )
+        .build();
+        assert!(enabled(&logger, "concordium_eur2ccd::sources", Level::Debug));
+        assert!(!enabled(&logger, "concordium_eur2ccd::sources", Level::Trace));
+        assert!(enabled(&logger, "concordium_eur2ccd::node", Level::Info));
+        assert!(!enabled(&logger, "concordium_eur2ccd::node", Level::Debug));
+        assert!(!enabled(&logger, "reqwest", Level::Info));
+    }
+
+    fn rate_pipeline() -> RatePipeline {
+        RatePipeline {
+            max_reading_age:         None,
+            max_identical_readings:  15,
+            min_readings_per_source: 1,
+            outlier_filter:          OutlierFilter::None,
+            median_even_policy:      MedianEvenPolicy::Average,
+            min_sources:             1,
+            aggregation:             Aggregation::WeightedMedian,
+            aggregation_canary:      false,
+            agreement_tolerance:     BigRational::from_integer(1.into()),
+            ewma_alpha:              None,
+            smoothing_alpha:         None,
+            clamp_change_percent:    None,
+            rate_precision:          None,
+        }
+    }
+
+    /// A source with the given weight, and readings in thousandths of CCD/EUR.
+    fn source_with_readings(label: &str, weight: u32, readings: &[i64]) -> SourceHandle {
+        let mut history = RateHistory::with_capacity(readings.len());
+        for (timestamp, reading) in (1..).zip(readings) {
+            let rate = BigRational::new((*reading).into(), 1000.into());
+            history.push_reading(rate, timestamp, HistoryLimit::Count(readings.len()));
+            history.last_reading_timestamp = timestamp;
+        }
+        SourceHandle {
+            label: label.into(),
+            max_age: None,
+            tier: 1,
+            weight,
+            history: Arc::new(Mutex::new(history)),
         }
     }
+
+    #[tokio::test]
+    async fn test_rate_pipeline() {
+        let stats = prometheus::initialize().await.unwrap().1;
+        let sources = [
+            source_with_readings("a", 1, &[40, 42]),
+            source_with_readings("b", 3, &[50]),
+            source_with_readings("c", 1, &[]),
+        ];
+        let micro_ccd = |rate: i64| BigRational::from_integer(rate.into());
+        let mut pipeline = rate_pipeline();
+        // The source without readings is excluded, and the weighted median follows the
+        // heaviest source:
+        let candidate = pipeline.candidate(&sources, &stats, 0).unwrap();
+        assert_eq!(candidate.used_sources(), "a,b");
+        assert_eq!(candidate.rate, micro_ccd(50000));
+        // The medians are ~10 % from their median:
+        assert_eq!(candidate.agreeing_sources, 0);
+        // The median policy is applied to the histories and the medians:
+        pipeline.aggregation = Aggregation::Median;
+        pipeline.median_even_policy = MedianEvenPolicy::Lower;
+        assert_eq!(pipeline.candidate(&sources, &stats, 0).unwrap().rate, micro_ccd(40000));
+        // The first rate is clamped to the change allowed from the rate on chain:
+        pipeline.clamp_change_percent = Some(BigRational::from_integer(10.into()));
+        assert_eq!(
+            pipeline.first_rate(&sources, &stats, 0, &micro_ccd(30000)),
+            Some(micro_ccd(33000))
+        );
+        assert_eq!(
+            pipeline.first_rate(&sources, &stats, 0, &micro_ccd(38000)),
+            Some(micro_ccd(40000))
+        );
+        // Too few usable sources:
+        pipeline.min_sources = 3;
+        assert!(pipeline.candidate(&sources, &stats, 0).is_none());
+    }
 }
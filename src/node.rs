@@ -1,7 +1,9 @@
 use crate::{
-    config::{RETRY_SUBMISSION_INTERVAL, UPDATE_EXPIRY_OFFSET},
+    config::{MAX_CLOCK_SKEW, RECONNECT_INITIAL_DELAY, RECONNECT_MAX_DELAY},
     prometheus::Stats,
+    sources::random_jitter,
 };
+use anyhow::Context;
 use concordium_rust_sdk::{
     common::types::TransactionTime,
     types::{
@@ -9,69 +11,206 @@ use concordium_rust_sdk::{
         transactions::{update, BlockItem, Payload},
         ExchangeRate, UpdateKeyPair, UpdateKeysIndex, UpdatePayload, UpdateSequenceNumber,
     },
-    v2,
+    v2::{self, ChainParameters},
 };
+use futures_util::future::join_all;
 use std::collections::BTreeMap;
-use tokio::time::{interval, Duration};
+use tokio::time::{interval, sleep, timeout, Duration};
 
+/// How updates are constructed and submitted.
+#[derive(Debug, Clone, Copy)]
+pub struct SubmissionSettings {
+    /// Expiry of an update, from when it is constructed. (In seconds)
+    pub expiry_offset:        u64,
+    /// Added to the expiry of an update, to tolerate the clock of the node
+    /// being ahead of ours. (In seconds)
+    pub clock_skew_allowance: u64,
+    /// Time between attempts of submitting an update.
+    pub retry_interval:       Duration,
+    /// Delay from when an update is constructed, until it takes effect. If 0,
+    /// the update takes effect immediately. (In seconds)
+    pub effective_delay:      u64,
+}
+
+/// Expiry of an update constructed at `now` (in seconds since the unix epoch).
+/// The clock skew allowance extends the expiry, so that the update is not
+/// rejected as expired by a node, whose clock is ahead of ours.
+fn update_expiry(now: i64, settings: &SubmissionSettings) -> TransactionTime {
+    TransactionTime::from_seconds(
+        (now.max(0) as u64)
+            .saturating_add(settings.expiry_offset)
+            .saturating_add(settings.clock_skew_allowance),
+    )
+}
+
+/// Effective time of an update constructed at `now` (in seconds since the
+/// unix epoch). An effective time of 0 means that the update takes effect
+/// immediately.
+fn update_effective_time(now: i64, settings: &SubmissionSettings) -> TransactionTime {
+    if settings.effective_delay == 0 {
+        TransactionTime::from_seconds(0)
+    } else {
+        TransactionTime::from_seconds((now.max(0) as u64).saturating_add(settings.effective_delay))
+    }
+}
+
+/// Constructs the update, and returns it along with its effective time.
 fn construct_block_item(
     seq_number: UpdateSequenceNumber,
     signer: &BTreeMap<UpdateKeysIndex, UpdateKeyPair>,
     exchange_rate: ExchangeRate,
-) -> BlockItem<Payload> {
-    let effective_time = 0.into();
-    let timeout = TransactionTime::from_seconds(
-        chrono::offset::Utc::now().timestamp() as u64 + UPDATE_EXPIRY_OFFSET,
-    );
+    settings: &SubmissionSettings,
+) -> (BlockItem<Payload>, TransactionTime) {
+    let now = chrono::offset::Utc::now().timestamp();
+    let effective_time = update_effective_time(now, settings);
+    let timeout = update_expiry(now, settings);
     let payload = UpdatePayload::MicroGTUPerEuro(exchange_rate);
-    update::update(signer, seq_number, effective_time, timeout, payload).into()
+    (update::update(signer, seq_number, effective_time, timeout, payload).into(), effective_time)
+}
+
+/// Counts the error in the `grpc_errors` metric, by its status code, if the
+/// node responded with a status.
+fn record_rpc_error(stats: &Stats, error: &v2::RPCError) {
+    if let v2::RPCError::CallError(status) = error {
+        stats.increment_grpc_errors(&format!("{:?}", status.code()));
+    }
+}
+
+/// Counts the error in the `grpc_errors` metric, by its status code, if the
+/// node responded with a status.
+fn record_query_error(stats: &Stats, error: &v2::QueryError) {
+    if let v2::QueryError::RPCError(e) = error {
+        record_rpc_error(stats, e);
+    }
+}
+
+/// A client connected to a node, along with the endpoint of the node.
+pub type NodeClient = (String, v2::Client);
+
+/**
+ * Connects to all of the given endpoints concurrently, within the given
+ * timeout. Endpoints, which cannot be reached, are logged and left out.
+ */
+pub async fn connect_all(
+    endpoints: Vec<v2::Endpoint>,
+    connect_timeout: Duration,
+) -> Vec<NodeClient> {
+    let connections = endpoints.into_iter().map(|node_ep| async move {
+        let uri = node_ep.uri().to_string();
+        match timeout(connect_timeout, v2::Client::new(node_ep)).await {
+            Ok(Ok(client)) => Some((uri, client)),
+            Ok(Err(e)) => {
+                log::warn!("Unable to connect to node at {}: {}", uri, e);
+                None
+            }
+            Err(_) => {
+                log::warn!(
+                    "Unable to connect to node at {} within {} seconds",
+                    uri,
+                    connect_timeout.as_secs()
+                );
+                None
+            }
+        }
+    });
+    join_all(connections).await.into_iter().flatten().collect()
+}
+
+/**
+ * Sends the block item to every given node concurrently. Returns whether
+ * any node accepted it, where a node reporting the block item as a
+ * duplicate counts as accepting it, as another node has then already
+ * accepted it.
+ */
+async fn broadcast_block_item(
+    stats: &Stats,
+    block_item: &BlockItem<Payload>,
+    clients: &[NodeClient],
+) -> bool {
+    let submissions = clients.iter().map(|(uri, client)| async move {
+        let outcome = match client.clone().send_block_item(block_item).await {
+            Ok(_) => "accepted",
+            Err(v2::RPCError::CallError(status)) if status.code() == tonic::Code::AlreadyExists => {
+                "duplicate"
+            }
+            Err(e) => {
+                log::warn!("Node at {} did not accept the update: {}", uri, e);
+                record_rpc_error(stats, &e);
+                "failed"
+            }
+        };
+        log::info!("Node at {}: update {}", uri, outcome);
+        stats.increment_node_submissions(uri, outcome);
+        outcome != "failed"
+    });
+    join_all(submissions).await.into_iter().any(|accepted| accepted)
+}
+
+/// Requests the next sequence number of exchange rate updates in the last
+/// finalized block of the given node.
+async fn next_seq_number(client: &mut v2::Client) -> v2::QueryResult<UpdateSequenceNumber> {
+    let seq_number = client
+        .get_next_update_sequence_numbers(v2::BlockIdentifier::LastFinal)
+        .await?
+        .response
+        .micro_ccd_per_euro;
+    Ok(seq_number)
 }
 
 /**
  * Sends an microCCD per Euro update, with the given exchange rate.
  * If it runs into issues, log the error and try again.
+ * Before each attempt, the next sequence number is requested from the given
+ * node. If it cannot be obtained, an error is returned, as the update
+ * cannot be constructed.
  * If the given node is not responding, then return None.
- * The given sequence number will be used initially, but a new one will be
- * requested, if the first attempt is not accepted. The returned sequence
- * number is the one used in the successful update.
+ * If broadcast clients are given, the update is sent to all of them
+ * concurrently instead, and it succeeds if any of them accepts it. The
+ * given node is then only used for requesting sequence numbers.
+ * Returns the submission id and the effective time of the update.
  */
 pub async fn send_update(
     stats: &Stats,
-    mut seq_number: UpdateSequenceNumber,
     signer: &BTreeMap<UpdateKeysIndex, UpdateKeyPair>,
     exchange_rate: ExchangeRate,
+    settings: &SubmissionSettings,
     mut client: v2::Client,
-) -> Option<(hashes::TransactionHash, UpdateSequenceNumber)> {
-    let mut get_new_seq_number = false;
-
-    let mut interval = interval(Duration::from_secs(RETRY_SUBMISSION_INTERVAL));
+    broadcast_clients: &[NodeClient],
+) -> anyhow::Result<Option<(hashes::TransactionHash, TransactionTime)>> {
+    let mut interval = interval(settings.retry_interval);
     interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
     loop {
         interval.tick().await;
 
-        if get_new_seq_number {
-            let new_seq =
-                match client.get_next_update_sequence_numbers(v2::BlockIdentifier::LastFinal).await
-                {
-                    Ok(o) => o,
-                    Err(e) => {
-                        log::error!("Unable to pull new sequence number due to: {}", e);
-                        // The only reason this should fail is a connection issue.
-                        return None;
-                    }
-                };
-            seq_number = new_seq.response.micro_ccd_per_euro;
-        }
+        observe_last_finalized_block(stats, &mut client).await;
+        let seq_number = next_seq_number(&mut client)
+            .await
+            .map_err(|e| {
+                record_query_error(stats, &e);
+                e
+            })
+            .context("Unable to obtain the next sequence number")?;
         // Construct the block item again. This sets the expiry from now so it is
         // necessary to reconstruct on each attempt.
-        let block_item = construct_block_item(seq_number, signer, exchange_rate);
+        let (block_item, effective_time) =
+            construct_block_item(seq_number, signer, exchange_rate, settings);
+        if !broadcast_clients.is_empty() {
+            if broadcast_block_item(stats, &block_item, broadcast_clients).await {
+                stats.reset_update_attempts();
+                return Ok(Some((block_item.hash(), effective_time)));
+            }
+            stats.increment_update_attempts();
+            log::error!("No node accepted the update");
+            continue;
+        }
         match client.send_block_item(&block_item).await {
             Ok(submission_id) => {
                 stats.reset_update_attempts();
-                return Some((submission_id, seq_number));
+                return Ok(Some((submission_id, effective_time)));
             }
             Err(v2::RPCError::CallError(status)) => {
                 stats.increment_update_attempts();
+                stats.increment_grpc_errors(&format!("{:?}", status.code()));
                 match status.code() {
                     tonic::Code::Internal
                     | tonic::Code::FailedPrecondition
@@ -80,11 +219,10 @@ pub async fn send_update(
                     | tonic::Code::Unavailable
                     | tonic::Code::Unknown => {
                         log::error!("Unable to reach current node during update");
-                        return None;
+                        return Ok(None);
                     }
                     code => {
                         log::error!("RPC error occurred while sending update: {}", code);
-                        get_new_seq_number = true;
                     }
                 }
             }
@@ -95,30 +233,368 @@ pub async fn send_update(
                 // we should do based on the status. If the node ever responds more precisely
                 // then we can revise this to be smarter about it.
                 log::error!("Error occurred while sending update: {}", e);
-                get_new_seq_number = true;
             }
         }
     }
 }
 
+/**
+ * Constructs a microCCD per Euro update, with the given exchange rate and
+ * the next sequence number of the given node, without sending it.
+ * This exercises fetching the sequence number and signing the update,
+ * without changing the state of the chain. Returns the block item, which
+ * would have been sent.
+ */
+pub async fn simulate_update(
+    signer: &BTreeMap<UpdateKeysIndex, UpdateKeyPair>,
+    exchange_rate: ExchangeRate,
+    settings: &SubmissionSettings,
+    client: &mut v2::Client,
+) -> anyhow::Result<BlockItem<Payload>> {
+    let seq_number = next_seq_number(client).await?;
+    let (block_item, _) = construct_block_item(seq_number, signer, exchange_rate, settings);
+    Ok(block_item)
+}
+
+/**
+ * Compares the local clock with the slot time of the last finalized block
+ * of the given node, and logs a warning if they differ by more than
+ * [MAX_CLOCK_SKEW] seconds. Returns the difference (local time minus the
+ * node's time) in seconds.
+ */
+pub async fn check_clock_skew(client: &mut v2::Client) -> anyhow::Result<i64> {
+    let block_time =
+        client.get_block_info(v2::BlockIdentifier::LastFinal).await?.response.block_slot_time;
+    let skew = chrono::offset::Utc::now().signed_duration_since(block_time).num_seconds();
+    if skew.abs() > MAX_CLOCK_SKEW {
+        log::warn!(
+            "The local clock differs from the time of the last finalized block by {} seconds, \
+             updates may be rejected as expired (consider setting `clock-skew-allowance`)",
+            skew
+        );
+    }
+    Ok(skew)
+}
+
+/// Reads the exchange rate from the given chain parameters, whichever version
+/// of the chain parameters is active.
+pub fn micro_ccd_per_euro(parameters: &ChainParameters) -> ExchangeRate {
+    match parameters {
+        ChainParameters::V0(params) => params.micro_ccd_per_euro,
+        ChainParameters::V1(params) => params.micro_ccd_per_euro,
+        ChainParameters::V2(params) => params.micro_ccd_per_euro,
+        ChainParameters::V3(params) => params.micro_ccd_per_euro,
+    }
+}
+
+/**
+ * Waits until the update with the given submission id is finalized, and
+ * returns the exchange rate on chain, after the block in which it was
+ * finalized.
+ */
 pub async fn check_update_status(
+    stats: &Stats,
     submission_id: hashes::TransactionHash,
     client: &mut v2::Client,
-) -> anyhow::Result<()> {
-    client.wait_until_finalized(&submission_id).await?;
-    Ok(())
+) -> anyhow::Result<ExchangeRate> {
+    let record = |e| {
+        record_query_error(stats, &e);
+        e
+    };
+    let (block_hash, _) = client.wait_until_finalized(&submission_id).await.map_err(record)?;
+    observe_last_finalized_block(stats, client).await;
+    let parameters = client.get_block_chain_parameters(block_hash).await.map_err(record)?;
+    Ok(micro_ccd_per_euro(&parameters.response))
+}
+
+/// Reads the last finalized block of the given node, and exposes its height
+/// and time in the `last_finalized_block_height` and
+/// `last_finalized_block_time` metrics.
+async fn observe_last_finalized_block(stats: &Stats, client: &mut v2::Client) {
+    match client.get_consensus_info().await {
+        Ok(consensus_info) => {
+            if let Some(last_finalized) = consensus_info.last_finalized_time {
+                stats.set_last_finalized_block(
+                    consensus_info.last_finalized_block_height.height,
+                    last_finalized.timestamp(),
+                );
+            }
+        }
+        Err(e) => {
+            record_query_error(stats, &e);
+            log::warn!("Unable to read the last finalized block of the node: {}", e);
+        }
+    }
+}
+
+/**
+ * Given a vector of endpoints, connect to each of them within the given
+ * timeout, and return the one, whose last finalized block is the most
+ * recent, along with its index. Nodes, whose last finalized block is older
+ * than the given maximum lag, are only used if every reachable node lags,
+ * in which case the freshest of them is used with a warning. If a node is
+ * already in use, given by its index, the selection is counted as a
+ * failover. Returns an error if no node is reachable.
+ */
+pub async fn get_node_client(
+    stats: &Stats,
+    endpoints: Vec<v2::Endpoint>,
+    connect_timeout: Duration,
+    max_lag: Duration,
+    in_use: Option<usize>,
+) -> anyhow::Result<(v2::Client, usize)> {
+    let mut best: Option<(v2::Client, usize, String, chrono::DateTime<chrono::Utc>, u64)> = None;
+    for (index, node_ep) in endpoints.into_iter().enumerate() {
+        let uri = node_ep.uri().to_string();
+        let connect = async {
+            let mut client = v2::Client::new(node_ep).await?;
+            let consensus_info = client.get_consensus_info().await.map_err(|e| {
+                record_query_error(stats, &e);
+                e
+            })?;
+            anyhow::Ok((
+                client,
+                consensus_info.last_finalized_time,
+                consensus_info.last_finalized_block_height.height,
+            ))
+        };
+        match timeout(connect_timeout, connect).await {
+            Ok(Ok((client, Some(last_finalized), height))) => {
+                let lag = chrono::offset::Utc::now().signed_duration_since(last_finalized);
+                log::info!(
+                    "Node at {}: last finalized block is {} seconds old",
+                    uri,
+                    lag.num_seconds()
+                );
+                if lag.to_std().map_or(false, |lag| lag > max_lag) {
+                    log::warn!(
+                        "Node at {} lags, as its last finalized block is older than {} seconds",
+                        uri,
+                        max_lag.as_secs()
+                    );
+                }
+                if best.as_ref().map_or(true, |(_, _, _, time, _)| last_finalized > *time) {
+                    best = Some((client, index, uri, last_finalized, height));
+                }
+            }
+            Ok(Ok((_, None, _))) => {
+                log::warn!("Skipping node at {}, as it has no finalized blocks", uri)
+            }
+            Ok(Err(e)) => log::warn!("Unable to connect to node at {}: {}", uri, e),
+            Err(_) => log::warn!(
+                "Unable to connect to node at {} within {} seconds",
+                uri,
+                connect_timeout.as_secs()
+            ),
+        }
+    }
+    match best {
+        Some((client, index, uri, last_finalized, height)) => {
+            let lag = chrono::offset::Utc::now().signed_duration_since(last_finalized);
+            if lag.to_std().map_or(false, |lag| lag > max_lag) {
+                log::warn!(
+                    "Every reachable node lags more than {} seconds, falling back to the node at \
+                     {}, whose last finalized block is the most recent",
+                    max_lag.as_secs(),
+                    uri
+                );
+            }
+            log::info!("Using node at {}", uri);
+            if let Some(previous) = in_use {
+                if previous != index {
+                    log::warn!("Failing over from node {} to node {}", previous, index);
+                }
+                stats.increment_node_failovers();
+            }
+            stats.set_node_in_use(&uri);
+            stats.set_last_finalized_block(height, last_finalized.timestamp());
+            Ok((client, index))
+        }
+        None => anyhow::bail!("Unable to connect to any node"),
+    }
+}
+
+/// Delay before the given retry (starting from 1) of connecting to the nodes,
+/// without jitter. The delay doubles with each retry, up to a maximum.
+fn reconnect_delay(retry: u32) -> Duration {
+    let delay = RECONNECT_INITIAL_DELAY.saturating_mul(2u64.saturating_pow(retry - 1));
+    Duration::from_secs(delay.min(RECONNECT_MAX_DELAY))
 }
 
 /**
- * Given a vector of endpoints, return the first one, which allows us to
- * connect to it. Returns an error if we are not able to connect to any of
- * the nodes.
+ * Selects a node like [get_node_client], making at most the given number of
+ * attempts. Between attempts, it waits with exponential backoff plus a
+ * random jitter, so that a brief restart of the nodes does not cost an
+ * update. Every retry is logged, and counted in the
+ * `node_reconnect_attempts` metric.
  */
-pub async fn get_node_client(endpoints: Vec<v2::Endpoint>) -> anyhow::Result<v2::Client> {
-    for node_ep in endpoints.into_iter() {
-        if let Ok(client) = v2::Client::new(node_ep).await {
-            return Ok(client);
+pub async fn connect_with_backoff(
+    stats: &Stats,
+    endpoints: &[v2::Endpoint],
+    connect_timeout: Duration,
+    max_lag: Duration,
+    in_use: Option<usize>,
+    max_attempts: u32,
+) -> anyhow::Result<(v2::Client, usize)> {
+    let mut attempt = 1;
+    loop {
+        match get_node_client(stats, endpoints.to_vec(), connect_timeout, max_lag, in_use).await {
+            Ok(selected) => return Ok(selected),
+            Err(e) if attempt >= max_attempts => {
+                return Err(e.context(format!("Gave up after {} attempts", attempt)))
+            }
+            Err(e) => {
+                let delay = reconnect_delay(attempt);
+                let delay = delay + random_jitter(delay / 2);
+                log::warn!(
+                    "{}, attempt {} of {}, retrying in {} ms",
+                    e,
+                    attempt,
+                    max_attempts,
+                    delay.as_millis()
+                );
+                stats.increment_node_reconnect_attempts();
+                sleep(delay).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::Instant;
+
+    #[tokio::test]
+    async fn test_get_node_client_timeout() {
+        // An address in a private range, which is not expected to be routable.
+        let endpoint = v2::Endpoint::from_static("http://10.255.255.1:20000");
+        let start = Instant::now();
+        let stats = crate::prometheus::initialize().await.unwrap().1;
+        let result = get_node_client(
+            &stats,
+            vec![endpoint.clone(), endpoint],
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+            None,
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_update_expiry() {
+        let mut settings = SubmissionSettings {
+            expiry_offset:        100,
+            clock_skew_allowance: 0,
+            retry_interval:       Duration::from_secs(10),
+            effective_delay:      0,
         };
+        assert_eq!(update_expiry(1_000, &settings), TransactionTime::from_seconds(1_100));
+        // A clock before the epoch does not wrap around:
+        assert_eq!(update_expiry(-5, &settings), TransactionTime::from_seconds(100));
+        settings.clock_skew_allowance = 30;
+        assert_eq!(update_expiry(1_000, &settings), TransactionTime::from_seconds(1_130));
+    }
+
+    #[test]
+    fn test_update_effective_time() {
+        let mut settings = SubmissionSettings {
+            expiry_offset:        100,
+            clock_skew_allowance: 0,
+            retry_interval:       Duration::from_secs(10),
+            effective_delay:      0,
+        };
+        // Without a delay, the update takes effect immediately:
+        assert_eq!(update_effective_time(1_000, &settings), TransactionTime::from_seconds(0));
+        settings.effective_delay = 160;
+        assert_eq!(update_effective_time(1_000, &settings), TransactionTime::from_seconds(1_160));
+        // The smallest delay accepted at startup takes effect no earlier than the
+        // update expires, as required by the chain:
+        settings.clock_skew_allowance = 30;
+        settings.effective_delay = settings.expiry_offset + settings.clock_skew_allowance;
+        for now in [-5, 0, 1_000] {
+            assert!(update_effective_time(now, &settings) >= update_expiry(now, &settings));
+        }
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        assert_eq!(reconnect_delay(1), Duration::from_secs(RECONNECT_INITIAL_DELAY));
+        assert_eq!(reconnect_delay(2), Duration::from_secs(2 * RECONNECT_INITIAL_DELAY));
+        assert_eq!(reconnect_delay(3), Duration::from_secs(4 * RECONNECT_INITIAL_DELAY));
+        // The delay is capped, also when the number of retries is large:
+        assert_eq!(reconnect_delay(10), Duration::from_secs(RECONNECT_MAX_DELAY));
+        assert_eq!(reconnect_delay(100), Duration::from_secs(RECONNECT_MAX_DELAY));
+    }
+    #[test]
+    fn test_micro_ccd_per_euro_v0() {
+        // Chain parameters before protocol version 4, as served by the node.
+        let parameters = serde_json::json!({
+            "electionDifficulty": 0.025,
+            "euroPerEnergy": {"numerator": 1, "denominator": 50000},
+            "microGTUPerEuro": {"numerator": 13023749, "denominator": 1},
+            "bakerCooldownEpochs": 166,
+            "accountCreationLimit": 10,
+            "rewardParameters": {
+                "mintDistribution": {
+                    "mintPerSlot": 7.555665e-10,
+                    "bakingReward": 0.6,
+                    "finalizationReward": 0.3
+                },
+                "transactionFeeDistribution": {"baker": 0.45, "gasAccount": 0.45},
+                "gASRewards": {
+                    "baker": 0.25,
+                    "finalizationProof": 0.005,
+                    "accountCreation": 0.02,
+                    "chainUpdate": 0.005
+                }
+            },
+            "foundationAccountIndex": 5,
+            "minimumThresholdForBaking": "15000000000"
+        });
+        let parameters = ChainParameters::V0(serde_json::from_value(parameters).unwrap());
+        let rate = micro_ccd_per_euro(&parameters);
+        assert_eq!((rate.numerator(), rate.denominator()), (13023749, 1));
+    }
+
+    #[test]
+    fn test_micro_ccd_per_euro_v1() {
+        // Chain parameters from protocol version 4, as served by the node.
+        let parameters = serde_json::json!({
+            "mintPerPayday": 2.61157877e-4,
+            "rewardParameters": {
+                "mintDistribution": {"bakingReward": 0.6, "finalizationReward": 0.3},
+                "transactionFeeDistribution": {"gasAccount": 0.45, "baker": 0.45},
+                "gASRewards": {
+                    "chainUpdate": 0.005,
+                    "accountCreation": 0.02,
+                    "baker": 0.25,
+                    "finalizationProof": 0.005
+                }
+            },
+            "poolOwnerCooldown": 10800,
+            "capitalBound": 0.25,
+            "microGTUPerEuro": {"numerator": 2000000000, "denominator": 141},
+            "rewardPeriodLength": 4,
+            "passiveTransactionCommission": 0.1,
+            "leverageBound": {"numerator": 3, "denominator": 1},
+            "foundationAccountIndex": 5,
+            "passiveFinalizationCommission": 1.0,
+            "delegatorCooldown": 7200,
+            "bakingCommissionRange": {"max": 0.05, "min": 0.05},
+            "passiveBakingCommission": 0.1,
+            "accountCreationLimit": 10,
+            "finalizationCommissionRange": {"max": 1.0, "min": 1.0},
+            "electionDifficulty": 0.025,
+            "euroPerEnergy": {"numerator": 1, "denominator": 1000000},
+            "transactionCommissionRange": {"max": 0.05, "min": 0.05},
+            "minimumEquityCapital": "14000000000"
+        });
+        let parameters = ChainParameters::V1(serde_json::from_value(parameters).unwrap());
+        let rate = micro_ccd_per_euro(&parameters);
+        assert_eq!((rate.numerator(), rate.denominator()), (2000000000, 141));
     }
-    anyhow::bail!("Unable to connect to any node");
 }
@@ -3,7 +3,8 @@ use anyhow::{Context, Result};
 use num_rational::BigRational;
 use num_traits::ToPrimitive;
 use prometheus::{
-    Encoder, Gauge, GaugeVec, IntCounter, IntGauge, IntGaugeVec, Registry, TextEncoder,
+    core::Collector, Encoder, Gauge, GaugeVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    Registry, TextEncoder,
 };
 use std::sync::{Arc, RwLock};
 use warp::{http::StatusCode, Filter};
@@ -56,23 +57,160 @@ impl prometheus::core::Collector for HidingGaugeCollector {
 pub struct Stats {
     /// The last exchange rate read from each source. The metrics inside have 1
     /// variable label, which denotes the source.
-    exchange_rate_read:           GaugeVec,
+    exchange_rate_read: GaugeVec,
     /// The value of the last exchange rate update performed on chain.
-    exchange_rate_updated:        HidingGaugeCollector,
+    exchange_rate_updated: HidingGaugeCollector,
+    /// The last computed exchange rate, before smoothing.
+    exchange_rate_computed: Gauge,
+    /// The last computed exchange rate, after smoothing.
+    exchange_rate_smoothed: Gauge,
+    /// The rate of the last update candidate, which would be submitted, also in
+    /// dry run.
+    exchange_rate_candidate: Gauge,
+    /// The exchange rate on chain, when it was last read from the node.
+    exchange_rate_on_chain: Gauge,
     /// Number of times an update has been outside the warning threshold.
     warning_threshold_violations: IntCounter,
+    /// Number of times an update has been outside the halt threshold.
+    halt_threshold_violations: IntCounter,
+    /// A boolean gauge per halt action, that indicates whether the action is
+    /// taken (1) or not (0), when an update violates a halt threshold.
+    /// Expects 1 label, the action.
+    halt_action: IntGaugeVec,
+    /// Number of consecutive update cycles, in which the halt threshold was
+    /// violated. Resets to 0 when an update is within the halt threshold.
+    halt_violation_streak: IntGauge,
+    /// Number of times an update has been outside the absolute rate bounds.
+    rate_bound_violations: IntCounter,
+    /// The sum of the changes of the updates in the last 24 hours. (In
+    /// percentage)
+    daily_change: Gauge,
+    /// Number of times an update was skipped or limited, because it would have
+    /// exceeded the maximum daily change.
+    daily_change_limited: IntCounter,
+    /// The relative spread between the lowest and the highest median of the
+    /// sources used in the last update cycle. (In percentage)
+    source_spread: Gauge,
+    /// The spread between the lowest and the highest median of the sources
+    /// used in the last update cycle, relative to the median of the medians.
+    source_spread_ratio: Gauge,
+    /// Number of times an update was skipped, because the spread of the
+    /// sources exceeded the maximum spread.
+    source_spread_skips: IntCounter,
+    /// Number of times protected mode was engaged, because the spread of the
+    /// sources exceeded the halt spread.
+    source_spread_halts: IntCounter,
+    /// The number of sources used in the last update cycle, whose median is
+    /// within the agreement tolerance of the median of all of them.
+    agreeing_sources: IntGauge,
+    /// Number of times an update was skipped, because too few sources agreed.
+    agreement_skips: IntCounter,
     /// Number of times we failed to read from each source.
     /// Resets to 0 upon successful poll.
     /// Expects 1 label, the source's label.
-    read_attempts:                IntGaugeVec,
+    read_attempts: IntGaugeVec,
     /// Number of times we failed to submit an update.
     /// Resets to 0 upon successful submission.
-    update_attempts:              IntGauge,
+    update_attempts: IntGauge,
     /// A boolean gauge that indicates whether the service is in
     /// dry_run/protected mode (1) or not (0).
-    protected:                    IntGauge,
+    protected: IntGauge,
+    /// A boolean gauge, that indicates whether no update has been finalized
+    /// for longer than the maximum update gap (1) or not (0).
+    update_overdue: IntGauge,
+    /// A boolean gauge, that indicates whether submitting updates has been
+    /// paused through the admin endpoint (1) or not (0).
+    paused: IntGauge,
+    /// Number of times the service has entered protected mode, for each
+    /// reason.
+    /// Expects 1 label, the reason.
+    protected_mode_transitions: IntCounterVec,
+    /// Number of times the service has left protected mode, because the rate
+    /// on chain was corrected.
+    protected_mode_recoveries: IntCounter,
     /// Number of times we failed to write to the database:
-    failed_database_updates:      IntCounter,
+    failed_database_updates: IntCounter,
+    /// Number of consecutive identical readings from each source.
+    /// Expects 1 label, the source's label.
+    identical_readings: IntGaugeVec,
+    /// Number of times the response of each source could not be parsed.
+    /// Expects 1 label, the source's label.
+    parse_errors: IntCounterVec,
+    /// Number of times each source has rate limited us.
+    /// Expects 1 label, the source's label.
+    rate_limited: IntCounterVec,
+    /// Number of responses received from each source, by their HTTP status
+    /// code.
+    /// Expects 2 labels, the source's label and the status code.
+    source_http_responses: IntCounterVec,
+    update_confirmations: IntCounterVec,
+    /// Number of submissions of updates to each node, when broadcasting, by
+    /// their outcome.
+    /// Expects 2 labels, the endpoint of the node and the outcome.
+    node_submissions: IntCounterVec,
+    /// A boolean gauge per source, that indicates whether the source was used
+    /// (1) or not (0) in the last update cycle.
+    /// Expects 1 label, the source's label.
+    source_used: IntGaugeVec,
+    /// A boolean gauge per node, that indicates whether the node is the one
+    /// in use (1) or not (0).
+    /// Expects 1 label, the endpoint of the node.
+    node_in_use: IntGaugeVec,
+    /// Number of times a node was selected, while another node (or the same)
+    /// was already in use, i.e. after the node in use failed.
+    node_failovers: IntCounter,
+    /// Number of retries of connecting to the nodes, after no node could be
+    /// selected.
+    node_reconnect_attempts: IntCounter,
+    /// Number of errors returned by nodes.
+    /// Expects 1 label, the gRPC status code.
+    grpc_errors: IntCounterVec,
+    /// Height of the last finalized block of the node in use, when it was
+    /// last observed.
+    last_finalized_block_height: IntGauge,
+    /// Time of the last finalized block of the node in use, when it was last
+    /// observed, in seconds since the unix epoch.
+    last_finalized_block_time: IntGauge,
+    /// Number of times an update was skipped, because the change was below the
+    /// minimum change threshold.
+    skipped_updates: IntCounter,
+    /// Number of times an update was skipped, because the converted rate was
+    /// identical to the rate on chain.
+    identical_updates_skipped: IntCounter,
+    /// Number of finalized updates, after which the rate on chain differed
+    /// from the submitted rate.
+    update_mismatches: IntCounter,
+    /// Number of updates, which were forced after `heartbeat-updates`
+    /// consecutive skipped updates.
+    heartbeat_updates: IntCounter,
+    /// Number of updates, which were clamped to the maximum change.
+    clamped_updates: IntCounter,
+    /// A boolean gauge per source, that indicates whether the source has been
+    /// disabled (1) or not (0) through the admin endpoint.
+    /// Expects 1 label, the source's label.
+    source_disabled: IntGaugeVec,
+    /// Number of consecutive update cycles, in which no rate could be
+    /// computed. Resets to 0 when a rate is computed again.
+    failed_cycles: IntGauge,
+    /// Number of consecutive update cycles, which failed, because no rate
+    /// could be computed, or the update could not be submitted or confirmed.
+    /// Resets to 0 upon a successful update cycle.
+    consecutive_failures: IntGauge,
+    /// Number of sources, which are configured.
+    sources_configured: IntGauge,
+    /// Number of sources, which had a non-stale reading in the last update
+    /// cycle.
+    sources_healthy: IntGauge,
+    /// Number of readings in the history of each source.
+    /// Expects 1 label, the source's label.
+    history_length: IntGaugeVec,
+    /// Number of times the computed rate could not be converted into an
+    /// exchange rate.
+    failed_conversions: IntCounter,
+    /// The last rate computed by each aggregation strategy, when comparing
+    /// them in the canary.
+    /// Expects 1 label, the strategy.
+    aggregation_canary: GaugeVec,
 }
 
 impl Stats {
@@ -107,8 +245,112 @@ impl Stats {
         }
     }
 
+    pub fn update_computed_rate(&self, rate: &BigRational) {
+        match rate.to_f64() {
+            Some(rate_float) => self.exchange_rate_computed.set(rate_float),
+            None => log::error!("Unable to convert computed rate {} to float for Prometheus", rate),
+        }
+    }
+
+    pub fn update_smoothed_rate(&self, rate: &BigRational) {
+        match rate.to_f64() {
+            Some(rate_float) => self.exchange_rate_smoothed.set(rate_float),
+            None => log::error!("Unable to convert smoothed rate {} to float for Prometheus", rate),
+        }
+    }
+
+    pub fn update_candidate_rate(&self, rate: &BigRational) {
+        match rate.to_f64() {
+            Some(rate_float) => self.exchange_rate_candidate.set(rate_float),
+            None => {
+                log::error!("Unable to convert candidate rate {} to float for Prometheus", rate)
+            }
+        }
+    }
+
+    pub fn update_on_chain_rate(&self, rate: &BigRational) {
+        match rate.to_f64() {
+            Some(rate_float) => self.exchange_rate_on_chain.set(rate_float),
+            None => log::error!("Unable to convert on-chain rate {} to float for Prometheus", rate),
+        }
+    }
+
+    pub fn update_canary_rate(&self, rate: &BigRational, aggregation: &str) {
+        let rate_float = match rate.to_f64() {
+            Some(rate_float) => rate_float,
+            None => {
+                log::error!("Unable to convert canary rate {} to float for Prometheus", rate);
+                return;
+            }
+        };
+        match self.aggregation_canary.get_metric_with_label_values(&[aggregation]) {
+            Ok(metric) => metric.set(rate_float),
+            Err(e) => log::error!(
+                "Unable to update canary rate to {}, on label {}, due to: {}",
+                rate,
+                aggregation,
+                e
+            ),
+        }
+    }
+
     pub fn increment_warning_threshold_violations(&self) { self.warning_threshold_violations.inc() }
 
+    pub fn increment_halt_threshold_violations(&self) { self.halt_threshold_violations.inc() }
+
+    pub fn set_halt_action(&self, action: &str) {
+        match self.halt_action.get_metric_with_label_values(&[action]) {
+            Ok(metric) => metric.set(1),
+            Err(e) => log::error!("Unable to set halt action {}, due to: {}", action, e),
+        }
+    }
+
+    pub fn set_halt_violation_streak(&self, cycles: u32) {
+        self.halt_violation_streak.set(cycles.into())
+    }
+
+    pub fn increment_rate_bound_violations(&self) { self.rate_bound_violations.inc() }
+
+    pub fn update_daily_change(&self, change: &BigRational) {
+        match change.to_f64() {
+            Some(change_float) => self.daily_change.set(change_float),
+            None => {
+                log::error!("Unable to convert daily change {} to float for Prometheus", change)
+            }
+        }
+    }
+
+    pub fn increment_daily_change_limited(&self) { self.daily_change_limited.inc() }
+
+    pub fn update_source_spread(&self, spread: &BigRational) {
+        match spread.to_f64() {
+            Some(spread_float) => self.source_spread.set(spread_float),
+            None => {
+                log::error!("Unable to convert source spread {} to float for Prometheus", spread)
+            }
+        }
+    }
+
+    pub fn set_source_spread(&self, ratio: &BigRational) {
+        match ratio.to_f64() {
+            Some(ratio_float) => self.source_spread_ratio.set(ratio_float),
+            None => log::error!(
+                "Unable to convert source spread ratio {} to float for Prometheus",
+                ratio
+            ),
+        }
+    }
+
+    pub fn increment_source_spread_skips(&self) { self.source_spread_skips.inc() }
+
+    pub fn increment_source_spread_halts(&self) { self.source_spread_halts.inc() }
+
+    pub fn set_agreeing_sources(&self, count: usize) {
+        self.agreeing_sources.set(i64::try_from(count).unwrap_or(i64::MAX))
+    }
+
+    pub fn increment_agreement_skips(&self) { self.agreement_skips.inc() }
+
     pub fn increment_read_attempts(&self, label: &Source) {
         match self.read_attempts.get_metric_with_label_values(&[&label.to_string()]) {
             Ok(metric) => metric.inc(),
@@ -131,9 +373,180 @@ impl Stats {
 
     pub fn reset_update_attempts(&self) { self.update_attempts.set(0) }
 
-    pub fn set_protected(&self) { self.protected.set(1); }
+    /// Marks the service as being in protected mode, and counts the
+    /// transition with the given reason.
+    pub fn set_protected_with_reason(&self, reason: &str) {
+        log::warn!("Entering protected mode, due to: {}", reason);
+        self.protected.set(1);
+        match self.protected_mode_transitions.get_metric_with_label_values(&[reason]) {
+            Ok(metric) => metric.inc(),
+            Err(e) => log::error!(
+                "Unable to increment protected mode transitions on label {}, due to: {}",
+                reason,
+                e
+            ),
+        }
+    }
+
+    /// Marks the service as having left protected mode, and counts the
+    /// recovery.
+    pub fn leave_protected(&self) {
+        self.protected.set(0);
+        self.protected_mode_recoveries.inc();
+    }
 
     pub fn increment_failed_database_updates(&self) { self.failed_database_updates.inc() }
+
+    pub fn update_identical_readings(&self, streak: u32, label: &Source) {
+        match self.identical_readings.get_metric_with_label_values(&[&label.to_string()]) {
+            Ok(metric) => metric.set(streak.into()),
+            Err(e) => log::error!(
+                "Unable to update identical readings to {}, on label {}, due to: {}",
+                streak,
+                label,
+                e
+            ),
+        }
+    }
+
+    pub fn increment_http_responses(&self, label: &Source, status: u16) {
+        match self
+            .source_http_responses
+            .get_metric_with_label_values(&[&label.to_string(), &status.to_string()])
+        {
+            Ok(metric) => metric.inc(),
+            Err(e) => log::error!(
+                "Unable to increment HTTP responses on label {} and status {}, due to: {}",
+                label,
+                status,
+                e
+            ),
+        }
+    }
+
+    pub fn increment_rate_limited(&self, label: &Source) {
+        match self.rate_limited.get_metric_with_label_values(&[&label.to_string()]) {
+            Ok(metric) => metric.inc(),
+            Err(e) => {
+                log::error!("Unable to increment rate limited on label {}, due to: {}", label, e)
+            }
+        }
+    }
+
+    pub fn increment_node_submissions(&self, endpoint: &str, outcome: &str) {
+        match self.node_submissions.get_metric_with_label_values(&[endpoint, outcome]) {
+            Ok(metric) => metric.inc(),
+            Err(e) => log::error!(
+                "Unable to increment node submissions on label {}, due to: {}",
+                endpoint,
+                e
+            ),
+        }
+    }
+
+    pub fn increment_node_reconnect_attempts(&self) { self.node_reconnect_attempts.inc() }
+
+    pub fn increment_grpc_errors(&self, code: &str) {
+        match self.grpc_errors.get_metric_with_label_values(&[code]) {
+            Ok(metric) => metric.inc(),
+            Err(e) => {
+                log::error!("Unable to increment gRPC errors on label {}, due to: {}", code, e)
+            }
+        }
+    }
+
+    pub fn set_last_finalized_block(&self, height: u64, timestamp: i64) {
+        self.last_finalized_block_height.set(height as i64);
+        self.last_finalized_block_time.set(timestamp);
+    }
+
+    pub fn increment_confirmations(&self, outcome: &str) {
+        match self.update_confirmations.get_metric_with_label_values(&[outcome]) {
+            Ok(metric) => metric.inc(),
+            Err(e) => log::error!(
+                "Unable to increment confirmations on outcome {}, due to: {}",
+                outcome,
+                e
+            ),
+        }
+    }
+
+    pub fn increment_parse_errors(&self, label: &Source) {
+        match self.parse_errors.get_metric_with_label_values(&[&label.to_string()]) {
+            Ok(metric) => metric.inc(),
+            Err(e) => {
+                log::error!("Unable to increment parse errors on label {}, due to: {}", label, e)
+            }
+        }
+    }
+
+    pub fn increment_skipped_updates(&self) { self.skipped_updates.inc() }
+
+    pub fn increment_identical_updates_skipped(&self) { self.identical_updates_skipped.inc() }
+
+    pub fn increment_update_mismatches(&self) { self.update_mismatches.inc() }
+
+    pub fn increment_heartbeat_updates(&self) { self.heartbeat_updates.inc() }
+
+    pub fn increment_clamped_updates(&self) { self.clamped_updates.inc() }
+
+    pub fn set_history_length(&self, label: &str, length: usize) {
+        match self.history_length.get_metric_with_label_values(&[label]) {
+            Ok(metric) => metric.set(length as i64),
+            Err(e) => {
+                log::error!("Unable to update history length on label {}, due to: {}", label, e)
+            }
+        }
+    }
+
+    pub fn increment_failed_conversions(&self) { self.failed_conversions.inc() }
+
+    pub fn set_failed_cycles(&self, cycles: u32) { self.failed_cycles.set(cycles.into()) }
+
+    pub fn set_consecutive_failures(&self, failures: u32) {
+        self.consecutive_failures.set(failures.into())
+    }
+
+    pub fn set_sources_configured(&self, sources: usize) {
+        self.sources_configured.set(sources as i64)
+    }
+
+    pub fn set_sources_healthy(&self, sources: usize) { self.sources_healthy.set(sources as i64) }
+
+    pub fn set_source_disabled(&self, label: &str, disabled: bool) {
+        match self.source_disabled.get_metric_with_label_values(&[label]) {
+            Ok(metric) => metric.set(disabled.into()),
+            Err(e) => {
+                log::error!("Unable to update source disabled on label {}, due to: {}", label, e)
+            }
+        }
+    }
+
+    pub fn set_update_overdue(&self, overdue: bool) { self.update_overdue.set(overdue.into()) }
+
+    pub fn set_paused(&self, paused: bool) { self.paused.set(paused.into()) }
+
+    pub fn set_source_used(&self, label: &str, used: bool) {
+        match self.source_used.get_metric_with_label_values(&[label]) {
+            Ok(metric) => metric.set(used.into()),
+            Err(e) => {
+                log::error!("Unable to update source used on label {}, due to: {}", label, e)
+            }
+        }
+    }
+
+    pub fn increment_node_failovers(&self) { self.node_failovers.inc() }
+
+    /// Marks the node with the given endpoint as the one in use.
+    pub fn set_node_in_use(&self, endpoint: &str) {
+        self.node_in_use.reset();
+        match self.node_in_use.get_metric_with_label_values(&[endpoint]) {
+            Ok(metric) => metric.set(1),
+            Err(e) => {
+                log::error!("Unable to update node in use on label {}, due to: {}", endpoint, e)
+            }
+        }
+    }
 }
 
 pub async fn initialize() -> anyhow::Result<(Registry, Stats)> {
@@ -147,10 +560,78 @@ pub async fn initialize() -> anyhow::Result<(Registry, Stats)> {
         gauge:         Gauge::new("exchange_rate_updated", "Last updated exchange rate.")?,
         allow_collect: Arc::new(RwLock::new(false)),
     };
+    let exchange_rate_computed =
+        Gauge::new("exchange_rate_computed", "Last computed exchange rate, before smoothing.")?;
+    let exchange_rate_smoothed =
+        Gauge::new("exchange_rate_smoothed", "Last computed exchange rate, after smoothing.")?;
+    let exchange_rate_candidate = Gauge::new(
+        "exchange_rate_candidate",
+        "Rate of the last update candidate, which would be submitted, also in dry run.",
+    )?;
+    let exchange_rate_on_chain =
+        Gauge::new("exchange_rate_on_chain", "Exchange rate on chain, when last read.")?;
     let warning_threshold_violations = IntCounter::new(
         "warning_threshold_violations",
         "Amount of times an update has been outside the warning threshold.",
     )?;
+    let halt_threshold_violations = IntCounter::new(
+        "halt_threshold_violations",
+        "Amount of times an update has been outside the halt threshold.",
+    )?;
+    let halt_action = IntGaugeVec::new(
+        prometheus::Opts::new(
+            "halt_action",
+            "Whether the action is taken (1) or not (0), when an update violates a halt threshold.",
+        ),
+        &["Action"],
+    )?;
+    let halt_violation_streak = IntGauge::new(
+        "halt_violation_streak",
+        "Amount of consecutive update cycles, in which the halt threshold has been violated.",
+    )?;
+    let rate_bound_violations = IntCounter::new(
+        "rate_bound_violations",
+        "Amount of times an update has been outside the minimum or maximum rate.",
+    )?;
+    let daily_change = Gauge::new(
+        "cumulative_daily_change",
+        "Sum of the changes of the updates in the last 24 hours, in percentage.",
+    )?;
+    let daily_change_limited = IntCounter::new(
+        "daily_change_limited",
+        "Amount of times an update was skipped or limited, because it would have exceeded the \
+         maximum daily change.",
+    )?;
+    let source_spread = Gauge::new(
+        "source_spread",
+        "Relative spread between the lowest and the highest median of the used sources, in \
+         percentage.",
+    )?;
+    let source_spread_ratio = Gauge::new(
+        "source_spread_ratio",
+        "Spread between the lowest and the highest median of the used sources, relative to the \
+         median of the medians.",
+    )?;
+    let source_spread_skips = IntCounter::new(
+        "source_spread_skips",
+        "Amount of times an update was skipped, because the sources disagreed by more than the \
+         maximum spread.",
+    )?;
+    let source_spread_halts = IntCounter::new(
+        "source_spread_halts",
+        "Amount of times protected mode was engaged, because the sources disagreed by more than \
+         the halt spread.",
+    )?;
+    let agreeing_sources = IntGauge::new(
+        "agreeing_sources",
+        "Number of used sources, whose median is within the agreement tolerance of the median of \
+         all of them.",
+    )?;
+    let agreement_skips = IntCounter::new(
+        "agreement_skips",
+        "Amount of times an update was skipped, because fewer than the minimum number of sources \
+         agreed.",
+    )?;
     let read_attempts = IntGaugeVec::new(
         prometheus::Opts::new("failed_reads", "Amount of times reading from a source has failed."),
         &["Source"],
@@ -161,24 +642,268 @@ pub async fn initialize() -> anyhow::Result<(Registry, Stats)> {
         "in_protected_mode",
         "Whether the service is in protected (1) mode or not (0).",
     )?;
+    let update_overdue = IntGauge::new(
+        "update_overdue",
+        "Whether no update has been finalized for longer than the maximum update gap (1) or not \
+         (0).",
+    )?;
+    let paused = IntGauge::new(
+        "paused",
+        "Whether submitting updates has been paused through the admin endpoint (1) or not (0).",
+    )?;
+    let protected_mode_transitions = IntCounterVec::new(
+        prometheus::Opts::new(
+            "protected_mode_transitions",
+            "Amount of times the service has entered protected mode.",
+        ),
+        &["reason"],
+    )?;
+    let protected_mode_recoveries = IntCounter::new(
+        "protected_mode_recoveries",
+        "Amount of times the service has left protected mode, because the rate on chain was \
+         corrected.",
+    )?;
     let failed_database_updates = IntCounter::new(
         "failed_database_updates",
         "Amount of times writing to the database has failed.",
     )?;
+    let identical_readings = IntGaugeVec::new(
+        prometheus::Opts::new(
+            "identical_readings",
+            "Number of consecutive identical readings from a source.",
+        ),
+        &["Source"],
+    )?;
+    let source_http_responses = IntCounterVec::new(
+        prometheus::Opts::new(
+            "source_http_responses",
+            "Amount of responses received from a source, by their HTTP status code.",
+        ),
+        &["Source", "Status"],
+    )?;
+    let rate_limited = IntCounterVec::new(
+        prometheus::Opts::new(
+            "rate_limited_total",
+            "Amount of times a source has rate limited us.",
+        ),
+        &["Source"],
+    )?;
+    let node_submissions = IntCounterVec::new(
+        prometheus::Opts::new(
+            "node_submissions",
+            "Amount of submissions of updates to each node, when broadcasting, by their outcome.",
+        ),
+        &["Endpoint", "Outcome"],
+    )?;
+    let update_confirmations = IntCounterVec::new(
+        prometheus::Opts::new(
+            "update_confirmations",
+            "Amount of updates violating the warning threshold, which were approved, rejected or \
+             expired while awaiting confirmation.",
+        ),
+        &["Outcome"],
+    )?;
+    let parse_errors = IntCounterVec::new(
+        prometheus::Opts::new(
+            "source_parse_errors",
+            "Amount of times the response of a source could not be parsed.",
+        ),
+        &["Source"],
+    )?;
+    let source_used = IntGaugeVec::new(
+        prometheus::Opts::new(
+            "source_used",
+            "Whether a source was used (1) or not (0) in the last update cycle.",
+        ),
+        &["Source"],
+    )?;
+    let node_in_use = IntGaugeVec::new(
+        prometheus::Opts::new("node_in_use", "Whether a node is the one in use (1) or not (0)."),
+        &["Endpoint"],
+    )?;
+    let node_failovers = IntCounter::new(
+        "node_failovers",
+        "Amount of times a node was selected, after the node in use failed.",
+    )?;
+    let node_reconnect_attempts = IntCounter::new(
+        "node_reconnect_attempts",
+        "Amount of retries of connecting to the nodes, after no node could be selected.",
+    )?;
+    let grpc_errors = IntCounterVec::new(
+        prometheus::Opts::new("grpc_errors", "Amount of errors returned by nodes."),
+        &["Code"],
+    )?;
+    let last_finalized_block_height = IntGauge::new(
+        "last_finalized_block_height",
+        "Height of the last finalized block of the node in use, when it was last observed.",
+    )?;
+    let last_finalized_block_time = IntGauge::new(
+        "last_finalized_block_time",
+        "Time of the last finalized block of the node in use, when it was last observed, in \
+         seconds since the unix epoch.",
+    )?;
+    let skipped_updates = IntCounter::new(
+        "updates_skipped_below_threshold",
+        "Amount of times an update was skipped, because the change was below the threshold.",
+    )?;
+    let identical_updates_skipped = IntCounter::new(
+        "updates_skipped_identical",
+        "Amount of times an update was skipped, because the rate was identical to the rate on \
+         chain.",
+    )?;
+    let update_mismatches = IntCounter::new(
+        "update_mismatch",
+        "Amount of finalized updates, after which the rate on chain differed from the submitted \
+         rate.",
+    )?;
+    let heartbeat_updates = IntCounter::new(
+        "heartbeat_updates",
+        "Amount of updates, which were forced after too many consecutive skipped updates.",
+    )?;
+    let clamped_updates = IntCounter::new(
+        "clamped_updates",
+        "Amount of times an update was clamped, because the change exceeded the maximum change.",
+    )?;
+    let source_disabled = IntGaugeVec::new(
+        prometheus::Opts::new("source_disabled", "Whether a source is disabled (1) or not (0)."),
+        &["Source"],
+    )?;
+    let failed_cycles = IntGauge::new(
+        "failed_update_cycles",
+        "Amount of consecutive update cycles, in which no rate could be computed.",
+    )?;
+    let consecutive_failures = IntGauge::new(
+        "consecutive_failed_cycles",
+        "Amount of consecutive update cycles, in which no rate could be computed, or the update \
+         could not be submitted or confirmed.",
+    )?;
+    let sources_configured =
+        IntGauge::new("sources_configured", "Number of sources, which are configured.")?;
+    let sources_healthy = IntGauge::new(
+        "sources_healthy",
+        "Number of sources, which had a non-stale reading in the last update cycle.",
+    )?;
+    let history_length = IntGaugeVec::new(
+        prometheus::Opts::new("history_length", "Number of readings in the history of a source."),
+        &["Source"],
+    )?;
+    let failed_conversions = IntCounter::new(
+        "failed_rate_conversions",
+        "Amount of times the computed rate could not be converted into an exchange rate.",
+    )?;
+    let aggregation_canary = GaugeVec::new(
+        prometheus::Opts::new(
+            "exchange_rate_canary",
+            "Last exchange rate computed by an aggregation strategy, for comparison.",
+        ),
+        &["Aggregation"],
+    )?;
     registry.register(Box::new(exchange_rate_read.clone()))?;
     registry.register(Box::new(exchange_rate_updated.clone()))?;
+    registry.register(Box::new(exchange_rate_computed.clone()))?;
+    registry.register(Box::new(exchange_rate_smoothed.clone()))?;
+    registry.register(Box::new(exchange_rate_candidate.clone()))?;
+    registry.register(Box::new(exchange_rate_on_chain.clone()))?;
     registry.register(Box::new(warning_threshold_violations.clone()))?;
+    registry.register(Box::new(halt_threshold_violations.clone()))?;
+    registry.register(Box::new(halt_action.clone()))?;
+    registry.register(Box::new(halt_violation_streak.clone()))?;
+    registry.register(Box::new(rate_bound_violations.clone()))?;
+    registry.register(Box::new(daily_change.clone()))?;
+    registry.register(Box::new(daily_change_limited.clone()))?;
+    registry.register(Box::new(source_spread.clone()))?;
+    registry.register(Box::new(source_spread_ratio.clone()))?;
+    registry.register(Box::new(source_spread_skips.clone()))?;
+    registry.register(Box::new(source_spread_halts.clone()))?;
+    registry.register(Box::new(agreeing_sources.clone()))?;
+    registry.register(Box::new(agreement_skips.clone()))?;
     registry.register(Box::new(read_attempts.clone()))?;
     registry.register(Box::new(update_attempts.clone()))?;
     registry.register(Box::new(protected.clone()))?;
+    registry.register(Box::new(update_overdue.clone()))?;
+    registry.register(Box::new(paused.clone()))?;
+    registry.register(Box::new(protected_mode_transitions.clone()))?;
+    registry.register(Box::new(protected_mode_recoveries.clone()))?;
     registry.register(Box::new(failed_database_updates.clone()))?;
+    registry.register(Box::new(identical_readings.clone()))?;
+    registry.register(Box::new(source_http_responses.clone()))?;
+    registry.register(Box::new(rate_limited.clone()))?;
+    registry.register(Box::new(update_confirmations.clone()))?;
+    registry.register(Box::new(node_submissions.clone()))?;
+    registry.register(Box::new(parse_errors.clone()))?;
+    registry.register(Box::new(source_used.clone()))?;
+    registry.register(Box::new(node_in_use.clone()))?;
+    registry.register(Box::new(node_failovers.clone()))?;
+    registry.register(Box::new(node_reconnect_attempts.clone()))?;
+    registry.register(Box::new(grpc_errors.clone()))?;
+    registry.register(Box::new(last_finalized_block_height.clone()))?;
+    registry.register(Box::new(last_finalized_block_time.clone()))?;
+    registry.register(Box::new(skipped_updates.clone()))?;
+    registry.register(Box::new(identical_updates_skipped.clone()))?;
+    registry.register(Box::new(update_mismatches.clone()))?;
+    registry.register(Box::new(heartbeat_updates.clone()))?;
+    registry.register(Box::new(clamped_updates.clone()))?;
+    registry.register(Box::new(source_disabled.clone()))?;
+    registry.register(Box::new(failed_cycles.clone()))?;
+    registry.register(Box::new(consecutive_failures.clone()))?;
+    registry.register(Box::new(sources_configured.clone()))?;
+    registry.register(Box::new(sources_healthy.clone()))?;
+    registry.register(Box::new(history_length.clone()))?;
+    registry.register(Box::new(failed_conversions.clone()))?;
+    registry.register(Box::new(aggregation_canary.clone()))?;
     Ok((registry, Stats {
         exchange_rate_read,
         exchange_rate_updated,
+        exchange_rate_computed,
+        exchange_rate_smoothed,
+        exchange_rate_candidate,
+        exchange_rate_on_chain,
         warning_threshold_violations,
+        halt_threshold_violations,
+        halt_action,
+        halt_violation_streak,
+        rate_bound_violations,
+        daily_change,
+        daily_change_limited,
+        source_spread,
+        source_spread_ratio,
+        source_spread_skips,
+        source_spread_halts,
+        agreeing_sources,
+        agreement_skips,
         read_attempts,
         update_attempts,
         protected,
+        update_overdue,
+        paused,
+        protected_mode_transitions,
+        protected_mode_recoveries,
         failed_database_updates,
+        identical_readings,
+        source_http_responses,
+        rate_limited,
+        update_confirmations,
+        node_submissions,
+        parse_errors,
+        source_used,
+        node_in_use,
+        node_failovers,
+        node_reconnect_attempts,
+        grpc_errors,
+        last_finalized_block_height,
+        last_finalized_block_time,
+        skipped_updates,
+        identical_updates_skipped,
+        update_mismatches,
+        heartbeat_updates,
+        clamped_updates,
+        source_disabled,
+        failed_cycles,
+        consecutive_failures,
+        sources_configured,
+        sources_healthy,
+        history_length,
+        failed_conversions,
+        aggregation_canary,
     }))
 }
@@ -0,0 +1,161 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    path::Path,
+};
+
+/// Why the service was forced into dry run mode. This is written to the file
+/// forcing the dry run, so the reason survives restarts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtectedState {
+    /// When the service was forced into dry run mode.
+    pub timestamp:     chrono::DateTime<chrono::Utc>,
+    /// The rate on chain, when the service was forced into dry run mode. (In
+    /// microCCD/EUR)
+    pub previous_rate: String,
+    /// The rate of the update, which was not submitted. (In microCCD/EUR)
+    pub proposed_rate: String,
+    /// The change from the previous rate to the proposed rate, rounded to
+    /// whole percentages, with its sign.
+    pub change:        String,
+    /// The threshold or bound, which the update violated.
+    pub violated:      String,
+}
+
+impl fmt::Display for ProtectedState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "at {}, changing the rate from {} to {} microCCD/EUR (~{} %) violated {}",
+            self.timestamp, self.previous_rate, self.proposed_rate, self.change, self.violated
+        )
+    }
+}
+
+impl ProtectedState {
+    /// The direction of the change, which violated the threshold or bound.
+    pub fn direction(&self) -> &'static str {
+        if self.change.starts_with('-') {
+            "decrease"
+        } else {
+            "increase"
+        }
+    }
+}
+
+/// Attempts to create a file at the given path containing the given state,
+/// signalling that the service should be forced into dry run mode.
+pub fn force_dry_run(path: &Path, state: &ProtectedState) {
+    let result = File::create(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| serde_json::to_writer_pretty(file, state).map_err(anyhow::Error::from));
+    if let Err(e) = result {
+        log::error!("Failed creating file to force dry run: {}", e)
+    }
+}
+
+/// Checks if the file at the given path, which force_dry_run creates, exists.
+pub fn is_dry_run_forced(path: &Path) -> bool { path.exists() }
+
+/// Reads the state from the file at the given path, which force_dry_run
+/// creates. Returns None, if the file is empty, as created by earlier versions
+/// of the service.
+pub fn read_protected_state(path: &Path) -> anyhow::Result<Option<ProtectedState>> {
+    let contents =
+        std::fs::read_to_string(path).context("Unable to read the file forcing dry run.")?;
+    parse_protected_state(&contents)
+}
+
+fn parse_protected_state(contents: &str) -> anyhow::Result<Option<ProtectedState>> {
+    if contents.trim().is_empty() {
+        return Ok(None);
+    }
+    serde_json::from_str(contents)
+        .context("Invalid contents of the file forcing dry run.")
+        .map(Some)
+}
+
+/// Removes the file at the given path, which force_dry_run creates, if it
+/// exists.
+pub fn clear_forced_dry_run(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the file forcing dry run can be created at the given path, by
+/// creating and removing a file next to it.
+pub fn check_writable(path: &Path) -> anyhow::Result<()> {
+    let file_name = path.file_name().context("The path does not name a file.")?;
+    let mut probe_name = file_name.to_os_string();
+    probe_name.push(".probe");
+    let probe = path.with_file_name(probe_name);
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe)
+        .with_context(|| format!("Unable to create {}", probe.display()))?;
+    std::fs::remove_file(&probe).with_context(|| format!("Unable to remove {}", probe.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_protected_state() {
+        let state = ProtectedState {
+            timestamp:     chrono::Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+            previous_rate: "1000".into(),
+            proposed_rate: "2500".into(),
+            change:        "+150".into(),
+            violated:      "halt-increase-threshold".into(),
+        };
+        let contents = serde_json::to_string_pretty(&state).unwrap();
+        assert_eq!(parse_protected_state(&contents).unwrap(), Some(state.clone()));
+        assert_eq!(
+            state.to_string(),
+            "at 2024-05-01 12:00:00 UTC, changing the rate from 1000 to 2500 microCCD/EUR (~+150 \
+             %) violated halt-increase-threshold"
+        );
+        assert_eq!(state.direction(), "increase");
+        assert_eq!(parse_protected_state("").unwrap(), None);
+        assert!(parse_protected_state("{\"timestamp\": 1}").is_err());
+    }
+
+    #[test]
+    fn test_check_writable() {
+        let dir = std::env::temp_dir().join("eur2ccd-check-writable");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("update.lockfile");
+        assert!(check_writable(&path).is_ok());
+        assert!(!path.exists());
+        assert!(std::fs::read_dir(&dir).unwrap().next().is_none());
+        assert!(check_writable(&dir.join("missing").join("update.lockfile")).is_err());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_force_and_clear_dry_run() {
+        let path = std::env::temp_dir().join("eur2ccd-force-dry-run.lockfile");
+        let state = ProtectedState {
+            timestamp:     chrono::Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+            previous_rate: "1000".into(),
+            proposed_rate: "400".into(),
+            change:        "-60".into(),
+            violated:      "halt-decrease-threshold".into(),
+        };
+        assert!(!is_dry_run_forced(&path));
+        force_dry_run(&path, &state);
+        assert!(is_dry_run_forced(&path));
+        assert_eq!(read_protected_state(&path).unwrap(), Some(state));
+        clear_forced_dry_run(&path).unwrap();
+        assert!(!is_dry_run_forced(&path));
+        assert!(clear_forced_dry_run(&path).is_ok());
+    }
+}
@@ -1,8 +1,73 @@
-use anyhow::{bail, Context};
+use crate::{
+    config::{SECRET_INITIAL_RETRY_INTERVAL, SECRET_MAX_RETRIES},
+    sources::{request_with_backoff, RequestFailure},
+};
+use anyhow::Context;
 use aws_config::meta::region::RegionProviderChain;
-use aws_sdk_secretsmanager::{Client, Region};
+use aws_sdk_secretsmanager::{error::GetSecretValueError, Client, Region, SdkError};
 use concordium_rust_sdk::types::UpdateKeyPair;
-use std::path::PathBuf;
+use std::{future::Future, path::PathBuf};
+
+/// Error codes of AWS, which indicate that the request was throttled.
+const THROTTLING_CODES: [&str; 3] = ["ThrottlingException", "Throttling", "RequestLimitExceeded"];
+
+/// Classifies a failed request for a secret. Throttling, server errors and
+/// network problems are transient, while other errors, e.g. the secret not
+/// being found or access being denied, are permanent.
+fn classify_failure(error: &SdkError<GetSecretValueError>) -> RequestFailure {
+    match error {
+        SdkError::TimeoutError(_)
+        | SdkError::DispatchFailure(_)
+        | SdkError::ResponseError {
+            ..
+        } => RequestFailure::Failed,
+        SdkError::ServiceError {
+            err,
+            raw,
+        } => {
+            if err.code().map_or(false, |code| THROTTLING_CODES.contains(&code)) {
+                RequestFailure::RateLimited {
+                    retry_after: None,
+                }
+            } else if err.is_internal_service_error() || raw.http().status().is_server_error() {
+                RequestFailure::Failed
+            } else {
+                RequestFailure::Permanent
+            }
+        }
+        SdkError::ConstructionFailure(_) => RequestFailure::Permanent,
+    }
+}
+
+/**
+ * Fetches the secret string of the given secret, using the given fetch
+ * function. Transient failures are retried with exponential backoff, and
+ * each retry is logged. Permanent failures are returned immediately.
+ */
+async fn fetch_with_retries<Fut>(
+    secret: &str,
+    fetch: impl Fn() -> Fut,
+    initial_delay: u64,
+    max_retries: u64,
+) -> anyhow::Result<String>
+where
+    Fut: Future<Output = Result<String, RequestFailure>>, {
+    request_with_backoff(
+        fetch,
+        |delay, failure| {
+            log::warn!(
+                "Fetching the secret {} failed ({:?}), retrying in {} seconds",
+                secret,
+                failure,
+                delay
+            )
+        },
+        initial_delay,
+        max_retries,
+    )
+    .await
+    .map_err(|failure| anyhow::anyhow!("Unable to fetch the secret {} ({:?})", secret, failure))
+}
 
 pub async fn get_governance_from_aws(
     region: String,
@@ -16,13 +81,24 @@ pub async fn get_governance_from_aws(
 
     let mut kps: Vec<UpdateKeyPair> = Vec::new();
     for secret in secret_names {
-        let resp = client.get_secret_value().secret_id(secret).send().await?;
-        let raw_secret = match resp.secret_string() {
-            Some(s) => s,
-            None => bail!("Secret string was not present"),
+        let fetch = || async {
+            let resp = client.get_secret_value().secret_id(&secret).send().await.map_err(|e| {
+                log::warn!("Unable to fetch the secret {}: {}", secret, e);
+                classify_failure(&e)
+            })?;
+            match resp.secret_string() {
+                Some(s) => Ok(s.to_string()),
+                None => {
+                    log::error!("Secret string of the secret {} was not present", secret);
+                    Err(RequestFailure::Permanent)
+                }
+            }
         };
-        let additional_key = serde_json::from_str::<UpdateKeyPair>(raw_secret)
-            .context("Could not read keys from secret {}.")?;
+        let raw_secret =
+            fetch_with_retries(&secret, fetch, SECRET_INITIAL_RETRY_INTERVAL, SECRET_MAX_RETRIES)
+                .await?;
+        let additional_key = serde_json::from_str::<UpdateKeyPair>(&raw_secret)
+            .with_context(|| format!("Could not read keys from secret {}.", secret))?;
         kps.push(additional_key);
     }
     Ok(kps)
@@ -38,3 +114,77 @@ pub fn get_governance_from_file(key_paths: &[PathBuf]) -> anyhow::Result<Vec<Upd
         })
         .collect::<anyhow::Result<_>>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_secretsmanager::error::{GetSecretValueErrorKind, ResourceNotFoundException};
+    use aws_smithy_http::{body::SdkBody, operation};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// A service error with the given kind, error code and HTTP status.
+    fn service_error(
+        kind: Option<GetSecretValueErrorKind>,
+        code: &str,
+        status: u16,
+    ) -> SdkError<GetSecretValueError> {
+        let meta = aws_smithy_types::Error::builder().code(code).build();
+        let err = match kind {
+            Some(kind) => GetSecretValueError::new(kind, meta),
+            None => GetSecretValueError::generic(meta),
+        };
+        let response = http::Response::builder().status(status).body(SdkBody::empty()).unwrap();
+        SdkError::ServiceError {
+            err,
+            raw: operation::Response::new(response),
+        }
+    }
+
+    #[test]
+    fn test_classify_failure() {
+        let throttled = service_error(None, "ThrottlingException", 400);
+        assert_eq!(classify_failure(&throttled), RequestFailure::RateLimited {
+            retry_after: None,
+        });
+        let unavailable = service_error(None, "ServiceUnavailable", 503);
+        assert_eq!(classify_failure(&unavailable), RequestFailure::Failed);
+        let not_found = service_error(
+            Some(GetSecretValueErrorKind::ResourceNotFoundException(
+                ResourceNotFoundException::builder().build(),
+            )),
+            "ResourceNotFoundException",
+            400,
+        );
+        assert_eq!(classify_failure(&not_found), RequestFailure::Permanent);
+        let denied = service_error(None, "AccessDeniedException", 400);
+        assert_eq!(classify_failure(&denied), RequestFailure::Permanent);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_with_retries() {
+        // A client failing transiently once, and then succeeding:
+        let attempts = AtomicU32::new(0);
+        let fetch = || {
+            let result = if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                Err(RequestFailure::RateLimited {
+                    retry_after: None,
+                })
+            } else {
+                Ok("secret".to_string())
+            };
+            futures::future::ready(result)
+        };
+        let secret = fetch_with_retries("name", fetch, 0, 2).await.unwrap();
+        assert_eq!(secret, "secret");
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        // A permanent failure is not retried:
+        let attempts = AtomicU32::new(0);
+        let fetch = || {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            futures::future::ready(Err(RequestFailure::Permanent))
+        };
+        assert!(fetch_with_retries("name", fetch, 0, 2).await.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
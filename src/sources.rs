@@ -1,31 +1,189 @@
 use crate::{
     config::{
-        BITFINEX_URL, COINGECKO_URL, COINMARKETCAP_URL, INITIAL_RETRY_INTERVAL, LIVECOINWATCH_URL,
-        MAX_RETRIES,
+        BITFINEX_URL, BITFINEX_WS_SYMBOL, BITFINEX_WS_URL, COINGECKO_URL, COINMARKETCAP_URL,
+        INITIAL_RETRY_INTERVAL, LIVECOINWATCH_URL, MAX_RETRIES, MAX_RETRY_AFTER,
     },
+    helpers::rational_from_decimal_str,
     prometheus,
 };
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
+use futures_util::{SinkExt, StreamExt};
 use num_rational::BigRational;
-use reqwest::Url;
-use serde::Deserialize as SerdeDeserialize;
-use serde_json::json;
+use num_traits::{ToPrimitive, Zero};
+use rand::Rng;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER},
+    Method, StatusCode, Url,
+};
+use serde::{Deserialize as SerdeDeserialize, Deserializer};
+use serde_json::{json, value::RawValue};
 use std::{
     collections::VecDeque,
     fmt,
     future::Future,
-    sync::{Arc, Mutex},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tokio::{
+    net::TcpStream,
+    time::{interval, sleep, Duration},
 };
-use tokio::time::{interval, sleep, Duration};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
 
 pub struct RateHistory {
     pub rates:                  VecDeque<BigRational>,
+    /// The timestamps (in seconds) of the readings in `rates`, in the same
+    /// order.
+    pub reading_timestamps:     VecDeque<i64>,
     pub last_reading_timestamp: i64,
+    /// The last raw reading received from the source.
+    pub last_raw_reading:       Option<f64>,
+    /// Number of consecutive readings that were identical to the last raw
+    /// reading. A long streak indicates that the source has frozen.
+    pub identical_readings:     u32,
+    /// Whether the source has been disabled through the admin endpoint. A
+    /// disabled source is not polled, and is excluded from updates.
+    pub disabled:               bool,
+    /// Whether the history has been recovered, after a task panicked while
+    /// holding it.
+    pub recovered:              bool,
+}
+
+impl RateHistory {
+    /// An empty history, with room for the given number of readings.
+    pub fn with_capacity(expected_readings: usize) -> Self {
+        RateHistory {
+            rates:                  VecDeque::with_capacity(expected_readings),
+            reading_timestamps:     VecDeque::with_capacity(expected_readings),
+            last_reading_timestamp: 0,
+            last_raw_reading:       None,
+            identical_readings:     0,
+            disabled:               false,
+            recovered:              false,
+        }
+    }
+
+    /// Whether the source has returned the same reading more than the given
+    /// number of consecutive times, and should be considered suspect.
+    pub fn is_frozen(&self, max_identical_readings: u32) -> bool {
+        self.identical_readings > max_identical_readings
+    }
+
+    /// Whether the last reading is too old to be used for an update at time
+    /// `now`. If a maximum age (in seconds) is given, the last reading must
+    /// be at most that old, otherwise the last reading must be after the last
+    /// update.
+    pub fn is_stale(&self, max_age: Option<u32>, last_update_timestamp: i64, now: i64) -> bool {
+        match max_age {
+            Some(max_age) => now - self.last_reading_timestamp > i64::from(max_age),
+            None => self.last_reading_timestamp < last_update_timestamp,
+        }
+    }
+
+    /// Adds the given reading, taken at the given timestamp, to the history,
+    /// and removes the readings exceeding the given limit. When the number of
+    /// readings is limited, the oldest reading is removed before the new one is
+    /// added, so the history never exceeds its initial capacity.
+    pub fn push_reading(&mut self, rate: BigRational, timestamp: i64, limit: HistoryLimit) {
+        match limit {
+            HistoryLimit::Count(max_rates_saved) => {
+                while self.rates.len() >= max_rates_saved.max(1) {
+                    self.rates.pop_front();
+                    self.reading_timestamps.pop_front();
+                }
+                self.rates.push_back(rate);
+                self.reading_timestamps.push_back(timestamp);
+            }
+            HistoryLimit::Window(window) => {
+                self.rates.push_back(rate);
+                self.reading_timestamps.push_back(timestamp);
+                self.prune_older_than(timestamp - i64::from(window));
+            }
+        }
+    }
+
+    /// Removes the readings taken before the given timestamp from the history,
+    /// and returns the number of readings removed.
+    pub fn prune_older_than(&mut self, cutoff: i64) -> usize {
+        let mut pruned = 0;
+        while self.reading_timestamps.front().map_or(false, |timestamp| *timestamp < cutoff) {
+            self.reading_timestamps.pop_front();
+            self.rates.pop_front();
+            pruned += 1;
+        }
+        pruned
+    }
+}
+
+/// Locks the given history of the source with the given label. If a task
+/// panicked while holding the lock, the history is recovered, instead of
+/// panicking as well. The lock stays poisoned, so the recovery is only logged
+/// the first time.
+pub fn lock_history(
+    history: &Mutex<RateHistory>,
+    label: impl fmt::Display,
+) -> MutexGuard<RateHistory> {
+    history.lock().unwrap_or_else(|poisoned| {
+        let mut guard = poisoned.into_inner();
+        if !guard.recovered {
+            log::warn!("{}: Recovering history, which a panicking task held", label);
+            guard.recovered = true;
+        }
+        guard
+    })
+}
+
+/// How the history of rates of a source is bounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryLimit {
+    /// Keep the given number of the latest readings.
+    Count(usize),
+    /// Keep the readings of the given number of latest seconds.
+    Window(u32),
+}
+
+impl HistoryLimit {
+    /// The number of readings the history is expected to hold, when the
+    /// source is polled with the given interval (in seconds).
+    pub fn expected_readings(&self, pull_interval: u32) -> usize {
+        match self {
+            HistoryLimit::Count(max_rates) => *max_rates,
+            HistoryLimit::Window(window) => (window / pull_interval) as usize + 1,
+        }
+    }
+
+    /// The number of seconds the history is expected to cover, when the source
+    /// is polled with the given interval (in seconds).
+    pub fn expected_duration(&self, pull_interval: u32) -> u64 {
+        match self {
+            HistoryLimit::Count(max_rates) => *max_rates as u64 * u64::from(pull_interval),
+            HistoryLimit::Window(window) => u64::from(*window),
+        }
+    }
+}
+
+/// A source used by the main loop, with the history of rates, which is
+/// maintained by the task polling the source.
+pub struct SourceHandle {
+    pub label:   String,
+    /// Maximum age of the last reading (in seconds), for the source to be used
+    /// in an update. See [RateHistory::is_stale].
+    pub max_age: Option<u32>,
+    /// Sources of higher tiers are only used, when there are not enough
+    /// healthy sources of lower tiers.
+    pub tier:    u8,
+    /// Weight of the source, when aggregating using the weighted median.
+    pub weight:  u32,
+    pub history: Arc<Mutex<RateHistory>>,
 }
 
 #[derive(Clone)]
 pub enum Source {
     Bitfinex,
+    /// Streams the rate from the ticker channel of Bitfinex over a websocket,
+    /// instead of polling it.
+    BitfinexWs,
     /// Only used for testing, assumes the url accepts a GET request, and serves
     /// a json response, consisting of a list with a number value. i.e. [1.0]
     /// The label is used to differentiate between different test sources in
@@ -34,42 +192,627 @@ pub enum Source {
         url:   Url,
         label: String,
     },
+    /// Pulled with a request using the given method, and the body as json, if
+    /// any. The rate is extracted from the json response using the JSON
+    /// pointer (RFC 6901).
+    Generic {
+        url:          Url,
+        json_pointer: String,
+        label:        String,
+        method:       Method,
+        body:         Option<serde_json::Value>,
+    },
     CoinGecko,
     LiveCoinWatch(String), // param is api key
     CoinMarketCap(String), // param is api key
+    /// Derived from the price of CCD in another currency, given by the base
+    /// source, and the price of EUR in that currency, given by the fx source,
+    /// i.e. EUR/CCD = (USD/CCD) / (USD/EUR). Both sub-sources are requested
+    /// in every poll, see [request_rate].
+    Derived {
+        base:  Box<Source>,
+        fx:    Box<Source>,
+        label: String,
+    },
 }
 
 impl fmt::Display for Source {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Source::Bitfinex => write!(f, "bitfinex"),
+            Source::BitfinexWs => write!(f, "bitfinex_ws"),
             Source::LiveCoinWatch(_) => write!(f, "live_coin_watch"),
             Source::CoinMarketCap(_) => write!(f, "coin_market_cap"),
             Source::CoinGecko => write!(f, "coin_gecko"),
             Source::Test {
                 label,
                 ..
+            }
+            | Source::Generic {
+                label,
+                ..
+            }
+            | Source::Derived {
+                label,
+                ..
             } => write!(f, "{}", label),
         }
     }
 }
 
+/// The configuration of a generic source.
+/// Parsed from the format `<url>,<pointer>,<label>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenericSource {
+    pub url:          Url,
+    pub json_pointer: String,
+    pub label:        String,
+}
+
+impl FromStr for GenericSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Split from the right, as the url may contain commas.
+        let mut parts = s.rsplitn(3, ',');
+        let (label, json_pointer, url) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(label), Some(json_pointer), Some(url)) => (label, json_pointer, url),
+            _ => anyhow::bail!("Expected a generic source of the form <url>,<pointer>,<label>"),
+        };
+        let json_pointer = json_pointer.trim();
+        anyhow::ensure!(
+            json_pointer.is_empty() || json_pointer.starts_with('/'),
+            "Invalid JSON pointer: {}, expected it to start with /",
+            json_pointer
+        );
+        let label = label.trim();
+        anyhow::ensure!(!label.is_empty(), "The label of a generic source must not be empty");
+        Ok(GenericSource {
+            url:          Url::parse(url.trim())
+                .with_context(|| format!("Invalid url: {}", url.trim()))?,
+            json_pointer: json_pointer.to_string(),
+            label:        label.to_string(),
+        })
+    }
+}
+
+impl GenericSource {
+    /// The source, which is pulled with GET requests.
+    pub fn into_source(self) -> Source {
+        Source::Generic {
+            url:          self.url,
+            json_pointer: self.json_pointer,
+            label:        self.label,
+            method:       Method::GET,
+            body:         None,
+        }
+    }
+}
+
+/// Parses the contents of a sources file, which contains a generic source in
+/// the format `<url>,<pointer>,<label>` on each line. Empty lines and lines
+/// starting with `#` are ignored.
+pub fn parse_sources_file(contents: &str) -> anyhow::Result<Vec<GenericSource>> {
+    let mut sources: Vec<GenericSource> = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let source = GenericSource::from_str(line).with_context(|| format!("Line {}", i + 1))?;
+        anyhow::ensure!(
+            sources.iter().all(|other| other.label != source.label),
+            "Multiple sources with the label {}",
+            source.label
+        );
+        sources.push(source);
+    }
+    Ok(sources)
+}
+
+/// Reads the generic sources from the sources file at the given path.
+pub fn read_sources_file(path: &Path) -> anyhow::Result<Vec<GenericSource>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Unable to read the sources file {}", path.display()))?;
+    parse_sources_file(&contents)
+        .with_context(|| format!("Invalid sources file {}", path.display()))
+}
+
+/// A value of an option, which is given for a specific source.
+/// Parsed from the format `<label>=<value>`, where the value is parsed as
+/// `T`. The label and the value are trimmed.
+#[derive(Debug, Clone)]
+pub struct LabelledOption<T> {
+    pub label: String,
+    pub value: T,
+}
+
+impl<T> LabelledOption<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    /// Parses the format `<label><separator><value>`, splitting at the first
+    /// occurrence of the separator.
+    pub fn parse_with_separator(s: &str, separator: char) -> anyhow::Result<Self> {
+        let (label, value) = s
+            .split_once(separator)
+            .with_context(|| format!("Expected the form <label>{}<value>", separator))?;
+        let value = value.trim();
+        Ok(LabelledOption {
+            label: label.trim().to_string(),
+            value: value.parse().map_err(|e| anyhow!("Invalid value {}: {}", value, e))?,
+        })
+    }
+}
+
+impl<T> FromStr for LabelledOption<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Self::parse_with_separator(s, '=') }
+}
+
+/// The configuration of a derived source, whose sub-sources are pulled with
+/// GET requests, and their prices are extracted with JSON pointers.
+/// Parsed from the format
+/// `<label>=<base url>,<base pointer>|<fx url>,<fx pointer>`.
+#[derive(Debug, Clone)]
+pub struct DerivedSource {
+    pub label:        String,
+    pub base_url:     Url,
+    pub base_pointer: String,
+    pub fx_url:       Url,
+    pub fx_pointer:   String,
+}
+
+impl DerivedSource {
+    /// Builds the source, labelling the sub-sources after it.
+    pub fn into_source(self) -> Source {
+        let sub_source = |url, json_pointer, suffix| {
+            Box::new(Source::Generic {
+                url,
+                json_pointer,
+                label: format!("{}_{}", self.label, suffix),
+                method: Method::GET,
+                body: None,
+            })
+        };
+        Source::Derived {
+            base:  sub_source(self.base_url, self.base_pointer, "base"),
+            fx:    sub_source(self.fx_url, self.fx_pointer, "fx"),
+            label: self.label.clone(),
+        }
+    }
+}
+
+impl FromStr for DerivedSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let LabelledOption {
+            label,
+            value: sub_sources,
+        } = LabelledOption::<String>::from_str(s).context(
+            "Expected a derived source of the form <label>=<base url>,<base pointer>|<fx url>,<fx \
+             pointer>",
+        )?;
+        anyhow::ensure!(!label.is_empty(), "The label of a derived source must not be empty");
+        // The | character is not allowed in urls, so it can separate the sub-sources.
+        let (base, fx) = sub_sources
+            .split_once('|')
+            .context("Expected the base and fx sources separated by |")?;
+        let parse_sub_source = |sub_source: &str| -> anyhow::Result<(Url, String)> {
+            let (url, json_pointer) = sub_source
+                .rsplit_once(',')
+                .context("Expected a sub-source of the form <url>,<pointer>")?;
+            let json_pointer = json_pointer.trim();
+            anyhow::ensure!(
+                json_pointer.is_empty() || json_pointer.starts_with('/'),
+                "Invalid JSON pointer: {}, expected it to start with /",
+                json_pointer
+            );
+            let url =
+                Url::parse(url.trim()).with_context(|| format!("Invalid url: {}", url.trim()))?;
+            Ok((url, json_pointer.to_string()))
+        };
+        let (base_url, base_pointer) = parse_sub_source(base)?;
+        let (fx_url, fx_pointer) = parse_sub_source(fx)?;
+        Ok(DerivedSource {
+            label,
+            base_url,
+            base_pointer,
+            fx_url,
+            fx_pointer,
+        })
+    }
+}
+
+/// The HTTP method to use for requests to a specific generic source.
+/// Parsed from the format `<label>=<method>`.
+#[derive(Debug, Clone)]
+pub struct GenericSourceMethod {
+    pub label:  String,
+    pub method: Method,
+}
+
+impl FromStr for GenericSourceMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let LabelledOption {
+            label,
+            value: method,
+        } = LabelledOption::<String>::from_str(s)
+            .context("Expected a method of the form <label>=<method>")?;
+        let method = method.to_uppercase();
+        Ok(GenericSourceMethod {
+            label,
+            method: Method::from_str(&method)
+                .with_context(|| format!("Invalid method: {}", method))?,
+        })
+    }
+}
+
+/// The json body to send in requests to a specific generic source.
+/// Parsed from the format `<label>=<json>`.
+#[derive(Debug, Clone)]
+pub struct GenericSourceBody {
+    pub label: String,
+    pub body:  serde_json::Value,
+}
+
+impl FromStr for GenericSourceBody {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let LabelledOption {
+            label,
+            value: body,
+        } = LabelledOption::from_str(s)
+            .context("Expected a request body of the form <label>=<json>")?;
+        Ok(GenericSourceBody {
+            label,
+            body,
+        })
+    }
+}
+
+/// An additional header to attach to every request to a specific source.
+/// Parsed from the format `<label>:<Header-Name>: <value>`.
+#[derive(Debug, Clone)]
+pub struct SourceHeader {
+    pub label: String,
+    pub name:  HeaderName,
+    pub value: HeaderValue,
+}
+
+impl FromStr for SourceHeader {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let LabelledOption {
+            label,
+            value: header,
+        } = LabelledOption::<String>::parse_with_separator(s, ':')
+            .context("Expected a source header of the form <label>:<Header-Name>: <value>")?;
+        let (name, value) = header
+            .split_once(':')
+            .context("Expected a header of the form <Header-Name>: <value>")?;
+        Ok(SourceHeader {
+            label,
+            name: HeaderName::from_str(name.trim())
+                .with_context(|| format!("Invalid header name: {}", name.trim()))?,
+            value: HeaderValue::from_str(value.trim())
+                .with_context(|| format!("Invalid value for header {}", name.trim()))?,
+        })
+    }
+}
+
+impl fmt::Display for SourceHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if is_secret_header(&self.name) {
+            write!(f, "{}: <redacted>", self.name)
+        } else {
+            write!(f, "{}: {}", self.name, self.value.to_str().unwrap_or("<non-ascii value>"))
+        }
+    }
+}
+
+/// Whether the value of the given header is likely a secret, and should not be
+/// logged.
+fn is_secret_header(name: &HeaderName) -> bool {
+    let name = name.as_str();
+    ["authorization", "api-key", "apikey", "token"].iter().any(|pattern| name.contains(pattern))
+}
+
+/// A CA certificate to trust for requests to a specific source, instead of
+/// the built-in root certificates.
+/// Parsed from the format `<label>=<path>`.
+#[derive(Debug, Clone)]
+pub struct SourceCaCertificate {
+    pub label: String,
+    pub path:  PathBuf,
+}
+
+impl FromStr for SourceCaCertificate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let LabelledOption {
+            label,
+            value: path,
+        } = LabelledOption::from_str(s)
+            .context("Expected a source CA certificate of the form <label>=<path>")?;
+        Ok(SourceCaCertificate {
+            label,
+            path,
+        })
+    }
+}
+
+/// The tier of a specific source.
+/// Parsed from the format `<label>=<tier>`.
+#[derive(Debug, Clone)]
+pub struct SourceTier {
+    pub label: String,
+    pub tier:  u8,
+}
+
+impl FromStr for SourceTier {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let LabelledOption {
+            label,
+            value: tier,
+        } = LabelledOption::from_str(s)
+            .context("Expected a source tier of the form <label>=<tier>")?;
+        Ok(SourceTier {
+            label,
+            tier,
+        })
+    }
+}
+
+/// The size of the history of rates, either of every source, or of a specific
+/// source, which overrides it.
+/// Parsed from the format `<max rates>` or `<label>=<max rates>`.
+#[derive(Debug, Clone)]
+pub struct MaxRatesSaved {
+    pub label:     Option<String>,
+    pub max_rates: usize,
+}
+
+impl FromStr for MaxRatesSaved {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (label, max_rates) = if s.contains('=') {
+            let LabelledOption {
+                label,
+                value,
+            } = LabelledOption::from_str(s)
+                .context("Expected a number of rates saved of the form <label>=<max rates>")?;
+            (Some(label), value)
+        } else {
+            let max_rates = s.trim();
+            let max_rates: usize = max_rates
+                .parse()
+                .with_context(|| format!("Invalid number of rates saved: {}", max_rates))?;
+            (None, max_rates)
+        };
+        anyhow::ensure!(max_rates > 0, "At least one rate must be saved");
+        Ok(MaxRatesSaved {
+            label,
+            max_rates,
+        })
+    }
+}
+
+/// Proxies to route requests to sources through.
+#[derive(Debug, Clone, Default)]
+pub struct ProxyConfig {
+    /// Proxy for requests to http URLs.
+    pub http:  Option<Url>,
+    /// Proxy for requests to https URLs.
+    pub https: Option<Url>,
+}
+
+/**
+ * Create a builder for a client, which identifies itself with the given
+ * user agent, and routes requests through the given proxies. The client
+ * accepts gzip and deflate compressed responses, and decompresses them
+ * transparently.
+ */
+fn client_builder(
+    user_agent: &str,
+    proxies: &ProxyConfig,
+) -> anyhow::Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::Client::builder().user_agent(user_agent).gzip(true).deflate(true);
+    if let Some(url) = &proxies.http {
+        builder = builder.proxy(reqwest::Proxy::http(url.clone()).context("Invalid http proxy.")?);
+    }
+    if let Some(url) = &proxies.https {
+        builder =
+            builder.proxy(reqwest::Proxy::https(url.clone()).context("Invalid https proxy.")?);
+    }
+    Ok(builder)
+}
+
+/// The weight of a specific source.
+/// Parsed from the format `<label>=<weight>`.
+#[derive(Debug, Clone)]
+pub struct SourceWeight {
+    pub label:  String,
+    pub weight: u32,
+}
+
+impl FromStr for SourceWeight {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let LabelledOption {
+            label,
+            value: weight,
+        } = LabelledOption::<u32>::from_str(s)
+            .context("Expected a source weight of the form <label>=<weight>")?;
+        anyhow::ensure!(weight > 0, "Weight must be positive");
+        Ok(SourceWeight {
+            label,
+            weight,
+        })
+    }
+}
+
+/**
+ * Build a client, which identifies itself with the given user agent, and
+ * routes requests through the given proxies.
+ */
+pub fn get_client(user_agent: &str, proxies: &ProxyConfig) -> anyhow::Result<reqwest::Client> {
+    client_builder(user_agent, proxies)?.build().context("Could not build client.")
+}
+
+/**
+ * Build a client, which only trusts the CA certificates at the given paths,
+ * which are expected to be PEM encoded, instead of the built-in root
+ * certificates. It identifies itself with the given user agent, and routes
+ * requests through the given proxies. Any certificate issued by one of the
+ * CA certificates is accepted, so this does not pin the certificate of the
+ * source itself. A self-signed certificate of the source can be given as
+ * its own CA. Multiple certificates can be given, to allow a source to
+ * rotate its certificate.
+ */
+pub fn get_client_with_ca_certificates(
+    paths: &[PathBuf],
+    user_agent: &str,
+    proxies: &ProxyConfig,
+) -> anyhow::Result<reqwest::Client> {
+    let mut builder = client_builder(user_agent, proxies)?.tls_built_in_root_certs(false);
+    for path in paths {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("Could not read certificate file {}.", path.display()))?;
+        let certificate = reqwest::Certificate::from_pem(&pem)
+            .with_context(|| format!("Could not parse certificate in {}.", path.display()))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    builder.build().context("Could not build client with the CA certificates.")
+}
+
 trait RequestExchangeRate: fmt::Display {
     /**
      * Pulls the exchange rate using the provided client from the given
-     * source.
+     * source. The extra headers are attached to the request.
      */
-    fn get_request(&self, client: reqwest::Client) -> reqwest::RequestBuilder;
+    fn get_request(
+        &self,
+        client: reqwest::Client,
+        extra_headers: &HeaderMap,
+    ) -> reqwest::RequestBuilder;
     /**
      * Takes the raw response, and extracts the exchange rate
      */
-    fn parse_response(&self, response_bytes: &[u8]) -> anyhow::Result<f64>;
+    fn parse_response(&self, response_bytes: &[u8]) -> Result<Price, SourceError>;
+}
+
+/// A price as reported by a source. If the source sent the price as a
+/// decimal number, its text is kept, so the price can be converted into the
+/// exact decimal fraction, instead of the binary fraction of the float.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Price {
+    pub value:   f64,
+    pub decimal: Option<String>,
+    /// The exact price, if it was derived from the prices of other sources.
+    pub exact:   Option<BigRational>,
+}
+
+impl Price {
+    /// Returns None, if the text is not a finite decimal number.
+    fn from_decimal(decimal: &str) -> Option<Self> {
+        let value = decimal.parse::<f64>().ok().filter(|value| value.is_finite())?;
+        Some(Price {
+            value,
+            decimal: Some(decimal.to_string()),
+            exact: None,
+        })
+    }
+
+    /// Derives the price of CCD in EUR from the price of CCD in another
+    /// currency, and the price of EUR in that currency. Returns None, if the
+    /// latter is zero, or either cannot be converted into a fraction.
+    fn derived(base: &Price, fx: &Price) -> Option<Self> {
+        let base = base.to_rational()?;
+        let fx = fx.to_rational()?;
+        if fx.is_zero() {
+            return None;
+        }
+        let exact = base / fx;
+        Some(Price {
+            value:   exact.to_f64()?,
+            decimal: None,
+            exact:   Some(exact),
+        })
+    }
+
+    /// Parses a json number, or a string containing a decimal number.
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        match value {
+            serde_json::Value::String(decimal) => Price::from_decimal(decimal),
+            // The text of the number is lost, when it is parsed into a Value,
+            // but it is printed as the shortest decimal with the same float.
+            serde_json::Value::Number(number) => Price::from_decimal(&number.to_string()),
+            _ => None,
+        }
+    }
+
+    /// The price as a fraction. The exact price is used, if it was derived, or
+    /// the exact decimal fraction, if the decimal is known, and otherwise the
+    /// binary fraction of the float.
+    pub fn to_rational(&self) -> Option<BigRational> {
+        self.exact
+            .clone()
+            .or_else(|| self.decimal.as_deref().and_then(rational_from_decimal_str))
+            .or_else(|| BigRational::from_float(self.value))
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.decimal {
+            Some(decimal) => write!(f, "{}", decimal),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+impl<'de> SerdeDeserialize<'de> for Price {
+    /// Deserializes a json number, or a string containing a decimal number,
+    /// keeping the text as it was sent.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = Box::<RawValue>::deserialize(deserializer)?;
+        let text = raw.get();
+        let price = if text.starts_with('"') {
+            serde_json::from_str::<String>(text)
+                .ok()
+                .and_then(|decimal| Price::from_decimal(&decimal))
+        } else {
+            Price::from_decimal(text)
+        };
+        price.ok_or_else(|| serde::de::Error::custom(format!("invalid price: {}", text)))
+    }
 }
 
 impl RequestExchangeRate for Source {
-    fn get_request(&self, client: reqwest::Client) -> reqwest::RequestBuilder {
-        match self {
-            Source::Bitfinex => {
+    fn get_request(
+        &self,
+        client: reqwest::Client,
+        extra_headers: &HeaderMap,
+    ) -> reqwest::RequestBuilder {
+        let request = match self {
+            Source::Bitfinex | Source::BitfinexWs => {
                 client.post(BITFINEX_URL).json(&json!({"ccy1": "CCD", "ccy2": "EUR"}))
             }
             Source::LiveCoinWatch(api_key) => client
@@ -84,141 +827,473 @@ impl RequestExchangeRate for Source {
                 url,
                 ..
             } => client.get(url.clone()),
-        }
+            Source::Generic {
+                url,
+                method,
+                body,
+                ..
+            } => {
+                let request = client.request(method.clone(), url.clone());
+                match body {
+                    Some(body) => request.json(body),
+                    None => request,
+                }
+            }
+            // Derived sources are requested through their sub-sources, so this is
+            // only the request to the base source.
+            Source::Derived {
+                base,
+                ..
+            } => return base.get_request(client, extra_headers),
+        };
+        request.headers(extra_headers.clone())
     }
 
-    fn parse_response(&self, response_bytes: &[u8]) -> anyhow::Result<f64> {
+    fn parse_response(&self, response_bytes: &[u8]) -> Result<Price, SourceError> {
         match self {
             Source::Bitfinex
+            | Source::BitfinexWs
             | Source::Test {
                 ..
-            } => serde_json::from_slice::<Vec<f64>>(response_bytes)?
-                .first()
-                .copied()
-                .ok_or_else(|| anyhow!("Unexpected missing value")),
+            } => serde_json::from_slice::<Vec<Price>>(response_bytes)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| SourceError::Malformed("Unexpected missing value".into())),
             Source::LiveCoinWatch(_) => {
                 Ok(serde_json::from_slice::<LiveCoinWatchResponse>(response_bytes)?.rate)
             }
             Source::CoinMarketCap(_) => {
                 let response = serde_json::from_slice::<CoinMarketCapResponse>(response_bytes)?;
-                if response.status.error_code != 0 {
-                    return Err(anyhow!(response.status.error_message.unwrap_or(format!(
-                        "No error message, but the code was {}",
-                        response.status.error_code
-                    ))));
+                let code = response.status.error_code;
+                if code != 0 {
+                    let message = response
+                        .status
+                        .error_message
+                        .unwrap_or(format!("No error message, but the code was {}", code));
+                    return Err(match code {
+                        // The API key is invalid, missing, inactive, expired or not allowed to
+                        // use the endpoint.
+                        1001..=1007 => SourceError::Unauthorized(message),
+                        _ => SourceError::Api {
+                            code,
+                            message,
+                        },
+                    });
                 }
-                Ok(response.data.ccd.quote.eur.price)
+                response
+                    .data
+                    .map(|data| data.ccd.quote.eur.price)
+                    .ok_or_else(|| SourceError::Malformed("Unexpected missing data".into()))
             }
             Source::CoinGecko => {
                 Ok(serde_json::from_slice::<CoinGeckoResponse>(response_bytes)?.concordium.eur)
             }
+            Source::Generic {
+                json_pointer,
+                ..
+            } => serde_json::from_slice::<serde_json::Value>(response_bytes)?
+                .pointer(json_pointer)
+                .and_then(Price::from_json)
+                .ok_or_else(|| SourceError::Malformed(format!("No number at {}", json_pointer))),
+            Source::Derived {
+                base,
+                ..
+            } => base.parse_response(response_bytes),
+        }
+    }
+}
+
+/// Reason the response of a source could not be parsed into a rate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceError {
+    /// The response is not valid json, or does not have the expected
+    /// structure.
+    Malformed(String),
+    /// The source reported an error in its response.
+    Api {
+        code:    u16,
+        message: String,
+    },
+    /// The source rejected our credentials, so retrying will not help.
+    Unauthorized(String),
+}
+
+impl SourceError {
+    /// Whether the error will persist, such that retrying is not worthwhile.
+    pub fn is_permanent(&self) -> bool { matches!(self, SourceError::Unauthorized(_)) }
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SourceError::Malformed(message) => write!(f, "{}", message),
+            SourceError::Api {
+                code,
+                message,
+            } => write!(f, "Error reported by the source (code {}): {}", code, message),
+            SourceError::Unauthorized(message) => {
+                write!(f, "Credentials rejected by the source: {}", message)
+            }
         }
     }
 }
 
+impl From<serde_json::Error> for SourceError {
+    fn from(e: serde_json::Error) -> Self { SourceError::Malformed(e.to_string()) }
+}
+
+/// Reason a request for the exchange rate failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestFailure {
+    /// The request failed for any reason other than rate limiting.
+    Failed,
+    /// The source responded with 429 (Too Many Requests) or 503 (Service
+    /// Unavailable). Contains the number of seconds the source asked us to
+    /// wait before retrying, if the Retry-After header was present.
+    RateLimited {
+        retry_after: Option<u64>,
+    },
+    /// The request failed in a way that will persist, e.g. because the source
+    /// rejected our credentials, so retrying is not worthwhile.
+    Permanent,
+}
+
 /**
  * Wrapper for a request function, for continous attempts, with exponential
  * backoff.
+ * If the source is rate limiting us and has specified how long to wait, we
+ * wait at least that long (capped at [MAX_RETRY_AFTER]) before the next
+ * attempt.
  * on_fail is invoked after every failed attempt of the request, but only if
- * there are any retries left.
+ * there are any retries left. Its arguments are the number of seconds until
+ * the next attempt, and the reason the attempt failed.
+ * Returns the reason of the last failure, if all retries are exhausted, or
+ * immediately, if the failure is permanent.
  */
-async fn request_with_backoff<'a, Fut, T>(
+pub async fn request_with_backoff<'a, Fut, T>(
     request_fn: impl Fn() -> Fut,
-    on_fail: impl Fn(u64),
+    on_fail: impl Fn(u64, RequestFailure),
     initial_delay: u64,
     max_retries: u64,
-) -> Option<T>
+) -> Result<T, RequestFailure>
 where
-    Fut: Future<Output = Option<T>>,
+    Fut: Future<Output = Result<T, RequestFailure>>,
     Fut: 'a, {
     let mut timeout = initial_delay;
     let mut retries = max_retries;
     loop {
-        if let Some(i) = request_fn().await {
-            return Some(i);
-        }
+        let failure = match request_fn().await {
+            Ok(i) => return Ok(i),
+            Err(failure) => failure,
+        };
 
-        if retries == 0 {
-            return None;
+        if retries == 0 || failure == RequestFailure::Permanent {
+            return Err(failure);
         }
 
-        on_fail(timeout);
+        let delay = match failure {
+            RequestFailure::RateLimited {
+                retry_after: Some(retry_after),
+            } => timeout.max(retry_after.min(MAX_RETRY_AFTER)),
+            _ => timeout,
+        };
+        on_fail(delay, failure);
 
         retries -= 1;
-        sleep(Duration::from_secs(timeout)).await;
+        sleep(Duration::from_secs(delay)).await;
         timeout *= 2;
     }
 }
 
+/**
+ * Parse the value of a Retry-After header into a number of seconds to wait.
+ * The header is either a number of seconds, or a HTTP date.
+ */
+fn parse_retry_after(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(seconds);
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let seconds = date.timestamp() - chrono::offset::Utc::now().timestamp();
+    Some(seconds.max(0) as u64)
+}
+
 /**
  * Auxillary function for requesting exchange rate.
  * Handles common behaviour among functions for requesting exchange rate.
  * The parser should handle converting the JSON response body into an
  * exchange rate, and its parameter specifies the expected JSON format.
  */
-async fn request_exchange_rate(source: &Source, client: reqwest::Client) -> Option<f64> {
-    let resp = match source.get_request(client).send().await {
+async fn request_exchange_rate(
+    stats: &prometheus::Stats,
+    source: &Source,
+    client: reqwest::Client,
+    extra_headers: &HeaderMap,
+) -> Result<Price, RequestFailure> {
+    let resp = match source.get_request(client, extra_headers).send().await {
         Ok(o) => o,
         Err(e) => {
             log::warn!("{}: Unable to send request: {}", source, e);
-            return None;
+            return Err(RequestFailure::Failed);
         }
     };
-    if resp.status().is_success() {
+    let status = resp.status();
+    stats.increment_http_responses(source, status.as_u16());
+    if status.is_success() {
         match resp.bytes().await {
             Ok(bytes) => match source.parse_response(&bytes) {
                 Ok(val) => {
-                    if val < 0.0 {
+                    if val.value < 0.0 {
                         log::error!("{}: Exchange rate is negative: {}", source, val);
-                        return None;
+                        return Err(RequestFailure::Failed);
                     }
                     log::debug!("{}: Raw exchange rate CCD in EUR polled: {}", source, val);
-                    return Some(val);
+                    return Ok(val);
                 }
-                Err(err) => {
-                    log::error!("{}: Unable to parse response: {}", source, err)
+                Err(err @ SourceError::Malformed(_)) => {
+                    log::error!("{}: Unable to parse response: {}", source, err);
+                    stats.increment_parse_errors(source);
+                }
+                Err(
+                    err @ SourceError::Api {
+                        ..
+                    },
+                ) => {
+                    log::warn!("{}: Unable to get exchange rate: {}", source, err);
+                    stats.increment_parse_errors(source);
+                }
+                Err(err @ SourceError::Unauthorized(_)) => {
+                    log::error!("{}: Unable to get exchange rate: {}", source, err);
+                    stats.increment_parse_errors(source);
+                    return Err(RequestFailure::Permanent);
                 }
             },
             Err(err) => {
                 log::error!("{}: Unable to read response bytes: {}", source, err)
             }
         }
+    } else if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+        let retry_after = resp
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        log::warn!("{}: rate limited: {} (retry after: {:?} seconds)", source, status, retry_after);
+        return Err(RequestFailure::RateLimited {
+            retry_after,
+        });
+    } else if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        log::error!("{}: credentials rejected: {}", source, status);
+        return Err(RequestFailure::Permanent);
     } else {
-        log::error!("{}: unsuccessful response: {}", source, resp.status());
+        log::error!("{}: unsuccessful response: {}", source, status);
     };
-    None
+    Err(RequestFailure::Failed)
+}
+
+/**
+ * Requests the exchange rate from the source. Derived sources request both
+ * of their sub-sources, and derive the rate from their prices.
+ */
+async fn request_rate(
+    stats: &prometheus::Stats,
+    source: &Source,
+    client: reqwest::Client,
+    extra_headers: &HeaderMap,
+) -> Result<Price, RequestFailure> {
+    match source {
+        Source::Derived {
+            base,
+            fx,
+            ..
+        } => {
+            let base_price =
+                request_exchange_rate(stats, base, client.clone(), extra_headers).await?;
+            let fx_price = request_exchange_rate(stats, fx, client, extra_headers).await?;
+            match Price::derived(&base_price, &fx_price) {
+                Some(price) => {
+                    log::debug!(
+                        "{}: Derived exchange rate {} from {} and {}",
+                        source,
+                        price,
+                        base_price,
+                        fx_price
+                    );
+                    Ok(price)
+                }
+                None => {
+                    log::error!(
+                        "{}: Unable to derive exchange rate from {} and {}",
+                        source,
+                        base_price,
+                        fx_price
+                    );
+                    Err(RequestFailure::Failed)
+                }
+            }
+        }
+        _ => request_exchange_rate(stats, source, client, extra_headers).await,
+    }
+}
+
+/**
+ * Requests the exchange rate from the source once, within the given
+ * timeout, and logs whether it succeeded. Returns whether it succeeded. The
+ * Bitfinex websocket is not probed, as it is not requested, and is counted
+ * as succeeding.
+ */
+pub async fn probe_source(
+    stats: &prometheus::Stats,
+    source: &Source,
+    client: reqwest::Client,
+    extra_headers: &HeaderMap,
+    probe_timeout: Duration,
+) -> bool {
+    if let Source::BitfinexWs = source {
+        log::info!("{}: Source is not probed, as it is streamed", source);
+        return true;
+    }
+    match tokio::time::timeout(probe_timeout, request_rate(stats, source, client, extra_headers))
+        .await
+    {
+        Ok(Ok(price)) => {
+            log::info!("{}: Source is reachable, it quoted {}", source, price);
+            true
+        }
+        Ok(Err(failure)) => {
+            log::error!("{}: Source is not reachable at startup: {:?}", source, failure);
+            false
+        }
+        Err(_) => {
+            log::error!(
+                "{}: Source did not respond within {} seconds at startup",
+                source,
+                probe_timeout.as_secs()
+            );
+            false
+        }
+    }
+}
+
+/// A random duration between zero and the given maximum.
+pub fn random_jitter(max_jitter: Duration) -> Duration {
+    let max_millis = u64::try_from(max_jitter.as_millis()).unwrap_or(u64::MAX);
+    Duration::from_millis(rand::thread_rng().gen_range(0..=max_millis))
 }
 
 /**
  * Function that continously pulls the exchange rate, from the source
- * specified, and updates the given rates_history_mutex. Ensures that old
- * rates are discarded, when the queue exceeds max size.
+ * specified, using the given client, and updates the given
+ * rates_history_mutex. Ensures that old rates are discarded, when the queue
+ * exceeds the history limit.
+ * To avoid sources being polled at the same moments, the first poll and
+ * each following poll are delayed by a random duration of at most
+ * max_jitter. Sources are expected to quote the rate in EUR/CCD, unless
+ * inverted is set, in which case the source is expected to quote the rate
+ * in CCD/EUR.
  */
 pub async fn pull_exchange_rate(
     stats: prometheus::Stats,
     source: Source,
+    client: reqwest::Client,
+    extra_headers: HeaderMap,
+    inverted: bool,
     rate_history_mutex: Arc<Mutex<RateHistory>>,
     pull_interval: u32,
-    max_rates_saved: usize,
-    db_conn_pool: Option<mysql::Pool>,
+    max_jitter: Duration,
+    history_limit: HistoryLimit,
+    max_identical_readings: u32,
+    db_conn_pool: Option<crate::database::Database>,
 ) -> anyhow::Result<()> {
-    let client = reqwest::Client::new();
+    let record_reading = |price: Price| {
+        let raw_rate = price.value;
+        if let Some(ref pool) = db_conn_pool {
+            if let Err(e) = crate::database::write_read_rate(pool, raw_rate, &source) {
+                stats.increment_failed_database_updates();
+                log::error!("{}: Unable to INSERT new reading: {}, due to: {}", source, raw_rate, e)
+            };
+        }
+        stats.update_read_rate(raw_rate, &source);
 
+        let rate = match price.to_rational() {
+            Some(r) if inverted => r, // The source already quotes in CCD/EUR
+            Some(r) => r.recip(),     /* Get the inverse value, to change units from EUR/CCD to */
+            // CCD/EUR
+            None => {
+                log::error!("{}: Unable to convert rate to rational: {}", source, price);
+                return;
+            }
+        };
+        log::info!("{}: New exchange rate polled: {}/{}", source, rate.numer(), rate.denom());
+        let mut rate_history = lock_history(&rate_history_mutex, &source);
+        let was_frozen = rate_history.is_frozen(max_identical_readings);
+        if rate_history.last_raw_reading == Some(raw_rate) {
+            rate_history.identical_readings += 1;
+        } else {
+            rate_history.identical_readings = 0;
+            rate_history.last_raw_reading = Some(raw_rate);
+        }
+        stats.update_identical_readings(rate_history.identical_readings, &source);
+        match (was_frozen, rate_history.is_frozen(max_identical_readings)) {
+            (false, true) => log::warn!(
+                "{}: Source returned the same reading {} more than {} times in a row, excluding \
+                 it from updates",
+                source,
+                raw_rate,
+                max_identical_readings
+            ),
+            (true, false) => {
+                log::info!("{}: Source returned a new reading, including it in updates", source)
+            }
+            _ => (),
+        }
+        let now = chrono::offset::Utc::now().timestamp();
+        rate_history.push_reading(rate, now, history_limit);
+        rate_history.last_reading_timestamp = now;
+    };
+
+    if let Source::BitfinexWs = source {
+        return stream_bitfinex_rate(
+            &stats,
+            &source,
+            &rate_history_mutex,
+            pull_interval,
+            record_reading,
+        )
+        .await;
+    }
+
+    // Randomize the phase of the interval.
+    sleep(random_jitter(max_jitter)).await;
     let mut interval = interval(Duration::from_secs(pull_interval.into()));
     interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
     loop {
         interval.tick().await;
+        if !max_jitter.is_zero() {
+            sleep(random_jitter(max_jitter)).await;
+        }
+        if lock_history(&rate_history_mutex, &source).disabled {
+            log::debug!("{}: Source is disabled, skipping poll", source);
+            continue;
+        }
         log::debug!("{}: Polling for exchange rate", source);
 
         let request_with_backoff = request_with_backoff(
-            || request_exchange_rate(&source, client.clone()),
-            |timeout: u64| {
+            || request_rate(&stats, &source, client.clone(), &extra_headers),
+            |timeout: u64, failure: RequestFailure| {
                 log::warn!(
                     "{}: Request not successful. Waiting for {} seconds until trying again",
                     source,
                     timeout
                 );
+                if let RequestFailure::RateLimited {
+                    ..
+                } = failure
+                {
+                    stats.increment_rate_limited(&source);
+                }
                 stats.increment_read_attempts(&source);
             },
             INITIAL_RETRY_INTERVAL,
@@ -227,46 +1302,184 @@ pub async fn pull_exchange_rate(
         .await;
 
         let raw_rate = match request_with_backoff {
-            Some(i) => i,
-            None => {
+            Ok(i) => i,
+            Err(RequestFailure::Permanent) => {
+                log::error!("{}: Request failed permanently. Not retrying until next poll", source);
+                stats.increment_read_attempts(&source);
+                continue;
+            }
+            Err(failure) => {
                 log::error!("{}: Request failed. Retries exhausted", source);
+                if let RequestFailure::RateLimited {
+                    ..
+                } = failure
+                {
+                    stats.increment_rate_limited(&source);
+                }
                 stats.increment_read_attempts(&source);
                 continue;
             }
         };
         stats.reset_read_attempts(&source);
+        record_reading(raw_rate);
+    }
+}
 
-        if let Some(ref pool) = db_conn_pool {
-            if let Err(e) = crate::database::write_read_rate(pool, raw_rate, &source) {
-                stats.increment_failed_database_updates();
-                log::error!("{}: Unable to INSERT new reading: {}, due to: {}", source, raw_rate, e)
-            };
+type BitfinexWsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/**
+ * Connect to the websocket of Bitfinex, and subscribe to the ticker
+ * channel.
+ */
+async fn connect_bitfinex_ws(source: &Source) -> Result<BitfinexWsStream, RequestFailure> {
+    let (mut stream, _) = match connect_async(BITFINEX_WS_URL).await {
+        Ok(o) => o,
+        Err(e) => {
+            log::warn!("{}: Unable to connect: {}", source, e);
+            return Err(RequestFailure::Failed);
         }
-        stats.update_read_rate(raw_rate, &source);
+    };
+    let subscribe =
+        json!({"event": "subscribe", "channel": "ticker", "symbol": BITFINEX_WS_SYMBOL});
+    if let Err(e) = stream.send(Message::Text(subscribe.to_string())).await {
+        log::warn!("{}: Unable to subscribe to ticker: {}", source, e);
+        return Err(RequestFailure::Failed);
+    }
+    Ok(stream)
+}
 
-        let rate = match BigRational::from_float(raw_rate) {
-            Some(r) => r.recip(), // Get the inverse value, to change units from EUR/CCD to CCD/EUR
-            None => {
-                log::error!("{}: Unable to convert rate to rational: {}", source, raw_rate);
+/**
+ * Stream the rate from the ticker channel of Bitfinex, and invoke
+ * on_reading with the last price received, once every pull_interval, while
+ * the source is not disabled. Ticks arrive far more often than the rates
+ * are polled from other sources, so recording each of them would fill the
+ * history with seconds of data, and write every tick to the database.
+ * Whenever the connection is lost, we reconnect using the same backoff as
+ * for requests.
+ */
+async fn stream_bitfinex_rate(
+    stats: &prometheus::Stats,
+    source: &Source,
+    rate_history_mutex: &Mutex<RateHistory>,
+    pull_interval: u32,
+    on_reading: impl Fn(Price),
+) -> anyhow::Result<()> {
+    loop {
+        let connection = request_with_backoff(
+            || connect_bitfinex_ws(source),
+            |timeout: u64, _| {
+                log::warn!(
+                    "{}: Connection not successful. Waiting for {} seconds until trying again",
+                    source,
+                    timeout
+                );
+                stats.increment_read_attempts(source);
+            },
+            INITIAL_RETRY_INTERVAL,
+            MAX_RETRIES,
+        )
+        .await;
+        let mut stream = match connection {
+            Ok(stream) => stream,
+            Err(_) => {
+                log::error!("{}: Connection failed. Retries exhausted", source);
+                stats.increment_read_attempts(source);
+                sleep(Duration::from_secs(pull_interval.into())).await;
                 continue;
             }
         };
-        log::info!("{}: New exchange rate polled: {}/{}", source, rate.numer(), rate.denom());
-        {
-            let mut rate_history = rate_history_mutex.lock().unwrap();
-            rate_history.rates.push_back(rate);
-            if rate_history.rates.len() > max_rates_saved {
-                rate_history.rates.pop_front();
+        log::info!("{}: Subscribed to ticker", source);
+
+        let mut record_interval = interval(Duration::from_secs(pull_interval.into()));
+        // The first tick of the interval completes immediately, before any price has
+        // been received.
+        record_interval.tick().await;
+        let mut latest: Option<Price> = None;
+        loop {
+            let message = tokio::select! {
+                message = stream.next() => match message {
+                    Some(message) => message,
+                    None => break,
+                },
+                _ = record_interval.tick() => {
+                    let raw_rate = match latest.as_ref() {
+                        Some(raw_rate) => raw_rate.clone(),
+                        None => {
+                            log::warn!("{}: No tick received since subscribing", source);
+                            continue;
+                        }
+                    };
+                    if lock_history(&rate_history_mutex, &source).disabled {
+                        log::debug!("{}: Source is disabled, ignoring tick", source);
+                        continue;
+                    }
+                    stats.reset_read_attempts(source);
+                    on_reading(raw_rate);
+                    continue;
+                }
+            };
+            let text = match message {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(frame)) => {
+                    log::warn!("{}: Connection closed: {:?}", source, frame);
+                    break;
+                }
+                // Pings are answered by tungstenite itself.
+                Ok(_) => continue,
+                Err(e) => {
+                    log::warn!("{}: Connection lost: {}", source, e);
+                    break;
+                }
+            };
+            let raw_rate = match parse_bitfinex_ticker(&text) {
+                Ok(Some(raw_rate)) => raw_rate,
+                Ok(None) => continue,
+                Err(e) => {
+                    log::error!("{}: Unable to parse message: {}", source, e);
+                    break;
+                }
+            };
+            if raw_rate.value < 0.0 {
+                log::error!("{}: Exchange rate is negative: {}", source, raw_rate);
+                continue;
             }
-            rate_history.last_reading_timestamp = chrono::offset::Utc::now().timestamp();
-        } // drop lock
+            log::debug!("{}: Raw exchange rate CCD in EUR received: {}", source, raw_rate);
+            latest = Some(raw_rate);
+        }
+
+        stats.increment_read_attempts(source);
+        log::warn!("{}: Waiting for {} seconds until reconnecting", source, INITIAL_RETRY_INTERVAL);
+        sleep(Duration::from_secs(INITIAL_RETRY_INTERVAL)).await;
+    }
+}
+
+/**
+ * Parse a message from the ticker channel of Bitfinex.
+ * Returns the last price, if the message is a tick, and None for heartbeats
+ * and informational events.
+ */
+fn parse_bitfinex_ticker(text: &str) -> anyhow::Result<Option<Price>> {
+    let value: serde_json::Value = serde_json::from_str(text)?;
+    if value["event"] == "error" {
+        return Err(anyhow!("Received error: {}", value));
+    }
+    match value.get(1) {
+        // [CHANNEL_ID, [BID, BID_SIZE, ASK, ASK_SIZE, DAILY_CHANGE,
+        // DAILY_CHANGE_RELATIVE, LAST_PRICE, VOLUME, HIGH, LOW]]
+        Some(serde_json::Value::Array(ticker)) => ticker
+            .get(6)
+            .and_then(Price::from_json)
+            .map(Some)
+            .ok_or_else(|| anyhow!("Unexpected missing last price")),
+        // Heartbeats ([CHANNEL_ID, "hb"]) and events
+        _ => Ok(None),
     }
 }
 
 #[derive(SerdeDeserialize)]
 struct CoinMarketCapResponsePrice {
     // Note: This object contains other fields like volume and change
-    price: f64,
+    price: Price,
 }
 
 #[derive(SerdeDeserialize)]
@@ -297,13 +1510,14 @@ struct CoinMarketCapResponseStatus {
 
 #[derive(SerdeDeserialize)]
 pub struct CoinMarketCapResponse {
-    data:   CoinMarketCapResponseData,
+    // Not present, if the status contains an error.
+    data:   Option<CoinMarketCapResponseData>,
     status: CoinMarketCapResponseStatus,
 }
 
 #[derive(SerdeDeserialize)]
 struct CoinGeckoResponseInner {
-    eur: f64,
+    eur: Price,
 }
 #[derive(SerdeDeserialize)]
 pub struct CoinGeckoResponse {
@@ -312,26 +1526,43 @@ pub struct CoinGeckoResponse {
 
 #[derive(SerdeDeserialize)]
 pub struct LiveCoinWatchResponse {
-    rate: f64,
+    rate: Price,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::DEFAULT_USER_AGENT;
     use tokio::time::Instant;
 
+    async fn test_stats() -> prometheus::Stats { prometheus::initialize().await.unwrap().1 }
+
     #[tokio::test]
     #[ignore]
     async fn test_ping_coingecko() {
         let client = reqwest::Client::new();
-        assert!(request_exchange_rate(&Source::CoinGecko, client).await.is_some())
+        assert!(request_exchange_rate(
+            &test_stats().await,
+            &Source::CoinGecko,
+            client,
+            &HeaderMap::new()
+        )
+        .await
+        .is_ok())
     }
 
     #[tokio::test]
     #[ignore]
     async fn test_ping_bitfinex() {
         let client = reqwest::Client::new();
-        assert!(request_exchange_rate(&Source::Bitfinex, client).await.is_some())
+        assert!(request_exchange_rate(
+            &test_stats().await,
+            &Source::Bitfinex,
+            client,
+            &HeaderMap::new()
+        )
+        .await
+        .is_ok())
     }
 
     #[tokio::test]
@@ -340,9 +1571,15 @@ mod tests {
         let client = reqwest::Client::new();
         // TODO Load api_key from parameter
         let api_key = "INSERT KEY".to_string();
-        let result = request_exchange_rate(&Source::LiveCoinWatch(api_key), client).await;
+        let result = request_exchange_rate(
+            &test_stats().await,
+            &Source::LiveCoinWatch(api_key),
+            client,
+            &HeaderMap::new(),
+        )
+        .await;
         println!("{:?}", result);
-        assert!(result.is_some())
+        assert!(result.is_ok())
     }
 
     #[tokio::test]
@@ -351,28 +1588,706 @@ mod tests {
         let client = reqwest::Client::new();
         // TODO Load api_key from parameter
         let api_key = "INSERT KEY".to_string();
-        let result = request_exchange_rate(&Source::CoinMarketCap(api_key), client).await;
+        let result = request_exchange_rate(
+            &test_stats().await,
+            &Source::CoinMarketCap(api_key),
+            client,
+            &HeaderMap::new(),
+        )
+        .await;
         println!("{:?}", result);
-        assert!(result.is_some())
+        assert!(result.is_ok())
+    }
+
+    #[test]
+    fn test_parse_bitfinex_ticker() {
+        let tick = r#"[17082,[0.0049,1000,0.0051,2000,0.0001,0.02,0.005,150000,0.0052,0.0048]]"#;
+        assert_eq!(parse_bitfinex_ticker(tick).unwrap().map(|price| price.value), Some(0.005));
+        assert_eq!(parse_bitfinex_ticker(r#"[17082,"hb"]"#).unwrap(), None);
+        let subscribed = r#"{"event":"subscribed","channel":"ticker","chanId":17082}"#;
+        assert_eq!(parse_bitfinex_ticker(subscribed).unwrap(), None);
+        let error = r#"{"event":"error","msg":"symbol: invalid","code":10300}"#;
+        assert!(parse_bitfinex_ticker(error).is_err());
+        assert!(parse_bitfinex_ticker(r#"[17082,[0.0049,1000]]"#).is_err());
+    }
+
+    fn history_with_reading_at(last_reading_timestamp: i64) -> RateHistory {
+        RateHistory {
+            rates: VecDeque::new(),
+            reading_timestamps: VecDeque::new(),
+            last_reading_timestamp,
+            last_raw_reading: None,
+            identical_readings: 0,
+            disabled: false,
+            recovered: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lock_history_after_panic() {
+        let history = Arc::new(Mutex::new(history_with_reading_at(100)));
+        let panicking = history.clone();
+        let task = tokio::spawn(async move {
+            let _guard = panicking.lock().unwrap();
+            panic!("Source task panicked");
+        });
+        assert!(task.await.unwrap_err().is_panic());
+        assert!(history.is_poisoned());
+        lock_history(&history, "test").last_reading_timestamp = 200;
+        assert!(lock_history(&history, "test").recovered);
+        assert_eq!(lock_history(&history, "test").last_reading_timestamp, 200);
+    }
+
+    #[tokio::test]
+    async fn test_pull_exchange_rate_after_panic() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A stub source, which responds with the same rate to every request.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/rate", listener.local_addr().unwrap())).unwrap();
+        let stub = tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0u8; 1024];
+                let _ = stream.read(&mut buffer).await.unwrap();
+                stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 5\r\n\r\n[2.0]",
+                    )
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let history = Arc::new(Mutex::new(RateHistory::with_capacity(10)));
+        let panicking = history.clone();
+        let task = tokio::spawn(async move {
+            let _guard = panicking.lock().unwrap();
+            panic!("Main loop panicked");
+        });
+        assert!(task.await.unwrap_err().is_panic());
+
+        let source = Source::Test {
+            url,
+            label: "test".to_string(),
+        };
+        let poller = tokio::spawn(pull_exchange_rate(
+            test_stats().await,
+            source,
+            reqwest::Client::new(),
+            HeaderMap::new(),
+            false,
+            history.clone(),
+            1,
+            Duration::ZERO,
+            HistoryLimit::Count(10),
+            100,
+            None,
+        ));
+        // The poller keeps recording readings into the recovered history:
+        for _ in 0..50 {
+            if lock_history(&history, "test").rates.len() >= 2 {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        assert!(!poller.is_finished());
+        poller.abort();
+        stub.abort();
+        let history = lock_history(&history, "test");
+        assert!(history.recovered);
+        assert!(history.rates.len() >= 2);
+        assert_eq!(history.rates[0], BigRational::new(1.into(), 2.into()));
+    }
+
+    #[test]
+    fn test_is_stale_since_last_update() {
+        let history = history_with_reading_at(100);
+        assert!(!history.is_stale(None, 100, 1000));
+        assert!(history.is_stale(None, 101, 1000));
+    }
+
+    #[test]
+    fn test_is_stale_max_age() {
+        let history = history_with_reading_at(100);
+        assert!(!history.is_stale(Some(900), 500, 1000));
+        assert!(history.is_stale(Some(899), 0, 1000));
+    }
+
+    #[test]
+    fn test_push_reading_count() {
+        let max_rates_saved = 5;
+        let mut history = history_with_reading_at(0);
+        history.rates = VecDeque::with_capacity(max_rates_saved);
+        history.reading_timestamps = VecDeque::with_capacity(max_rates_saved);
+        let capacity = history.rates.capacity();
+        for timestamp in 0..20 {
+            history.push_reading(
+                BigRational::from_integer(timestamp.into()),
+                timestamp,
+                HistoryLimit::Count(max_rates_saved),
+            );
+            assert!(history.rates.len() <= max_rates_saved);
+            assert_eq!(history.rates.len(), history.reading_timestamps.len());
+            assert_eq!(history.rates.capacity(), capacity);
+        }
+        assert_eq!(history.reading_timestamps, VecDeque::from([15, 16, 17, 18, 19]));
+    }
+
+    #[test]
+    fn test_push_reading_window() {
+        let mut history = history_with_reading_at(0);
+        for timestamp in [100, 200, 300, 400] {
+            history.push_reading(
+                BigRational::from_integer(timestamp.into()),
+                timestamp,
+                HistoryLimit::Window(150),
+            );
+        }
+        assert_eq!(history.reading_timestamps, VecDeque::from([300, 400]));
+        assert_eq!(history.rates.len(), 2);
+    }
+
+    #[test]
+    fn test_prune_older_than() {
+        let mut history = history_with_reading_at(300);
+        for timestamp in [100, 200, 300] {
+            history.rates.push_back(BigRational::from_integer(timestamp.into()));
+            history.reading_timestamps.push_back(timestamp);
+        }
+        assert_eq!(history.prune_older_than(100), 0);
+        assert_eq!(history.prune_older_than(250), 2);
+        assert_eq!(history.rates, VecDeque::from([BigRational::from_integer(300.into())]));
+        assert_eq!(history.reading_timestamps, VecDeque::from([300]));
+        assert_eq!(history.prune_older_than(1000), 1);
+        assert!(history.rates.is_empty());
+        assert!(history.reading_timestamps.is_empty());
+    }
+
+    #[test]
+    fn test_history_limit_expectations() {
+        assert_eq!(HistoryLimit::Count(60).expected_readings(30), 60);
+        assert_eq!(HistoryLimit::Count(60).expected_duration(30), 1800);
+        assert_eq!(HistoryLimit::Window(1800).expected_readings(30), 61);
+        assert_eq!(HistoryLimit::Window(1800).expected_duration(30), 1800);
+    }
+
+    #[test]
+    fn test_parse_labelled_option() {
+        let option = LabelledOption::<u8>::from_str(" coin_gecko = 2 ").unwrap();
+        assert_eq!(option.label, "coin_gecko");
+        assert_eq!(option.value, 2);
+        // Only the first separator splits the label from the value:
+        let option = LabelledOption::<String>::from_str("generic=a=b").unwrap();
+        assert_eq!(option.label, "generic");
+        assert_eq!(option.value, "a=b");
+        let option = LabelledOption::<String>::parse_with_separator("bitfinex:A: b", ':').unwrap();
+        assert_eq!(option.label, "bitfinex");
+        assert_eq!(option.value, "A: b");
+        assert!(LabelledOption::<u8>::from_str("coin_gecko").is_err());
+        assert!(LabelledOption::<u8>::from_str("coin_gecko=first").is_err());
+        assert!(LabelledOption::<u8>::from_str("coin_gecko=256").is_err());
+    }
+
+    #[test]
+    fn test_parse_source_header() {
+        let header = SourceHeader::from_str("coin_gecko:User-Agent: eur2ccd/1.0").unwrap();
+        assert_eq!(header.label, "coin_gecko");
+        assert_eq!(header.name, "user-agent");
+        assert_eq!(header.value, "eur2ccd/1.0");
+        assert_eq!(header.to_string(), "user-agent: eur2ccd/1.0");
+    }
+
+    #[test]
+    fn test_parse_source_header_redacts_secrets() {
+        let header = SourceHeader::from_str("bitfinex:X-Api-Key: secret").unwrap();
+        assert_eq!(header.to_string(), "x-api-key: <redacted>");
+    }
+
+    #[test]
+    fn test_parse_source_ca_certificate() {
+        let certificate = SourceCaCertificate::from_str("coin_gecko=/etc/certs/gecko.pem").unwrap();
+        assert_eq!(certificate.label, "coin_gecko");
+        assert_eq!(certificate.path, PathBuf::from("/etc/certs/gecko.pem"));
+        assert!(SourceCaCertificate::from_str("coin_gecko").is_err());
+    }
+
+    fn self_signed_certificate(
+        name: &str,
+    ) -> (openssl::x509::X509, openssl::pkey::PKey<openssl::pkey::Private>) {
+        use openssl::{
+            asn1::Asn1Time,
+            hash::MessageDigest,
+            pkey::PKey,
+            rsa::Rsa,
+            x509::{extension::SubjectAlternativeName, X509Builder, X509NameBuilder},
+        };
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_text("CN", name).unwrap();
+        let subject = subject.build();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder.set_issuer_name(&subject).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        let alt_name =
+            SubjectAlternativeName::new().dns(name).build(&builder.x509v3_context(None, None));
+        builder.append_extension(alt_name.unwrap()).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        (builder.build(), key)
+    }
+
+    fn write_certificate(file_name: &str, certificate: &openssl::x509::X509) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("eur2ccd-test-{}.pem", file_name));
+        std::fs::write(&path, certificate.to_pem().unwrap()).unwrap();
+        path
+    }
+
+    fn write_self_signed_certificate(name: &str) -> PathBuf {
+        write_certificate(name, &self_signed_certificate(name).0)
+    }
+
+    #[test]
+    fn test_client_with_multiple_certificates() {
+        let old = write_self_signed_certificate("old.example.com");
+        let new = write_self_signed_certificate("new.example.com");
+        let proxies = ProxyConfig::default();
+        assert!(
+            get_client_with_ca_certificates(&[old.clone()], DEFAULT_USER_AGENT, &proxies).is_ok()
+        );
+        assert!(
+            get_client_with_ca_certificates(&[new.clone()], DEFAULT_USER_AGENT, &proxies).is_ok()
+        );
+        assert!(get_client_with_ca_certificates(&[old, new.clone()], DEFAULT_USER_AGENT, &proxies)
+            .is_ok());
+        let missing = std::env::temp_dir().join("eur2ccd-test-missing.pem");
+        assert!(
+            get_client_with_ca_certificates(&[new, missing], DEFAULT_USER_AGENT, &proxies).is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_rejects_certificate_of_other_ca() {
+        use openssl::ssl::{SslAcceptor, SslMethod};
+        use std::io::{Read, Write};
+
+        // A stub source served over TLS with a self-signed certificate.
+        let (certificate, key) = self_signed_certificate("localhost");
+        let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        acceptor.set_certificate(&certificate).unwrap();
+        let acceptor = acceptor.build();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("https://localhost:{}/rate", listener.local_addr().unwrap().port());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                // Handshakes, which the client aborts, are expected to fail.
+                if let Ok(mut stream) = acceptor.accept(stream.unwrap()) {
+                    let mut buffer = [0u8; 1024];
+                    let _ = stream.read(&mut buffer);
+                    let _ = stream.write_all(
+                        b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 5\r\n\r\n[0.5]",
+                    );
+                }
+            }
+        });
+
+        let proxies = ProxyConfig::default();
+        let trusted = write_certificate("trusted-localhost", &certificate);
+        let client =
+            get_client_with_ca_certificates(&[trusted], DEFAULT_USER_AGENT, &proxies).unwrap();
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "[0.5]");
+
+        // A certificate for the same name, but with another key, is rejected:
+        let other = write_certificate("other-localhost", &self_signed_certificate("localhost").0);
+        let client =
+            get_client_with_ca_certificates(&[other], DEFAULT_USER_AGENT, &proxies).unwrap();
+        assert!(client.get(&url).send().await.is_err());
+        // As are the built-in root certificates:
+        let client = get_client(DEFAULT_USER_AGENT, &proxies).unwrap();
+        assert!(client.get(&url).send().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gzipped_response() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"[0.5]").unwrap();
+        let body = encoder.finish().unwrap();
+
+        // A stub source, which serves a gzipped body and returns the request it
+        // received.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/rate", listener.local_addr().unwrap())).unwrap();
+        let stub = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0u8; 1024];
+            let n = stream.read(&mut buffer).await.unwrap();
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(header.as_bytes()).await.unwrap();
+            stream.write_all(&body).await.unwrap();
+            String::from_utf8_lossy(&buffer[..n]).to_string()
+        });
+
+        let client = get_client(DEFAULT_USER_AGENT, &ProxyConfig::default()).unwrap();
+        let source = Source::Test {
+            url,
+            label: "test".to_string(),
+        };
+        let rate =
+            request_exchange_rate(&test_stats().await, &source, client, &HeaderMap::new()).await;
+        assert_eq!(rate.map(|price| price.value), Ok(0.5));
+        assert!(stub.await.unwrap().to_lowercase().contains("accept-encoding: gzip, deflate"));
+    }
+
+    #[tokio::test]
+    async fn test_client_uses_http_proxy() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A stub proxy, which responds with an empty body and returns the request
+        // it received.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let stub = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0u8; 1024];
+            let n = stream.read(&mut buffer).await.unwrap();
+            stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").await.unwrap();
+            String::from_utf8_lossy(&buffer[..n]).to_string()
+        });
+
+        let client = get_client("test-agent/1.0", &ProxyConfig {
+            http:  Some(proxy_url),
+            https: None,
+        })
+        .unwrap();
+        let response = client.get("http://source.invalid/rate").send().await.unwrap();
+        assert!(response.status().is_success());
+        let request = stub.await.unwrap();
+        assert!(request.starts_with("GET http://source.invalid/rate HTTP/1.1"));
+        assert!(request.to_lowercase().contains("user-agent: test-agent/1.0"));
+    }
+
+    #[test]
+    fn test_parse_source_tier() {
+        let tier = SourceTier::from_str("bitfinex=2").unwrap();
+        assert_eq!(tier.label, "bitfinex");
+        assert_eq!(tier.tier, 2);
+        assert!(SourceTier::from_str("bitfinex=first").is_err());
+    }
+
+    #[test]
+    fn test_parse_max_rates_saved() {
+        let default = MaxRatesSaved::from_str("60").unwrap();
+        assert_eq!(default.label, None);
+        assert_eq!(default.max_rates, 60);
+        let specific = MaxRatesSaved::from_str("coin_gecko=120").unwrap();
+        assert_eq!(specific.label.as_deref(), Some("coin_gecko"));
+        assert_eq!(specific.max_rates, 120);
+        assert!(MaxRatesSaved::from_str("coin_gecko=0").is_err());
+        assert!(MaxRatesSaved::from_str("0").is_err());
+        assert!(MaxRatesSaved::from_str("coin_gecko=many").is_err());
+    }
+
+    #[test]
+    fn test_parse_coinmarketcap_errors() {
+        let source = Source::CoinMarketCap("key".into());
+        let invalid_key =
+            br#"{"status": {"error_code": 1001, "error_message": "This API Key is invalid."}}"#;
+        let error = source.parse_response(invalid_key).unwrap_err();
+        assert_eq!(error, SourceError::Unauthorized("This API Key is invalid.".into()));
+        assert!(error.is_permanent());
+        let internal = br#"{"status": {"error_code": 500, "error_message": null}}"#;
+        let error = source.parse_response(internal).unwrap_err();
+        assert!(matches!(error, SourceError::Api {
+            code: 500,
+            ..
+        }));
+        assert!(!error.is_permanent());
+        let missing = br#"{"status": {"error_code": 0, "error_message": null}}"#;
+        assert!(matches!(source.parse_response(missing), Err(SourceError::Malformed(_))));
+        assert!(matches!(source.parse_response(b"<html>"), Err(SourceError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_parse_exact_price() {
+        let price =
+            Source::CoinGecko.parse_response(br#"{"concordium": {"eur": 0.00512}}"#).unwrap();
+        assert_eq!(price.value, 0.00512);
+        assert_eq!(price.to_rational(), Some(BigRational::new(512.into(), 100000.into())));
+        let price =
+            Source::LiveCoinWatch("key".into()).parse_response(br#"{"rate": "1.5e-3"}"#).unwrap();
+        assert_eq!(price.to_rational(), Some(BigRational::new(3.into(), 2000.into())));
+        assert!(Source::CoinGecko.parse_response(br#"{"concordium": {"eur": null}}"#).is_err());
+        assert!(Source::CoinGecko.parse_response(br#"{"concordium": {"eur": "abc"}}"#).is_err());
+        // Without the decimal, the binary fraction of the float is used.
+        let price = Price {
+            value:   0.1,
+            decimal: None,
+            exact:   None,
+        };
+        assert_eq!(price.to_rational(), BigRational::from_float(0.1));
+    }
+
+    fn generic_source(json_pointer: &str) -> Source {
+        Source::Generic {
+            url:          Url::parse("http://localhost/rate").unwrap(),
+            json_pointer: json_pointer.to_string(),
+            label:        "generic".to_string(),
+            method:       Method::GET,
+            body:         None,
+        }
+    }
+
+    #[test]
+    fn test_generic_request() {
+        let client = reqwest::Client::new();
+        let request = generic_source("/rate").get_request(client.clone(), &HeaderMap::new());
+        let request = request.build().unwrap();
+        assert_eq!(request.method(), Method::GET);
+        assert!(request.body().is_none());
+
+        let source = Source::Generic {
+            url:          Url::parse("http://localhost/rate").unwrap(),
+            json_pointer: "/rate".to_string(),
+            label:        "generic".to_string(),
+            method:       GenericSourceMethod::from_str("generic=post").unwrap().method,
+            body:         Some(
+                GenericSourceBody::from_str(r#"generic={"currency":"EUR","code":"CCD"}"#)
+                    .unwrap()
+                    .body,
+            ),
+        };
+        let header = SourceHeader::from_str("generic:x-api-key: secret").unwrap();
+        let mut headers = HeaderMap::new();
+        headers.append(header.name, header.value);
+        let request = source.get_request(client, &headers).build().unwrap();
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(request.headers()["x-api-key"], "secret");
+        assert_eq!(
+            request.body().and_then(|body| body.as_bytes()),
+            Some(br#"{"code":"CCD","currency":"EUR"}"#.as_slice())
+        );
+    }
+
+    #[test]
+    fn test_parse_generic_source_request() {
+        assert!(GenericSourceMethod::from_str("generic=FETCH ME").is_err());
+        assert!(GenericSourceMethod::from_str("generic").is_err());
+        assert!(GenericSourceBody::from_str("generic={\"a\":").is_err());
+        assert_eq!(
+            GenericSourceBody::from_str("generic=[1, 2]").unwrap().body,
+            serde_json::json!([1, 2])
+        );
+    }
+
+    #[test]
+    fn test_parse_generic_response() {
+        let response =
+            br#"{"data": {"CCD": {"quote": {"EUR": {"price": 0.0123}}}, "list": [1, 2.5]}}"#;
+        let parse = |json_pointer| generic_source(json_pointer).parse_response(response);
+        assert_eq!(parse("/data/CCD/quote/EUR/price").unwrap().value, 0.0123);
+        assert_eq!(parse("/data/list/1").unwrap().value, 2.5);
+        assert!(parse("/data/CCD/quote/USD/price").is_err());
+        assert!(parse("/data/CCD/quote").is_err());
+    }
+
+    #[test]
+    fn test_parse_generic_source() {
+        let generic =
+            GenericSource::from_str("https://example.com/rate?a=1,b=2,/data/price,example")
+                .unwrap();
+        assert_eq!(generic.url.as_str(), "https://example.com/rate?a=1,b=2");
+        assert_eq!(generic.json_pointer, "/data/price");
+        assert_eq!(generic.label, "example");
+        assert!(GenericSource::from_str("https://example.com/rate,data/price,example").is_err());
+        assert!(GenericSource::from_str("https://example.com/rate,/data/price").is_err());
+        assert!(GenericSource::from_str("https://example.com/rate,/data/price,").is_err());
+    }
+
+    #[test]
+    fn test_parse_sources_file() {
+        let sources = parse_sources_file(
+            "# Generic sources\n\nhttps://example.com/a,/price,a\n  https://example.com/b,,b  \n",
+        )
+        .unwrap();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].label, "a");
+        assert_eq!(sources[0].json_pointer, "/price");
+        assert_eq!(sources[1].url.as_str(), "https://example.com/b");
+        assert_eq!(sources[1].json_pointer, "");
+        assert!(parse_sources_file("https://example.com/a,/price").is_err());
+        assert!(parse_sources_file(
+            "https://example.com/a,/price,a\nhttps://example.com/b,/price,a"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_derived_source() {
+        let derived = DerivedSource::from_str(
+            "derived=https://example.com/ccd?a=1,b=2,/usd|https://example.com/fx,/rates/USD",
+        )
+        .unwrap();
+        assert_eq!(derived.base_url.as_str(), "https://example.com/ccd?a=1,b=2");
+        assert_eq!(derived.base_pointer, "/usd");
+        assert_eq!(derived.fx_url.as_str(), "https://example.com/fx");
+        assert_eq!(derived.fx_pointer, "/rates/USD");
+        let source = derived.into_source();
+        assert_eq!(source.to_string(), "derived");
+        match source {
+            Source::Derived {
+                base,
+                fx,
+                ..
+            } => {
+                assert_eq!(base.to_string(), "derived_base");
+                assert_eq!(fx.to_string(), "derived_fx");
+            }
+            _ => panic!("Expected a derived source"),
+        }
+        assert!(DerivedSource::from_str("derived=https://example.com/ccd,/usd").is_err());
+        assert!(
+            DerivedSource::from_str("=https://example.com/a,/b|https://example.com/c,/d").is_err()
+        );
+        assert!(DerivedSource::from_str(
+            "derived=https://example.com/a,b|https://example.com/c,/d"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_derived_price() {
+        // CCD at 0.0055 USD, and EUR at 1.1 USD, gives CCD at 0.005 EUR.
+        let base = Price::from_decimal("0.0055").unwrap();
+        let fx = Price::from_decimal("1.1").unwrap();
+        let price = Price::derived(&base, &fx).unwrap();
+        assert_eq!(price.to_rational(), Some(BigRational::new(1.into(), 200.into())));
+        assert_eq!(price.value, 0.005);
+        assert!(Price::derived(&base, &Price::from_decimal("0").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_parse_source_weight() {
+        let weight = SourceWeight::from_str("coin_gecko=3").unwrap();
+        assert_eq!(weight.label, "coin_gecko");
+        assert_eq!(weight.weight, 3);
+        assert!(SourceWeight::from_str("coin_gecko=0").is_err());
+        assert!(SourceWeight::from_str("coin_gecko").is_err());
+    }
+
+    #[test]
+    fn test_parse_source_header_invalid() {
+        assert!(SourceHeader::from_str("bitfinex").is_err());
+        assert!(SourceHeader::from_str("bitfinex:User-Agent").is_err());
+        assert!(SourceHeader::from_str("bitfinex:Bad Header: value").is_err());
+        assert!(SourceHeader::from_str("bitfinex:User-Agent: bad\nvalue").is_err());
     }
 
     #[tokio::test]
     async fn test_backoff_lower_bound() {
-        let dummy_req = || futures::future::ready::<Option<()>>(None);
+        let dummy_req =
+            || futures::future::ready::<Result<(), RequestFailure>>(Err(RequestFailure::Failed));
 
         let start = Instant::now();
-        request_with_backoff(dummy_req, |_| {}, 10, 1).await;
+        request_with_backoff(dummy_req, |_, _| {}, 10, 1).await;
         let duration = start.elapsed();
         assert!(duration <= std::time::Duration::from_secs(30)); // 10 + 20
     }
 
     #[tokio::test]
     async fn test_backoff_upper_bound() {
-        let dummy_req = || futures::future::ready::<Option<()>>(None);
+        let dummy_req =
+            || futures::future::ready::<Result<(), RequestFailure>>(Err(RequestFailure::Failed));
 
         let start = Instant::now();
-        request_with_backoff(dummy_req, |_| {}, 10, 2).await;
+        request_with_backoff(dummy_req, |_, _| {}, 10, 2).await;
         let duration = start.elapsed();
         assert!(duration >= std::time::Duration::from_secs(30)); // 10 + 20
     }
+
+    #[tokio::test]
+    async fn test_backoff_retry_after() {
+        let dummy_req = || {
+            futures::future::ready::<Result<(), RequestFailure>>(Err(RequestFailure::RateLimited {
+                retry_after: Some(3),
+            }))
+        };
+
+        let start = Instant::now();
+        request_with_backoff(dummy_req, |_, _| {}, 1, 1).await;
+        let duration = start.elapsed();
+        assert!(duration >= std::time::Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn test_backoff_permanent_failure() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A stub source, which rejects every request with 401, and counts them.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/rate", listener.local_addr().unwrap())).unwrap();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0u8; 1024];
+                let _ = stream.read(&mut buffer).await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                stream
+                    .write_all(
+                        b"HTTP/1.1 401 Unauthorized\r\nConnection: close\r\nContent-Length: \
+                          0\r\n\r\n",
+                    )
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let source = Source::Test {
+            url,
+            label: "test".to_string(),
+        };
+        let client = reqwest::Client::new();
+        let stats = test_stats().await;
+        let start = Instant::now();
+        let result = request_with_backoff(
+            || request_exchange_rate(&stats, &source, client.clone(), &HeaderMap::new()),
+            |_, _| {},
+            10,
+            3,
+        )
+        .await;
+        assert_eq!(result, Err(RequestFailure::Permanent));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_random_jitter() {
+        assert_eq!(random_jitter(Duration::ZERO), Duration::ZERO);
+        for _ in 0..100 {
+            assert!(random_jitter(Duration::from_secs(6)) <= Duration::from_secs(6));
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("120"), Some(120));
+        assert_eq!(parse_retry_after(" 5 "), Some(5));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(0));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
 }
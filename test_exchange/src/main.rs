@@ -1,10 +1,36 @@
 use log::info;
 use std::{
     collections::VecDeque,
+    str::FromStr,
     sync::{Arc, Mutex, RwLock},
+    time::Duration,
 };
 use structopt::StructOpt;
-use warp::{Filter, Reply};
+use warp::{http::StatusCode, Filter, Reply};
+
+/// What is returned, when the queue of values is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResortMode {
+    /// The resort value.
+    Value,
+    /// An internal server error.
+    Error,
+    /// A body, which is not valid JSON.
+    Malformed,
+}
+
+impl FromStr for ResortMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "value" => Ok(ResortMode::Value),
+            "error" => Ok(ResortMode::Error),
+            "malformed" => Ok(ResortMode::Malformed),
+            _ => anyhow::bail!("Unknown resort mode (value, error or malformed): {}", s),
+        }
+    }
+}
 
 #[derive(Debug, StructOpt)]
 struct Config {
@@ -14,14 +40,29 @@ struct Config {
         help = "Port on which the server will listen on.",
         env = "TEST_EXCHANGE_PORT"
     )]
-    port:         u16,
+    port:              u16,
     #[structopt(
         long = "resort-value",
         default_value = "0.5",
         help = "Value to resort to if value queue is empty.",
         env = "TEST_EXCHANGE_RESORT_VALUE"
     )]
-    resort_value: f64,
+    resort_value:      f64,
+    #[structopt(
+        long = "resort-mode",
+        default_value = "value",
+        help = "What to return if value queue is empty: the resort value (value), an internal \
+                server error (error), or a body which is not valid JSON (malformed).",
+        env = "TEST_EXCHANGE_RESORT_MODE"
+    )]
+    resort_mode:       ResortMode,
+    #[structopt(
+        long = "response-delay-ms",
+        default_value = "0",
+        help = "Milliseconds to wait, before responding to requests for the rate.",
+        env = "TEST_EXCHANGE_RESPONSE_DELAY_MS"
+    )]
+    response_delay_ms: u64,
 }
 
 #[tokio::main]
@@ -40,18 +81,67 @@ async fn main() {
 
     let rates = Arc::new(Mutex::new(VecDeque::<serde_json::Value>::new()));
     let resort_value = Arc::new(RwLock::new(opt.resort_value));
+    let resort_mode = Arc::new(RwLock::new(opt.resort_mode));
+    let response_delay = Arc::new(RwLock::new(opt.response_delay_ms));
+    // The number of requests, which should still fail, and their status.
+    let failures = Arc::new(Mutex::new((0u32, StatusCode::SERVICE_UNAVAILABLE)));
 
     let resort_serve = resort_value.clone();
+    let resort_mode_serve = resort_mode.clone();
     let rates_serve = rates.clone();
-    let serve_rate = warp::get().and(warp::path!("rate")).map(move || {
-        let mut rates_unlocked = rates_serve.lock().unwrap();
-        let rate = match rates_unlocked.pop_front() {
-            Some(v) => v,
-            None => serde_json::json!(*resort_serve.read().unwrap()),
-        };
-
-        info!("Received request for rate, returning {}", rate);
-        warp::reply::json(&vec![rate]).into_response()
+    let delay_serve = response_delay.clone();
+    let failures_serve = failures.clone();
+    let serve_rate = warp::get().and(warp::path!("rate")).then(move || {
+        let rates_serve = rates_serve.clone();
+        let failures_serve = failures_serve.clone();
+        let resort_serve = resort_serve.clone();
+        let resort_mode_serve = resort_mode_serve.clone();
+        let delay = *delay_serve.read().unwrap();
+        async move {
+            if delay > 0 {
+                info!("Received request for rate, waiting {} ms", delay);
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+            }
+            {
+                let mut failures_unlocked = failures_serve.lock().unwrap();
+                let (remaining, status) = &mut *failures_unlocked;
+                if *remaining > 0 {
+                    *remaining -= 1;
+                    info!(
+                        "Received request for rate, returning status {} ({} failures remaining)",
+                        status, remaining
+                    );
+                    return warp::reply::with_status("Simulated failure", *status).into_response();
+                }
+            }
+            let mut rates_unlocked = rates_serve.lock().unwrap();
+            let rate = match rates_unlocked.pop_front() {
+                Some(v) => v,
+                None => match *resort_mode_serve.read().unwrap() {
+                    ResortMode::Value => serde_json::json!(*resort_serve.read().unwrap()),
+                    ResortMode::Error => {
+                        info!("Received request for rate, returning an error");
+                        return warp::reply::with_status(
+                            "Internal error",
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                        )
+                        .into_response();
+                    }
+                    ResortMode::Malformed => {
+                        info!("Received request for rate, returning a malformed body");
+                        return warp::reply::with_header(
+                            "[0.5,",
+                            "content-type",
+                            "application/json",
+                        )
+                        .into_response();
+                    }
+                },
+            };
+
+            info!("Received request for rate, returning {}", rate);
+            warp::reply::json(&vec![rate]).into_response()
+        }
     });
 
     let rates_add = rates.clone();
@@ -74,6 +164,37 @@ async fn main() {
             warp::reply::reply()
         });
 
+    let change_resort_mode = warp::put().and(warp::path!("update-resort-mode" / ResortMode)).map(
+        move |new_mode: ResortMode| {
+            info!("Received new resort mode {:?}", new_mode);
+            let mut mode_unlocked = resort_mode.write().unwrap();
+            *mode_unlocked = new_mode;
+            warp::reply::reply()
+        },
+    );
+
+    let change_delay =
+        warp::put().and(warp::path!("update-delay" / u64)).map(move |new_delay: u64| {
+            info!("Received new response delay {} ms", new_delay);
+            let mut delay_unlocked = response_delay.write().unwrap();
+            *delay_unlocked = new_delay;
+            warp::reply::reply()
+        });
+
+    let fail_next = warp::put().and(warp::path!("fail-next" / u32 / u16)).map(
+        move |count: u32, status: u16| match StatusCode::from_u16(status) {
+            Ok(status) => {
+                info!("Failing the next {} requests for rate with status {}", count, status);
+                let mut failures_unlocked = failures.lock().unwrap();
+                *failures_unlocked = (count, status);
+                warp::reply::reply().into_response()
+            }
+            Err(_) => {
+                warp::reply::with_status("Invalid status", StatusCode::BAD_REQUEST).into_response()
+            }
+        },
+    );
+
     let reset_rates = warp::put().and(warp::path!("reset")).map(move || {
         let mut rates_unlocked = rates.lock().unwrap();
         rates_unlocked.clear();
@@ -81,7 +202,15 @@ async fn main() {
         warp::reply::reply()
     });
 
-    warp::serve(serve_rate.or(reset_rates).or(add_rates).or(change_resort))
-        .run(([0, 0, 0, 0], opt.port))
-        .await;
+    warp::serve(
+        serve_rate
+            .or(reset_rates)
+            .or(add_rates)
+            .or(change_resort)
+            .or(change_resort_mode)
+            .or(change_delay)
+            .or(fail_next),
+    )
+    .run(([0, 0, 0, 0], opt.port))
+    .await;
 }